@@ -0,0 +1,117 @@
+//! A button of nested boxes, and a sibling label.
+//!
+//! `cargo run --example demo`, or with the `headless` feature
+//! `--screenshot <path> [--frames <n>] [--size <width>x<height>]` to render
+//! offscreen and save a PNG instead of opening a window.
+
+use peg::input::Events;
+use peg::rendering;
+use peg::style_system::*;
+use peg::transform::*;
+use peg::{UiApp, WindowOptions};
+use specs::prelude::*;
+
+fn scene(world: &mut World) {
+    let e0 = world
+        .create_entity()
+        .with(Transform::new(0.0, 0.0))
+        .with(EElement::new("Root".into()))
+        .with(StyleBackground::from_color(255, 0, 0, 255))
+        // .with(<Pseudo as Default>::default())
+        .build();
+
+    let e1 = world
+        .create_entity()
+        .with(Transform::default().with_size(200.0, 50.0))
+        .with(EElement::new("Button".into()))
+        .with(StyleBackground::from_color(255, 0, 0, 255))
+        .with(<Pseudo as Default>::default())
+        .with(rendering::Material::default())
+        .with(Parent { entity: e0 })
+        .build();
+
+    {
+        let mut events = world.write_resource::<Events>();
+        events.register(e1, Box::new(move |e| println!("clicked {:?}", e)));
+    }
+
+    let e2 = world
+        .create_entity()
+        .with(Transform::default().with_size(190.0, 40.0))
+        .with(EElement::new("Border".into()))
+        .with(StyleBackground::from_color(0, 255, 0, 255))
+        .with(<Pseudo as Default>::default())
+        .with(rendering::Material::default())
+        .with(Parent { entity: e1 })
+        .build();
+    let _e3 = world
+        .create_entity()
+        .with(Transform::default().with_size(180.0, 30.0))
+        .with(EElement::new("Label".into()))
+        .with(StyleBackground::from_color(0, 0, 255, 255))
+        .with(<Pseudo as Default>::default())
+        .with(rendering::Material::default())
+        .with(Parent { entity: e2 })
+        .with(rendering::Text {
+            text: "button".to_string(),
+        })
+        .build();
+
+    let _e4 = world
+        .create_entity()
+        .with(Transform::default())
+        .with(EElement::new("Button".into()))
+        .with(StyleBackground::from_color(255, 255, 0, 255))
+        // .with(<Pseudo as Default>::default())
+        .with(rendering::Material::default())
+        .with(Parent { entity: e0 })
+        .with(rendering::Text {
+            text: "ent 4 child of 2".to_string(),
+        })
+        .build();
+}
+
+fn app<'a, 'b>() -> UiApp<'a, 'b> {
+    UiApp::new()
+        .with_stylesheet("style/style.css")
+        .with_window(WindowOptions {
+            title: "Cube example".to_owned(),
+            ..Default::default()
+        })
+        // release builds need no style/ directory next to them
+        .with_embedded("style/style.css", include_bytes!("../style/style.css"))
+        .with_scene(scene)
+}
+
+#[cfg(feature = "headless")]
+fn screenshot_args() -> Option<(std::path::PathBuf, usize, (u32, u32))> {
+    let mut path = None;
+    let mut frames = 3;
+    let mut size = (1024, 768);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--screenshot" => path = args.next().map(std::path::PathBuf::from),
+            "--frames" => frames = args.next()?.parse().ok()?,
+            "--size" => {
+                let value = args.next()?;
+                let mut parts = value.split('x').map(|v| v.parse().ok());
+                size = (parts.next()??, parts.next()??);
+            }
+            _ => {}
+        }
+    }
+    path.map(|path| (path, frames, size))
+}
+
+pub fn main() {
+    #[cfg(feature = "headless")]
+    {
+        if let Some((path, frames, size)) = screenshot_args() {
+            // the first frames load the resources
+            app().screenshot(size, frames, &path);
+            return;
+        }
+    }
+    app().run();
+}
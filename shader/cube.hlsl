@@ -1,31 +1,249 @@
 struct VsOutput {
     float4 pos: SV_Position;
-    float2 tc: TEXCOORD;
-};
-
-cbuffer Locals {
-	float4x4 u_Transform;
-    float4 u_Color;
-	float2 u_Size;
+    float4 color: COLOR0;
+    // position in the box, in pixels from its top left corner
+    float2 local: TEXCOORD1;
+    float2 size: TEXCOORD2;
+    float4 radius: TEXCOORD3;
+    float4 border: TEXCOORD4;
+    float4 border_color: COLOR1;
+    float4 gradient: TEXCOORD8;
+    float4 image: TEXCOORD9;
+    float border_style: TEXCOORD5;
+    float4 shadow: TEXCOORD6;
+    float kind: TEXCOORD7;
+    // the clip, in the same space as local
+    float4 clip: TEXCOORD0;
 };
 
+float4x4 u_Transform;
 float2 u_Screen;
 
-VsOutput Vertex(float4 pos: a_Pos, float2 tc: a_TexCoord) {
-    pos.xy = pos.xy * u_Size;
+// one instance per quad; the instance transform already includes the view projection
+VsOutput Vertex(float4 pos: a_Pos,
+                float4 tx: a_TransformX, float4 ty: a_TransformY,
+                float4 tz: a_TransformZ, float4 tw: a_TransformW,
+                float4 color: a_Color, float2 size: a_Size,
+                float4 radius: a_Radius, float4 border: a_Border,
+                float4 border_color: a_BorderColor, float4 gradient: a_Gradient,
+                float4 image: a_Image,
+                float border_style: a_BorderStyle,
+                float4 shadow: a_Shadow, float kind: a_Kind,
+                float4 clip: a_Clip) {
+    pos.xy = pos.xy * size;
     VsOutput output = {
-    	mul(u_Transform, pos),
-    	tc,
+        tx * pos.x + ty * pos.y + tz * pos.z + tw * pos.w,
+        color,
+        pos.xy,
+        size,
+        radius,
+        border,
+        border_color,
+        gradient,
+        image,
+        border_style,
+        shadow,
+        kind,
+        clip,
     };
     return output;
 }
 
+// the picture of the batch, placed at image.xy with size image.zw
 Texture2D<float4> t_Color;
 SamplerState t_Color_;
 
+// one gradient ramp per row
+Texture2D<float4> t_Gradients;
+SamplerState t_Gradients_;
+
+// the font bitmap, glyphs placed at image.xy with size image.zw
+Texture2D<float4> t_Font;
+SamplerState t_Font_;
+
+// signed distance from p to a box of half extents `half_size` centered on the
+// origin, with the radii of the top left, top right, bottom right and bottom
+// left corners
+float RoundedBox(float2 p, float2 half_size, float4 radii) {
+    float r = p.x < 0.0 ? (p.y < 0.0 ? radii.x : radii.w)
+                        : (p.y < 0.0 ? radii.y : radii.z);
+    r = min(r, min(half_size.x, half_size.y));
+    float2 q = abs(p) - half_size + r;
+    return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+}
+
+// maps a position along one axis of a box to a texel of a border image sliced
+// s0 and s1 texels from its edges, and drawn in borders b0 and b1 wide; region
+// is 0 for the start border, 1 for the middle and 2 for the end border
+float SliceAxis(float p, float size, float b0, float b1, float s0, float s1,
+                float picture, float region, float scale, float mode) {
+    if (region < 0.5) {
+        return p / b0 * s0;
+    }
+    if (region > 1.5) {
+        return picture - s1 + (p - (size - b1)) / max(b1, 0.0001) * s1;
+    }
+    float span = max(size - b0 - b1, 0.0001);
+    float middle = max(picture - s0 - s1, 0.0);
+    float f;
+    if (mode < 5.5) {
+        // stretch
+        f = (p - b0) / span;
+    } else {
+        float tile = max(middle * scale, 1.0);
+        if (mode < 6.5) {
+            // repeat, with a whole tile at the center
+            f = frac((p - b0 - span * 0.5) / tile + 0.5);
+        } else {
+            // round
+            float count = max(round(span / tile), 1.0);
+            f = frac((p - b0) * count / span);
+        }
+    }
+    return s0 + f * middle;
+}
+
+// border image: slices in image (top, right, bottom, left), fill flag in gradient.y
+float4 NineSlice(VsOutput pin) {
+    float w, h;
+    t_Color.GetDimensions(w, h);
+    float4 s = pin.image;
+    float4 b = pin.border;
+    float2 p = pin.local;
+
+    float rx = p.x < b.w ? 0.0 : (p.x >= pin.size.x - b.y ? 2.0 : 1.0);
+    float ry = p.y < b.x ? 0.0 : (p.y >= pin.size.y - b.z ? 2.0 : 1.0);
+    if (rx == 1.0 && ry == 1.0 && pin.gradient.y < 0.5) {
+        return 0.0;
+    }
+
+    // tiles keep the scale of the border they run along
+    float scale_x = ry > 1.5 ? b.z / max(s.z, 1.0) : b.x / max(s.x, 1.0);
+    float scale_y = rx > 1.5 ? b.y / max(s.y, 1.0) : b.w / max(s.w, 1.0);
+    float u = SliceAxis(p.x, pin.size.x, b.w, b.y, s.w, s.y, w, rx, scale_x, pin.gradient.x);
+    float v = SliceAxis(p.y, pin.size.y, b.x, b.z, s.x, s.z, h, ry, scale_y, pin.gradient.x);
+    return t_Color.Sample(t_Color_, float2(u / w, v / h));
+}
+
+// abramowitz and stegun approximation, good to 5e-4
+float Erf(float x) {
+    float s = sign(x);
+    float a = abs(x);
+    float t = 1.0 + (0.278393 + (0.230389 + 0.078108 * a * a) * a) * a;
+    t *= t;
+    return s - s / (t * t);
+}
+
+// coverage of a shape at signed distance d once blurred by a gaussian of
+// deviation sigma, approximated by blurring across the closest edge only
+float BlurredCoverage(float d, float sigma) {
+    return 0.5 - 0.5 * Erf(d / (max(sigma, 0.3) * 1.4142136));
+}
+
+float4 Shade(VsOutput pin) {
+    float2 half_size = pin.size * 0.5;
+    float outer = RoundedBox(pin.local - half_size, half_size, pin.radius);
+
+    // 4: a glyph, its coverage read from the font bitmap
+    if (pin.kind > 3.5) {
+        float coverage = t_Font.Sample(t_Font_, pin.image.xy + pin.local / pin.size * pin.image.zw).r;
+        return float4(pin.color.rgb, pin.color.a * coverage);
+    }
+
+    // 3: a layer of the size of the screen, with premultiplied colors,
+    // composited at the opacity in color.a
+    if (pin.kind > 2.5) {
+        float4 layer = t_Color.Sample(t_Color_, pin.pos.xy / u_Screen);
+        return float4(layer.rgb / max(layer.a, 0.0001), layer.a * pin.color.a);
+    }
+
+    // box shadows: 1 outer, 2 inset
+    if (pin.kind > 0.5) {
+        float2 shape_half = max(half_size - pin.shadow.z, 0.0);
+        float4 shape_radius = pin.kind > 1.5 ? max(pin.radius - pin.shadow.z, 0.0) : pin.radius;
+        float shape = RoundedBox(pin.local - half_size - pin.shadow.xy, shape_half, shape_radius);
+        float coverage = BlurredCoverage(shape, pin.shadow.w);
+        if (pin.kind > 1.5) {
+            coverage = (1.0 - coverage) * saturate(0.5 - outer);
+        } else {
+            // an outer shadow is not drawn under the box casting it, placed
+            // in the quad by the border widths, with the border color radii
+            float4 b = pin.border;
+            float2 box_half = max(pin.size - float2(b.y + b.w, b.x + b.z), 0.0) * 0.5;
+            float box = RoundedBox(pin.local - float2(b.w, b.x) - box_half, box_half, pin.border_color);
+            coverage *= saturate(0.5 + box);
+        }
+        float4 shadow_color = pin.color;
+        shadow_color.a *= coverage;
+        return shadow_color;
+    }
+
+    // the padding box: widths are top, right, bottom, left
+    float4 b = pin.border;
+    float2 inner_min = float2(b.w, b.x);
+    float2 inner_half = max(pin.size - float2(b.y + b.w, b.x + b.z), 0.0) * 0.5;
+    float4 inner_radius = max(pin.radius - float4(max(b.w, b.x), max(b.x, b.y),
+                                                  max(b.y, b.z), max(b.z, b.w)), 0.0);
+    float inner = RoundedBox(pin.local - inner_min - inner_half, inner_half, inner_radius);
+
+    float border = pin.border_style > 0.5 && any(b > 0.0) ? saturate(0.5 + inner) : 0.0;
+    if (pin.border_style > 1.5) {
+        // dashes three times as long as the border is wide, along the closest edge
+        float4 edges = float4(pin.local.y, pin.size.x - pin.local.x,
+                              pin.size.y - pin.local.y, pin.local.x);
+        float closest = min(min(edges.x, edges.z), min(edges.y, edges.w));
+        bool horizontal = closest == edges.x || closest == edges.z;
+        float width = max(horizontal ? max(b.x, b.z) : max(b.y, b.w), 1.0);
+        float along = horizontal ? pin.local.x : pin.local.y;
+        border *= step(fmod(along, width * 6.0), width * 3.0);
+    }
+
+    float4 background = pin.color;
+    // images: 1 linear gradient, 2 radial circle gradient, 3 radial ellipse
+    // gradient, 4 picture, 5 to 7 border image stretched, repeated or rounded
+    if (pin.gradient.x > 0.5) {
+        float2 p = pin.local - half_size;
+        float t = 0.0;
+        float4 g = 0.0;
+        if (pin.gradient.x > 4.5) {
+            g = NineSlice(pin);
+        } else if (pin.gradient.x > 3.5) {
+            float2 uv = (pin.local - pin.image.xy) / max(pin.image.zw, 1.0);
+            // no repeat: nothing is drawn outside the picture
+            g = all(uv >= 0.0 && uv <= 1.0) ? t_Color.Sample(t_Color_, uv) : 0.0;
+        } else if (pin.gradient.x < 1.5) {
+            // the gradient line goes through the center and reaches the corners
+            float2 dir = float2(sin(pin.gradient.y), -cos(pin.gradient.y));
+            float len = abs(pin.size.x * dir.x) + abs(pin.size.y * dir.y);
+            t = dot(p, dir) / max(len, 1.0) + 0.5;
+        } else if (pin.gradient.x < 2.5) {
+            t = length(p) / max(length(half_size), 1.0);
+        } else {
+            // an ellipse with the aspect of the box through its corners
+            t = length(p / max(half_size * 1.4142136, 1.0));
+        }
+        if (pin.gradient.x < 3.5) {
+            g = t_Gradients.Sample(t_Gradients_, float2(saturate(t), pin.gradient.z));
+        }
+        // the image is composited over the background color
+        float a = g.a + background.a * (1.0 - g.a);
+        background.rgb = (g.rgb * g.a + background.rgb * background.a * (1.0 - g.a)) / max(a, 0.0001);
+        background.a = a;
+    }
+
+    float4 color = lerp(background, pin.border_color, border);
+    color.a *= saturate(0.5 - outer);
+    return color;
+}
+
+// the part of the pixel inside the clip, antialiased like the edges of a box
+float ClipCoverage(VsOutput pin) {
+    float2 inside = saturate(min(pin.local - pin.clip.xy, pin.clip.zw - pin.local) + 0.5);
+    return inside.x * inside.y;
+}
+
 float4 Pixel(VsOutput pin) : SV_Target {
-	float4 tex = u_Color;// float4(1.0,0,0,1);// t_Color.Sample(t_Color_, pin.tc);
-	// float4 tex = t_Color.Sample(t_Color_, pin.tc) * u_Color;
-    float blend = dot(pin.tc-0.5, pin.tc-0.5);
-    return lerp(tex, 0.0, blend*1.0);   
+    float4 color = Shade(pin);
+    color.a *= ClipCoverage(pin);
+    return color;
 }
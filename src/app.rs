@@ -0,0 +1,581 @@
+//! The application: a world of elements, the systems styling, laying out and
+//! picking them, and the renderer drawing them into a window.
+
+use crate::gfx_app;
+use crate::input::*;
+use crate::manager::{self, SimpleKey};
+use crate::shade;
+use crate::style_system::{StyleBundle, Stylesheets};
+use crate::{layout, rendering, text, UiBundle};
+use specs::prelude::*;
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+type Scene<'a> = Box<dyn FnOnce(&mut World) + 'a>;
+
+pub struct WindowOptions {
+    pub title: String,
+    /// In logical pixels; the platform picks one when `None`.
+    pub size: Option<(u32, u32)>,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        WindowOptions {
+            title: "peg".to_owned(),
+            size: None,
+        }
+    }
+}
+
+/// Builds an application:
+///
+/// ```ignore
+/// UiApp::new()
+///     .with_bundle(TooltipBundle)
+///     .register::<Health>()
+///     .with_system(HealthBarSystem, "sys_health_bar", &["sys_layout"])
+///     .with_stylesheet("style/game.css")
+///     .with_scene(|world| { world.create_entity().with(EElement::new("Root".into())).build(); })
+///     .run();
+/// ```
+///
+/// The core bundles come first, with these systems for the added ones to
+/// depend on:
+///
+/// - `StyleBundle`: `parent_hierarchy_system`, `sys_style`
+/// - `LayoutBundle`: `sys_text_shaping`, `sys_scroll`, `sys_image`,
+///   `sys_layout`, `transform_system`
+/// - `RenderBundle`: `sys_display_list`
+/// - `PickBundle`: `sys_pick`, `sys_consume`, `sys_clean_events`
+pub struct UiApp<'a, 'b> {
+    world: World,
+    dispatcher: DispatcherBuilder<'a, 'b>,
+    /// The logical names and the CSS of the styles of the bundles.
+    styles: Vec<(PathBuf, &'static str)>,
+    /// Cascaded after `styles`.
+    stylesheets: Vec<SimpleKey>,
+    embedded: Vec<(PathBuf, Cow<'static, [u8]>)>,
+    window: WindowOptions,
+    scene: Option<Scene<'a>>,
+}
+
+impl<'a, 'b> Default for UiApp<'a, 'b> {
+    fn default() -> Self {
+        UiApp::new()
+    }
+}
+
+impl<'a, 'b> UiApp<'a, 'b> {
+    pub fn new() -> Self {
+        let mut world = World::new();
+        world.add_resource::<specs::shrev::EventChannel<manager::ResourceReloaded>>(
+            Default::default(),
+        );
+        world.add_resource(manager::Diagnostics::default());
+        UiApp {
+            world,
+            dispatcher: DispatcherBuilder::new(),
+            styles: Vec::new(),
+            stylesheets: Vec::new(),
+            embedded: Vec::new(),
+            window: WindowOptions::default(),
+            scene: None,
+        }
+        .with_bundle(StyleBundle)
+        .with_bundle(layout::LayoutBundle)
+        .with_bundle(rendering::RenderBundle)
+        .with_bundle(PickBundle)
+    }
+
+    /// Adds the components, resources, systems and style of `bundle`.
+    pub fn with_bundle<B>(mut self, bundle: B) -> Self
+    where
+        B: UiBundle<'a, 'b>,
+    {
+        if let Some(css) = bundle.style() {
+            let name = format!("bundle/{}.css", std::any::type_name::<B>());
+            self.styles.push((name.into(), css));
+        }
+        bundle.build(&mut self.world, &mut self.dispatcher);
+        self
+    }
+
+    /// Registers the component `C`, for the systems and the scene to use.
+    pub fn register<C>(mut self) -> Self
+    where
+        C: Component,
+        C::Storage: Default,
+    {
+        self.world.register::<C>();
+        self
+    }
+
+    pub fn with_resource<T: Resource>(mut self, resource: T) -> Self {
+        self.world.add_resource(resource);
+        self
+    }
+
+    /// Runs `system` each frame after the systems named in `dependencies`.
+    pub fn with_system<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'a,
+    {
+        self.dispatcher.add(system, name, dependencies);
+        self
+    }
+
+    /// Adds the stylesheet at `path` after the previous ones, which its rules
+    /// override.
+    pub fn with_stylesheet<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.stylesheets.push(SimpleKey::Path(path.into()));
+        self
+    }
+
+    /// Embeds the file `path` in release builds, which then read it from
+    /// memory unless a pack or a theme holds it. Debug builds read it from disk.
+    pub fn with_embedded<P: Into<PathBuf>>(mut self, path: P, bytes: &'static [u8]) -> Self {
+        self.embedded.push((path.into(), Cow::Borrowed(bytes)));
+        self
+    }
+
+    pub fn with_window(mut self, window: WindowOptions) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Creates the initial elements, once the resource store is available.
+    pub fn with_scene<S>(mut self, scene: S) -> Self
+    where
+        S: FnOnce(&mut World) + 'a,
+    {
+        self.scene = Some(Box::new(scene));
+        self
+    }
+
+    /// Opens the window and runs until it is closed.
+    pub fn run(self) {
+        let mut wb = winit::WindowBuilder::new().with_title(self.window.title.clone());
+        if let Some(size) = self.window.size {
+            wb = wb.with_dimensions(size.into());
+        }
+        gfx_app::launch_default(wb, move |factory, backend, targets| {
+            App::new(factory, backend, targets, self)
+        });
+    }
+
+    /// Renders `frames` frames offscreen, the first ones loading the
+    /// resources, and saves the last one to `path` as a PNG.
+    #[cfg(feature = "headless")]
+    pub fn screenshot(self, size: (u32, u32), frames: usize, path: &std::path::Path) {
+        gfx_app::launch_headless(size, frames, path, move |factory, backend, targets| {
+            gfx_app::Wrap::new(factory, backend, targets, |factory, backend, targets| {
+                App::new(factory, backend, targets, self)
+            })
+        });
+    }
+}
+
+struct App<'a, 'b, R: gfx::Resources, F: gfx::Factory<R> + Clone> {
+    renderer: rendering::Renderer<R, F>,
+    world: World,
+    dispatcher: Dispatcher<'a, 'b>,
+}
+
+impl<'a, 'b, R: gfx::Resources, F: gfx::Factory<R> + Clone> App<'a, 'b, R, F> {
+    fn new(
+        factory: F,
+        backend: shade::Backend,
+        window_targets: gfx_app::WindowTargets<R>,
+        ui: UiApp<'a, 'b>,
+    ) -> Self {
+        info!("backend {:?}", backend);
+
+        let UiApp {
+            mut world,
+            dispatcher,
+            styles,
+            stylesheets,
+            embedded,
+            scene,
+            ..
+        } = ui;
+        world.add_resource::<rendering::Screen>(rendering::Screen {
+            size: window_targets.size,
+            dpi_factor: window_targets.dpi_factor,
+        });
+
+        let mut dispatcher = dispatcher.build();
+        dispatcher.setup(&mut world.res);
+
+        let renderer = rendering::Renderer::new(factory, backend, window_targets);
+
+        let store = manager::ResourceManager::new(manager::shader_compiler(backend));
+        text::register_fallback_font(&store);
+        mount_assets(&store, embedded);
+        // a theme overrides the assets it holds
+        if std::path::Path::new("theme").is_dir() {
+            store.mount_dir("", "theme");
+        }
+
+        world.add_resource(cascade(&store, styles, stylesheets));
+        world.add_resource(store);
+
+        if let Some(scene) = scene {
+            scene(&mut world);
+        }
+
+        App {
+            world,
+            dispatcher,
+            renderer,
+        }
+    }
+}
+
+impl<'a, 'b, R: gfx::Resources, F: gfx::Factory<R> + Clone> gfx_app::Application<R, F>
+    for App<'a, 'b, R, F>
+{
+    fn render<C2: gfx::CommandBuffer<R>>(&mut self, encoder: &mut gfx::Encoder<R, C2>) {
+        self.dispatcher.dispatch(&self.world.res);
+
+        {
+            let store = self.world.read_resource::<manager::ResourceManager>();
+            let mut reloads = self
+                .world
+                .write_resource::<specs::shrev::EventChannel<manager::ResourceReloaded>>();
+            store.sync(&mut reloads);
+        }
+
+        self.renderer.render(&self.world.res, encoder);
+
+        {
+            let mut m = self.world.write_resource::<MouseEvent>();
+            // println!("  left click {:?}", m.left_click);
+            match m.left_click {
+                ButtonState::Released => m.left_click = ButtonState::Up,
+                ButtonState::Pressed => m.left_click = ButtonState::Down,
+                _ => {}
+            }
+            m.wheel = (0.0, 0.0);
+        }
+        self.world.maintain();
+    }
+
+    fn on_resize(&mut self, window_targets: gfx_app::WindowTargets<R>) {
+        self.world.write_resource::<rendering::Screen>().size = window_targets.size;
+        self.renderer.on_resize(window_targets);
+    }
+
+    fn on(&mut self, event: winit::WindowEvent) {
+        match event {
+            winit::WindowEvent::MouseInput {
+                button: _button,
+                state,
+                ..
+            } => {
+                let mut m = self.world.write_resource::<MouseEvent>();
+                let prev = m.left_click.clone();
+                //state;
+                use winit::ElementState;
+                match (prev, state) {
+                    (ButtonState::Up, ElementState::Pressed)
+                    | (ButtonState::Released, ElementState::Pressed) => {
+                        m.left_click = ButtonState::Pressed
+                    }
+                    (ButtonState::Down, ElementState::Released)
+                    | (ButtonState::Pressed, ElementState::Released) => {
+                        m.left_click = ButtonState::Released
+                    }
+                    _ => {}
+                };
+            }
+            winit::WindowEvent::CursorMoved { position: p, .. } => {
+                let p: (i32, i32) = p.into();
+                let mut m = self.world.write_resource::<MouseEvent>();
+
+                // hack: a first CursorMoved 0,0 event is sent on start even if the mouse is not in the window
+                if m.position.0 == -1 && m.position.1 == -1 && p.0 == 0 && p.1 == 0 {
+                    m.position = (-2, -2);
+                } else {
+                    m.position = p;
+                }
+            }
+            winit::WindowEvent::MouseWheel { delta, .. } => {
+                let dpi = self.world.read_resource::<rendering::Screen>().dpi_factor as f32;
+                let (x, y) = match delta {
+                    winit::MouseScrollDelta::LineDelta(x, y) => {
+                        (x * SCROLL_LINE_HEIGHT * dpi, y * SCROLL_LINE_HEIGHT * dpi)
+                    }
+                    winit::MouseScrollDelta::PixelDelta(p) => (p.x as f32 * dpi, p.y as f32 * dpi),
+                };
+                let mut m = self.world.write_resource::<MouseEvent>();
+                m.wheel = (m.wheel.0 + x, m.wheel.1 + y);
+            }
+            winit::WindowEvent::HiDpiFactorChanged(d) => debug!("dpi changed to {}", d),
+            _ => (),
+        };
+        // println!("{:?}",event);
+    }
+}
+
+/// Registers the `styles` of the bundles in `store` and cascades them first,
+/// for the `stylesheets` of the application to override them.
+fn cascade(
+    store: &manager::ResourceManager,
+    styles: Vec<(PathBuf, &'static str)>,
+    stylesheets: Vec<SimpleKey>,
+) -> Stylesheets {
+    let mut cascade = Vec::with_capacity(styles.len() + stylesheets.len());
+    for (name, css) in styles {
+        store.insert(&name, css.as_bytes());
+        cascade.push(SimpleKey::Logical(name));
+    }
+    cascade.extend(stylesheets);
+    Stylesheets(cascade)
+}
+
+/// The assets of release builds: those of the library and `embedded`, in the
+/// binary, which then needs no `style/` or `shader/` directory next to it,
+/// under those of `assets.pack` when there is one.
+#[cfg(not(debug_assertions))]
+fn mount_assets(store: &manager::ResourceManager, embedded: Vec<(PathBuf, Cow<'static, [u8]>)>) {
+    macro_rules! embed {
+        ($($path:literal),*) => {
+            vec![$((
+                PathBuf::from($path),
+                Cow::Borrowed(&include_bytes!(concat!("../", $path))[..]),
+            )),*]
+        };
+    }
+    let mut assets = embed!(
+        "shader/cube_150_core.glslv",
+        "shader/cube_150_core.glslf",
+        "shader/cube_300_es.glslv",
+        "shader/cube_300_es.glslf"
+    );
+    assets.push((
+        PathBuf::from(text::FONT_PATH),
+        Cow::Borrowed(text::FONT_BYTES),
+    ));
+    store.mount_memory("", assets);
+    store.mount_memory("", embedded);
+    match store.mount_pack("", "assets.pack") {
+        Ok(()) => info!("mounted assets.pack"),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("cannot mount assets.pack: {}", e),
+    }
+}
+
+/// Debug builds read the assets from disk, to reload them when they change.
+#[cfg(debug_assertions)]
+fn mount_assets(_store: &manager::ResourceManager, _embedded: Vec<(PathBuf, Cow<'static, [u8]>)>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Badge;
+
+    impl Component for Badge {
+        type Storage = VecStorage<Self>;
+    }
+
+    /// The badges `BadgeSystem` found laid out and picked.
+    #[derive(Default)]
+    struct Ready(usize);
+
+    struct BadgeSystem;
+
+    impl<'a> System<'a> for BadgeSystem {
+        type SystemData = (
+            ReadStorage<'a, Badge>,
+            ReadStorage<'a, layout::LayoutBox>,
+            ReadStorage<'a, crate::style_system::Pseudo>,
+            Write<'a, Ready>,
+        );
+
+        fn run(&mut self, (badges, boxes, pseudos, mut ready): Self::SystemData) {
+            ready.0 = (&badges, &boxes, &pseudos).join().count();
+        }
+    }
+
+    struct BadgeBundle;
+
+    impl<'a, 'b> UiBundle<'a, 'b> for BadgeBundle {
+        fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>) {
+            world.register::<Badge>();
+            dispatcher.add(BadgeSystem, "sys_badge", &["sys_layout", "sys_pick"]);
+        }
+
+        fn style(&self) -> Option<&'static str> {
+            Some("Badge { background-color: #ff0000; }")
+        }
+    }
+
+    #[test]
+    fn bundles_plug_in_after_the_core_ones() {
+        let app = UiApp::new().with_bundle(BadgeBundle);
+        assert_eq!((&app.world.read_storage::<Badge>()).join().count(), 0);
+        let (ref name, css) = *app.styles.last().unwrap();
+        assert!(name.to_string_lossy().ends_with("BadgeBundle.css"));
+        assert_eq!(css, "Badge { background-color: #ff0000; }");
+    }
+
+    #[test]
+    fn bundle_systems_run_after_their_dependencies() {
+        use crate::style_system::EElement;
+        use crate::transform::Transform;
+
+        let UiApp {
+            mut world,
+            dispatcher,
+            styles,
+            stylesheets,
+            ..
+        } = UiApp::new().with_bundle(BadgeBundle);
+        let store = manager::ResourceManager::new(std::sync::Arc::new(manager::Fxc));
+        text::register_fallback_font(&store);
+        world.add_resource(cascade(&store, styles, stylesheets));
+        world.add_resource(store);
+        let mut dispatcher = dispatcher.build();
+        dispatcher.setup(&mut world.res);
+        world
+            .create_entity()
+            .with(Transform::default().with_size(40.0, 20.0))
+            .with(EElement::new("Badge".into()))
+            .with(Badge)
+            .build();
+
+        // `sys_layout` and `sys_pick` gave the badge its box and its pseudo
+        // classes before `sys_badge` looked for them, in the first frame
+        dispatcher.dispatch(&world.res);
+        assert_eq!(world.read_resource::<Ready>().0, 1);
+    }
+
+    #[test]
+    fn bundle_styles_cascade_before_the_stylesheets() {
+        let app = UiApp::new()
+            .with_bundle(BadgeBundle)
+            .with_stylesheet("style/game.css");
+        let store = manager::ResourceManager::new(std::sync::Arc::new(manager::Fxc));
+        let names: Vec<PathBuf> = app.styles.iter().map(|(name, _)| name.clone()).collect();
+        let Stylesheets(cascade) = cascade(&store, app.styles, app.stylesheets);
+
+        let (game, bundles) = cascade.split_last().unwrap();
+        assert_eq!(*game, SimpleKey::Path("style/game.css".into()));
+        let expected: Vec<SimpleKey> = names.into_iter().map(SimpleKey::Logical).collect();
+        assert_eq!(bundles, &expected[..]);
+        let badge = bundles.last().unwrap();
+        assert!(badge.to_string().ends_with("BadgeBundle.css"));
+        // the styles are read from the store, not from the disk
+        assert!(store.get::<manager::FromFS>(badge).is_ok());
+    }
+
+    #[test]
+    fn stylesheets_override_in_the_order_they_are_added() {
+        let app = UiApp::new();
+        assert!(app.stylesheets.is_empty());
+        let app = app
+            .with_stylesheet("style/base.css")
+            .with_stylesheet("style/theme.css");
+        assert_eq!(
+            app.stylesheets,
+            vec![
+                SimpleKey::Path("style/base.css".into()),
+                SimpleKey::Path("style/theme.css".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn components_and_resources_go_to_the_world() {
+        struct Score(u32);
+
+        let app = UiApp::new().register::<Badge>().with_resource(Score(3));
+        assert_eq!(app.world.read_resource::<Score>().0, 3);
+        assert_eq!((&app.world.read_storage::<Badge>()).join().count(), 0);
+        // the core resources are there from the start
+        app.world.read_resource::<manager::Diagnostics>();
+    }
+
+    #[test]
+    fn window_assets_and_scene_are_kept_for_run() {
+        let app = UiApp::new()
+            .with_window(WindowOptions {
+                title: "Badges".to_owned(),
+                size: Some((320, 200)),
+            })
+            .with_embedded("style/badges.css", b"Badge { }")
+            .with_scene(|world| {
+                world.create_entity().build();
+            });
+        assert_eq!(app.window.title, "Badges");
+        assert_eq!(app.window.size, Some((320, 200)));
+        assert_eq!(app.embedded.len(), 1);
+        assert_eq!(app.embedded[0].0, PathBuf::from("style/badges.css"));
+        assert_eq!(&*app.embedded[0].1, &b"Badge { }"[..]);
+        assert!(app.scene.is_some());
+        assert_eq!(UiApp::default().window.title, "peg");
+    }
+
+    /// A frame drawn by the quad shader, against the software rasterizer that
+    /// mirrors it as reference.
+    #[cfg(feature = "headless")]
+    #[test]
+    fn gpu_frame_matches_the_software_renderer() {
+        use crate::style_system::{EElement, StyleBackground};
+        use crate::transform::{Parent, Transform};
+
+        let scene = |world: &mut World| {
+            // loaded now, not on the loading thread while the frames are drawn
+            let store = world.read_resource::<manager::ResourceManager>();
+            store
+                .get::<manager::ShaderSet>(&SimpleKey::Logical("shader/cube".into()))
+                .unwrap();
+            for key in &world.read_resource::<Stylesheets>().0 {
+                store.get::<crate::styling::Stylesheet>(key).ok();
+            }
+            drop(store);
+            let root = world
+                .create_entity()
+                .with(Transform::new(0.0, 0.0))
+                .with(EElement::new("Root".into()))
+                .with(StyleBackground::from_color(255, 0, 0, 255))
+                .build();
+            world
+                .create_entity()
+                .with(Transform::default().with_size(40.0, 20.0))
+                .with(EElement::new("Button".into()))
+                .with(StyleBackground::from_color(0, 255, 0, 255))
+                .with(rendering::Material::default())
+                .with(Parent { entity: root })
+                .build();
+        };
+        let ui = UiApp::new().with_scene(scene);
+        let size = (64, 48);
+        let (wrap, pixels) = gfx_app::render_headless(size, 3, move |factory, backend, targets| {
+            gfx_app::Wrap::new(factory, backend, targets, |factory, backend, targets| {
+                App::new(factory, backend, targets, ui)
+            })
+        });
+
+        let reference = rendering::SoftwareRenderer::new().render(&wrap.app().world.res);
+        assert_eq!((reference.width, reference.height), size);
+        let differing = pixels
+            .chunks(4)
+            .zip(reference.pixels.chunks(4))
+            .filter(|(gpu, cpu)| {
+                gpu.iter()
+                    .zip(cpu.iter())
+                    .any(|(a, b)| (i32::from(*a) - i32::from(*b)).abs() > 2)
+            })
+            .count();
+        // antialiased edges may round differently, nothing else
+        assert!(
+            differing * 100 <= pixels.len() / 4,
+            "{} pixels differ",
+            differing
+        );
+    }
+}
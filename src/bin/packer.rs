@@ -0,0 +1,48 @@
+//! Packs asset folders into a pack file, which release builds mount over the
+//! assets embedded in them.
+//!
+//! `packer [output] [folder...]`, by default `packer assets.pack style shader`.
+//! Folders are relative to the current directory, as the paths of resources.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let output = args.next().unwrap_or_else(|| "assets.pack".to_owned());
+    let mut folders: Vec<String> = args.collect();
+    if folders.is_empty() {
+        folders = vec!["style".to_owned(), "shader".to_owned()];
+    }
+
+    let mut paths = Vec::new();
+    for folder in &folders {
+        collect(Path::new(folder), &mut paths)?;
+    }
+    // the same folders give the same pack
+    paths.sort();
+    let contents = paths.iter().map(fs::read).collect::<io::Result<Vec<_>>>()?;
+    let files: Vec<(&Path, &[u8])> = paths
+        .iter()
+        .map(PathBuf::as_path)
+        .zip(contents.iter().map(Vec::as_slice))
+        .collect();
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    peg::vfs::write_pack(&mut out, &files)?;
+    println!("packed {} files into {}", files.len(), output);
+    Ok(())
+}
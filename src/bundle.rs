@@ -0,0 +1,18 @@
+//! Bundles group the components, resources and systems of a feature, so an
+//! application plugs it in with `UiApp::with_bundle`. Styling, layout,
+//! rendering and picking are bundles themselves.
+
+use specs::prelude::*;
+
+pub trait UiBundle<'a, 'b> {
+    /// Registers the components and resources of the bundle in `world`, and
+    /// adds its systems to `dispatcher`. Systems depend on those of the
+    /// bundles added before, by name.
+    fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>);
+
+    /// The default style of the elements of the bundle, as CSS. It cascades
+    /// before the stylesheets of the application, which override it.
+    fn style(&self) -> Option<&'static str> {
+        None
+    }
+}
@@ -14,16 +14,7 @@
 #![allow(dead_code)]
 use crate::shade;
 
-#[cfg(not(any(feature = "vulkan", feature = "metal")))]
 pub type ColorFormat = gfx::format::Rgba8;
-#[cfg(feature = "vulkan")]
-pub type ColorFormat = gfx::format::Bgra8;
-#[cfg(feature = "metal")]
-pub type ColorFormat = (gfx::format::B8_G8_R8_A8, gfx::format::Srgb);
-
-#[cfg(feature = "metal")]
-pub type DepthFormat = gfx::format::Depth32F;
-#[cfg(not(feature = "metal"))]
 pub type DepthFormat = gfx::format::DepthStencil;
 
 pub struct WindowTargets<R: gfx::Resources> {
@@ -60,8 +51,8 @@ impl Harness {
 impl Drop for Harness {
     fn drop(&mut self) {
         let time_end = self.start.elapsed();
-        println!(
-            "Avg frame time: {} ms",
+        info!(
+            "avg frame time: {} ms",
             ((time_end.as_secs() * 1000) as f64 + (time_end.subsec_nanos() / 1_000_000) as f64)
                 / self.num_frames
         );
@@ -74,9 +65,6 @@ pub trait Factory<R: gfx::Resources>: gfx::Factory<R> {
 }
 
 pub trait ApplicationBase<R: gfx::Resources, C: gfx::CommandBuffer<R>, F: gfx::Factory<R>> {
-    fn new(f: F, b: shade::Backend, w: WindowTargets<R>) -> Self
-    where
-        F: Factory<R, CommandBuffer = C>;
     fn render<D>(&mut self, d: &mut D)
     where
         D: gfx::Device<Resources = R, CommandBuffer = C>;
@@ -92,7 +80,8 @@ impl Factory<gfx_device_gl::Resources> for gfx_device_gl::Factory {
     }
 }
 
-pub fn launch_gl3<A>(window: winit::WindowBuilder)
+/// Opens `window` and runs the application `init` makes until it is closed.
+pub fn launch_gl3<A, I>(window: winit::WindowBuilder, init: I)
 where
     A: Sized
         + ApplicationBase<
@@ -100,6 +89,7 @@ where
             gfx_device_gl::CommandBuffer,
             gfx_device_gl::Factory,
         >,
+    I: FnOnce(gfx_device_gl::Factory, shade::Backend, WindowTargets<gfx_device_gl::Resources>) -> A,
 {
     use gfx::traits::Device;
 
@@ -127,7 +117,7 @@ where
     } else {
         shade::Backend::Glsl(shade_lang)
     };
-    let mut app = A::new(
+    let mut app = init(
         factory,
         backend,
         WindowTargets {
@@ -184,6 +174,149 @@ where
     }
 }
 
+/// Renders `frames` frames of the application into an offscreen target of
+/// `size` pixels, without any window, and saves the last one to `path` as a
+/// PNG.
+#[cfg(feature = "headless")]
+pub fn launch_headless<A, I>(size: (u32, u32), frames: usize, path: &std::path::Path, init: I)
+where
+    A: Sized
+        + ApplicationBase<
+            gfx_device_gl::Resources,
+            gfx_device_gl::CommandBuffer,
+            gfx_device_gl::Factory,
+        >,
+    I: FnOnce(gfx_device_gl::Factory, shade::Backend, WindowTargets<gfx_device_gl::Resources>) -> A,
+{
+    let (_, pixels) = render_headless(size, frames, init);
+    image::save_buffer(path, &pixels, size.0, size.1, image::ColorType::RGBA(8))
+        .expect("Failed to save the screenshot");
+}
+
+/// Renders `frames` frames of the application into an offscreen target of
+/// `size` pixels, and returns the application with the RGBA8 pixels of the
+/// last frame, top row first.
+///
+/// The GL context comes from OSMesa, Mesa's offscreen software rasterizer: it
+/// needs neither a display nor a GPU, only `libOSMesa` with OpenGL 3.2 core.
+#[cfg(feature = "headless")]
+pub fn render_headless<A, I>(size: (u32, u32), frames: usize, init: I) -> (A, Vec<u8>)
+where
+    A: Sized
+        + ApplicationBase<
+            gfx_device_gl::Resources,
+            gfx_device_gl::CommandBuffer,
+            gfx_device_gl::Factory,
+        >,
+    I: FnOnce(gfx_device_gl::Factory, shade::Backend, WindowTargets<gfx_device_gl::Resources>) -> A,
+{
+    use gfx::format::Formatted;
+    use gfx::memory::Typed;
+    use gfx::traits::{Device, Factory as _, FactoryExt as _};
+    use std::os::raw::c_void;
+
+    // tests render several times in one process
+    let _ = env_logger::try_init();
+    osmesa_sys::OsMesa::try_loading().expect("Failed to load libOSMesa");
+    let attribs = [
+        osmesa_sys::OSMESA_FORMAT,
+        osmesa_sys::OSMESA_RGBA,
+        osmesa_sys::OSMESA_DEPTH_BITS,
+        24,
+        osmesa_sys::OSMESA_STENCIL_BITS,
+        8,
+        osmesa_sys::OSMESA_PROFILE,
+        osmesa_sys::OSMESA_CORE_PROFILE,
+        osmesa_sys::OSMESA_CONTEXT_MAJOR_VERSION,
+        3,
+        osmesa_sys::OSMESA_CONTEXT_MINOR_VERSION,
+        2,
+        0,
+    ];
+    let context =
+        unsafe { osmesa_sys::OSMesaCreateContextAttribs(attribs.as_ptr(), std::ptr::null_mut()) };
+    assert!(!context.is_null(), "Failed to create the OSMesa context");
+    // the default framebuffer, unused: frames are drawn in the color target below
+    let mut buffer = vec![0u8; size.0 as usize * size.1 as usize * 4];
+    let current = unsafe {
+        osmesa_sys::OSMesaMakeCurrent(
+            context,
+            buffer.as_mut_ptr() as *mut c_void,
+            // GL_UNSIGNED_BYTE
+            0x1401,
+            size.0 as i32,
+            size.1 as i32,
+        )
+    };
+    assert!(current != 0, "Failed to make the context current");
+    let (mut device, mut factory) = gfx_device_gl::create(|name| {
+        let name = std::ffi::CString::new(name).unwrap();
+        unsafe {
+            std::mem::transmute::<_, *const c_void>(osmesa_sys::OSMesaGetProcAddress(name.as_ptr()))
+        }
+    });
+    let shade_lang = device.get_info().shading_language;
+    let backend = if shade_lang.is_embedded {
+        shade::Backend::GlslEs(shade_lang)
+    } else {
+        shade::Backend::Glsl(shade_lang)
+    };
+
+    let (width, height) = (size.0 as gfx::texture::Size, size.1 as gfx::texture::Size);
+    let (texture, _, color) = factory
+        .create_render_target::<ColorFormat>(width, height)
+        .expect("Failed to create the color target");
+    let depth = factory
+        .create_depth_stencil_view_only::<DepthFormat>(width, height)
+        .expect("Failed to create the depth target");
+    let mut app = init(
+        factory.clone(),
+        backend,
+        WindowTargets {
+            color,
+            depth,
+            aspect_ratio: size.0 as f32 / size.1 as f32,
+            size,
+            dpi_factor: 1.0,
+        },
+    );
+    for _ in 0..frames {
+        app.render(&mut device);
+        device.cleanup();
+    }
+
+    // read back the color target
+    let download = factory
+        .create_download_buffer::<[u8; 4]>(width as usize * height as usize)
+        .expect("Failed to create the download buffer");
+    let mut encoder: gfx::Encoder<_, _> = factory.create_command_buffer().into();
+    encoder
+        .copy_texture_to_buffer_raw(
+            texture.raw(),
+            None,
+            texture
+                .get_info()
+                .to_raw_image_info(ColorFormat::get_format(), 0),
+            download.raw(),
+            0,
+        )
+        .expect("Failed to copy the color target");
+    encoder.flush(&mut device);
+    let pixels: Vec<u8> = {
+        let reader = factory
+            .read_mapping(&download)
+            .expect("Failed to map the download buffer");
+        // GL rows go from the bottom up
+        reader
+            .chunks(width as usize)
+            .rev()
+            .flat_map(|row| row.iter().flat_map(|p| p.iter().cloned()))
+            .collect()
+    };
+    unsafe { osmesa_sys::OSMesaDestroyContext(context) };
+    (app, pixels)
+}
+
 #[cfg(target_os = "windows")]
 pub type D3D11CommandBuffer = gfx_device_dx11::CommandBuffer<gfx_device_dx11::DeferredContext>;
 #[cfg(target_os = "windows")]
@@ -198,10 +331,15 @@ impl Factory<gfx_device_dx11::Resources> for gfx_device_dx11::Factory {
 }
 
 #[cfg(target_os = "windows")]
-pub fn launch_d3d11<A>(wb: winit::WindowBuilder)
+pub fn launch_d3d11<A, I>(wb: winit::WindowBuilder, init: I)
 where
     A: Sized
         + ApplicationBase<gfx_device_dx11::Resources, D3D11CommandBuffer, gfx_device_dx11::Factory>,
+    I: FnOnce(
+        gfx_device_dx11::Factory,
+        shade::Backend,
+        WindowTargets<gfx_device_dx11::Resources>,
+    ) -> A,
 {
     use gfx::traits::{Device, Factory};
 
@@ -214,7 +352,7 @@ where
         .unwrap();
 
     let backend = shade::Backend::Hlsl(device.get_shader_model());
-    let mut app = A::new(
+    let mut app = init(
         factory,
         backend,
         WindowTargets {
@@ -280,154 +418,17 @@ where
     }
 }
 
-#[cfg(feature = "metal")]
-impl Factory<gfx_device_metal::Resources> for gfx_device_metal::Factory {
-    type CommandBuffer = gfx_device_metal::CommandBuffer;
-    fn create_encoder(&mut self) -> gfx::Encoder<gfx_device_metal::Resources, Self::CommandBuffer> {
-        self.create_command_buffer().into()
-    }
-}
-
-#[cfg(feature = "metal")]
-pub fn launch_metal<A>(wb: winit::WindowBuilder)
-where
-    A: Sized + ApplicationBase<gfx_device_metal::Resources, gfx_device_metal::CommandBuffer>,
-{
-    use gfx::texture::Size;
-    use gfx::traits::{Device, Factory};
-
-    env_logger::init();
-    let mut events_loop = winit::EventsLoop::new();
-    let (window, mut device, mut factory, main_color) =
-        gfx_window_metal::init::<ColorFormat>(wb, &events_loop).unwrap();
-    let (width, height): (u32, u32) = window.get_inner_size().unwrap().into();
-    let main_depth = factory
-        .create_depth_stencil_view_only(width as Size, height as Size)
-        .unwrap();
-
-    let backend = shade::Backend::Msl(device.get_shader_model());
-    let mut app = A::new(
-        &mut factory,
-        backend,
-        WindowTargets {
-            color: main_color,
-            depth: main_depth,
-            aspect_ratio: width as f32 / height as f32,
-        },
-    );
-
-    let mut harness = Harness::new();
-    let mut running = true;
-    while running {
-        events_loop.poll_events(|event| {
-            if let winit::Event::WindowEvent { event, .. } = event {
-                match event {
-                    winit::WindowEvent::CloseRequested => running = false,
-                    winit::WindowEvent::KeyboardInput {
-                        input:
-                            winit::KeyboardInput {
-                                state: winit::ElementState::Pressed,
-                                virtual_keycode: key,
-                                ..
-                            },
-                        ..
-                    } if key == A::get_exit_key() => return,
-                    winit::WindowEvent::Resized(_size) => {
-                        warn!("TODO: resize on Metal");
-                    }
-                    _ => app.on(event),
-                }
-            }
-        });
-        app.render(&mut device);
-        window.swap_buffers().unwrap();
-        device.cleanup();
-        harness.bump();
-    }
-}
-
-#[cfg(feature = "vulkan")]
-impl Factory<gfx_device_vulkan::Resources> for gfx_device_vulkan::Factory {
-    type CommandBuffer = gfx_device_vulkan::CommandBuffer;
-    fn create_encoder(
-        &mut self,
-    ) -> gfx::Encoder<gfx_device_vulkan::Resources, Self::CommandBuffer> {
-        self.create_command_buffer().into()
-    }
-}
-
-#[cfg(feature = "vulkan")]
-pub fn launch_vulkan<A>(wb: winit::WindowBuilder)
-where
-    A: Sized + ApplicationBase<gfx_device_vulkan::Resources, gfx_device_vulkan::CommandBuffer>,
-{
-    use gfx::texture::Size;
-    use gfx::traits::{Device, Factory};
-
-    env_logger::init();
-    let mut events_loop = winit::EventsLoop::new();
-    let (mut win, mut factory) = gfx_window_vulkan::init::<ColorFormat>(wb, &events_loop);
-    let (width, height) = win.get_size();
-    let main_depth = factory
-        .create_depth_stencil::<DepthFormat>(width as Size, height as Size)
-        .unwrap();
-
-    let backend = shade::Backend::Vulkan;
-    let mut app = A::new(
-        &mut factory,
-        backend,
-        WindowTargets {
-            color: win.get_any_target(),
-            depth: main_depth.2,
-            aspect_ratio: width as f32 / height as f32, //TODO
-        },
-    );
-
-    let mut harness = Harness::new();
-    let mut running = true;
-    while running {
-        events_loop.poll_events(|event| {
-            if let winit::Event::WindowEvent { event, .. } = event {
-                match event {
-                    winit::WindowEvent::CloseRequested => running = false,
-                    winit::WindowEvent::KeyboardInput {
-                        input:
-                            winit::KeyboardInput {
-                                state: winit::ElementState::Pressed,
-                                virtual_keycode: key,
-                                ..
-                            },
-                        ..
-                    } if key == A::get_exit_key() => return,
-                    winit::WindowEvent::Resized(_size) => {
-                        warn!("TODO: resize on Vulkan");
-                    }
-                    _ => app.on(event),
-                }
-            }
-        });
-        let mut frame = win.start_frame();
-        app.render(frame.get_queue());
-        frame.get_queue().cleanup();
-        harness.bump();
-    }
-}
-
-#[cfg(all(
-    not(target_os = "windows"),
-    not(feature = "vulkan"),
-    not(feature = "metal")
-))]
+#[cfg(not(target_os = "windows"))]
 pub type DefaultResources = gfx_device_gl::Resources;
-#[cfg(all(target_os = "windows", not(feature = "vulkan")))]
+#[cfg(target_os = "windows")]
 pub type DefaultResources = gfx_device_dx11::Resources;
-#[cfg(feature = "metal")]
-pub type DefaultResources = gfx_device_metal::Resources;
-#[cfg(feature = "vulkan")]
-pub type DefaultResources = gfx_device_vulkan::Resources;
+
+#[cfg(not(target_os = "windows"))]
+pub type DefaultFactory = gfx_device_gl::Factory;
+#[cfg(target_os = "windows")]
+pub type DefaultFactory = gfx_device_dx11::Factory;
 
 pub trait Application<R: gfx::Resources, F: gfx::Factory<R>>: Sized {
-    fn new(f: F, b: shade::Backend, wt: WindowTargets<R>) -> Self;
     fn render<C: gfx::CommandBuffer<R>>(&mut self, e: &mut gfx::Encoder<R, C>);
 
     fn get_exit_key() -> Option<winit::VirtualKeyCode> {
@@ -438,46 +439,29 @@ pub trait Application<R: gfx::Resources, F: gfx::Factory<R>>: Sized {
         self.on_resize(targets);
     }
     fn on(&mut self, _event: winit::WindowEvent) {}
+}
 
-    fn launch_simple(name: &str)
-    where
-        Self: Application<DefaultResources, gfx_device_dx11::Factory>,
-    {
-        let wb = winit::WindowBuilder::new().with_title(name);
-        <Self as Application<DefaultResources, gfx_device_dx11::Factory>>::launch_default(wb)
-    }
-    #[cfg(all(
-        not(target_os = "windows"),
-        not(feature = "vulkan"),
-        not(feature = "metal")
-    ))]
-    fn launch_default(wb: winit::WindowBuilder)
-    where
-        Self: Application<DefaultResources>,
-    {
-        launch_gl3::<Wrap<_, _, Self, gfx_device_gl::Factory>>(wb);
-    }
-    #[cfg(all(target_os = "windows", not(feature = "vulkan")))]
-    fn launch_default(wb: winit::WindowBuilder)
-    where
-        Self: Application<DefaultResources, gfx_device_dx11::Factory>,
-    {
-        launch_d3d11::<Wrap<_, _, Self, gfx_device_dx11::Factory>>(wb);
-    }
-    #[cfg(feature = "metal")]
-    fn launch_default(wb: winit::WindowBuilder)
-    where
-        Self: Application<DefaultResources>,
-    {
-        launch_metal::<Wrap<_, _, Self>>(wb);
-    }
-    #[cfg(feature = "vulkan")]
-    fn launch_default(wb: winit::WindowBuilder)
-    where
-        Self: Application<DefaultResources>,
-    {
-        launch_vulkan::<Wrap<_, _, Self>>(wb);
-    }
+/// Opens `wb` with the default backend of the platform and runs the
+/// application `init` makes until the window is closed.
+#[cfg(not(target_os = "windows"))]
+pub fn launch_default<A, I>(wb: winit::WindowBuilder, init: I)
+where
+    A: Application<DefaultResources, DefaultFactory>,
+    I: FnOnce(DefaultFactory, shade::Backend, WindowTargets<DefaultResources>) -> A,
+{
+    launch_gl3(wb, |factory, backend, targets| {
+        Wrap::new(factory, backend, targets, init)
+    });
+}
+#[cfg(target_os = "windows")]
+pub fn launch_default<A, I>(wb: winit::WindowBuilder, init: I)
+where
+    A: Application<DefaultResources, DefaultFactory>,
+    I: FnOnce(DefaultFactory, shade::Backend, WindowTargets<DefaultResources>) -> A,
+{
+    launch_d3d11(wb, |factory, backend, targets| {
+        Wrap::new(factory, backend, targets, init)
+    });
 }
 
 pub struct Wrap<R: gfx::Resources, C, A, F: Factory<R, CommandBuffer = C>>
@@ -489,21 +473,41 @@ where
     _f: std::marker::PhantomData<F>,
 }
 
-impl<R, C, A, F> ApplicationBase<R, C, F> for Wrap<R, C, A, F>
+impl<R, C, A, F> Wrap<R, C, A, F>
 where
     R: gfx::Resources,
     C: gfx::CommandBuffer<R>,
     A: Application<R, F>,
     F: Factory<R, CommandBuffer = C>,
 {
-    fn new(mut factory: F, backend: shade::Backend, window_targets: WindowTargets<R>) -> Self {
+    pub fn new<I>(
+        mut factory: F,
+        backend: shade::Backend,
+        window_targets: WindowTargets<R>,
+        init: I,
+    ) -> Self
+    where
+        I: FnOnce(F, shade::Backend, WindowTargets<R>) -> A,
+    {
         Wrap {
             encoder: factory.create_encoder(),
-            app: A::new(factory, backend, window_targets),
+            app: init(factory, backend, window_targets),
             _f: std::marker::PhantomData,
         }
     }
 
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+}
+
+impl<R, C, A, F> ApplicationBase<R, C, F> for Wrap<R, C, A, F>
+where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    A: Application<R, F>,
+    F: Factory<R, CommandBuffer = C>,
+{
     fn render<D>(&mut self, device: &mut D)
     where
         D: gfx::Device<Resources = R, CommandBuffer = C>,
@@ -0,0 +1,268 @@
+//! Mouse input: the state of the mouse, the picking of the element under it,
+//! the click callbacks and the scrolling of containers.
+
+use crate::style_system::{EElement, Pseudo};
+use crate::transform::{GlobalTransform, Parent};
+use crate::{layout, rendering};
+use specs::prelude::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ButtonState {
+    Up,
+    Pressed,
+    Down,
+    Released,
+}
+
+#[derive(Debug)]
+pub struct MouseEvent {
+    pub position: (i32, i32),
+    pub left_click: ButtonState,
+    /// Wheel scrolling of the current frame, in physical pixels.
+    pub wheel: (f32, f32),
+}
+
+impl Default for MouseEvent {
+    fn default() -> Self {
+        MouseEvent {
+            position: (-1, -1),
+            left_click: ButtonState::Up,
+            wheel: (0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EventType {
+    Pressed,
+    Released,
+}
+#[derive(Debug)]
+pub struct Event {
+    pub target: Entity,
+    pub event_type: EventType,
+}
+
+impl Component for Event {
+    type Storage = HashMapStorage<Self>;
+}
+
+pub type Callback = Box<dyn Fn(Entity) + Send>;
+pub struct Events {
+    pub map: std::sync::Mutex<std::collections::HashMap<Entity, Callback>>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            map: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+}
+
+impl Events {
+    pub fn register(&mut self, e: Entity, c: Callback) {
+        self.map.lock().unwrap().insert(e, c);
+    }
+    pub fn invoke(&self, e: Entity) {
+        if let Some(cb) = self.map.lock().unwrap().get(&e) {
+            let cb2 = cb;
+            cb2(e);
+        }
+    }
+}
+
+/// Logs the events of the frame, at the debug level.
+pub struct ConsumeEventsSystem;
+impl<'a> System<'a> for ConsumeEventsSystem {
+    type SystemData = (Entities<'a>, ReadStorage<'a, Event>);
+
+    fn run(&mut self, (entities, event): Self::SystemData) {
+        for (_e, event) in (&entities, &event).join() {
+            debug!("event {:?}", event);
+        }
+    }
+}
+
+pub struct CleanEventsSystem;
+impl<'a> System<'a> for CleanEventsSystem {
+    type SystemData = (Entities<'a>, ReadStorage<'a, Event>);
+
+    fn run(&mut self, (entities, event): Self::SystemData) {
+        for (e, _event) in (&entities, &event).join() {
+            entities.delete(e).unwrap();
+        }
+    }
+}
+
+pub struct PickSystem;
+impl<'a> System<'a> for PickSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, EElement>,
+        ReadStorage<'a, GlobalTransform>,
+        ReadStorage<'a, Parent>,
+        WriteStorage<'a, Pseudo>,
+        WriteStorage<'a, Event>,
+        Read<'a, MouseEvent>,
+        Read<'a, Events>,
+        Read<'a, rendering::Screen>,
+        Read<'a, rendering::DisplayList>,
+    );
+
+    #[allow(dead_code)]
+    fn run(
+        &mut self,
+        (entities, eelements, global, parents, mut pseudo, mut event, mouse, events, screen, list): Self::SystemData,
+    ) {
+        use cgmath::Transform;
+
+        let dpi = screen.dpi_factor as f32;
+        // typically on a retina macbook: dpi=2
+        // mouse position will be in logical space (eg. 1024x768),
+        // positions are in physical space (logical*dpi = 2048x1534)
+        let p: cgmath::Point3<f32> = cgmath::Point3::new(
+            mouse.position.0 as f32 * dpi,
+            mouse.position.1 as f32 * dpi,
+            0.0,
+        );
+
+        let missing_pseudos: specs::BitSet = (&entities, &eelements, !&pseudo)
+            .join()
+            .map(|(e, _, _)| e.id())
+            .collect();
+        for id in (&missing_pseudos).join() {
+            pseudo
+                .insert(entities.entity(id), Pseudo { hover: false })
+                .unwrap();
+        }
+
+        // the display list is back to front: the first hit in reverse order is the topmost
+        let target = list
+            .order
+            .iter()
+            .rev()
+            .find(|painted| {
+                let e = painted.entity;
+                if pseudo.get(e).is_none() {
+                    return false;
+                }
+                // points outside the clip of an ancestor cannot reach the entity
+                if let Some(clip) = painted.clip {
+                    if !clip.contains(p.x, p.y) {
+                        return false;
+                    }
+                }
+                let global = match global.get(e) {
+                    Some(global) => global,
+                    None => return false,
+                };
+                // back in the box space, where it is axis aligned
+                let local = match global.0.inverse_transform() {
+                    Some(inverse) => inverse.transform_point(p),
+                    None => return false,
+                };
+                let size = global.1;
+                local.x >= 0.0 && local.x <= size.0 && local.y >= 0.0 && local.y <= size.1
+            })
+            .map(|painted| painted.entity);
+        // println!("pick mouse {:?} target {:?}", p, target);
+
+        for pseudo in (&mut pseudo).join() {
+            pseudo.hover = false;
+        }
+
+        let target = match target {
+            Some(target) => target,
+            None => return,
+        };
+
+        // the target and its ancestors are hovered, and clicks bubble up to them
+        let mut current = Some(target);
+        while let Some(e) = current {
+            if let Some(pseudo) = pseudo.get_mut(e) {
+                pseudo.hover = true;
+            }
+            if mouse.left_click == ButtonState::Pressed {
+                events.invoke(e);
+            }
+            current = parents.get(e).map(|p| p.entity);
+        }
+
+        let event_type = match mouse.left_click {
+            ButtonState::Pressed => EventType::Pressed,
+            ButtonState::Released => EventType::Released,
+            _ => return,
+        };
+        entities
+            .build_entity()
+            .with(
+                Event {
+                    target,
+                    event_type,
+                },
+                &mut event,
+            )
+            .build();
+    }
+}
+
+/// Picks the element under the mouse, hovering it and its ancestors and
+/// calling their click callbacks, once the display list is made.
+pub struct PickBundle;
+
+impl<'a, 'b> crate::UiBundle<'a, 'b> for PickBundle {
+    fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>) {
+        world.register::<Event>();
+        world.add_resource(MouseEvent::default());
+        world.add_resource(Events::default());
+        dispatcher.add(PickSystem, "sys_pick", &["sys_display_list"]);
+        dispatcher.add(ConsumeEventsSystem, "sys_consume", &["sys_pick"]);
+        dispatcher.add(CleanEventsSystem, "sys_clean_events", &["sys_consume"]);
+    }
+}
+
+/// Pixels scrolled per line of a mouse wheel notch.
+pub const SCROLL_LINE_HEIGHT: f32 = 20.0;
+
+/// Scrolls the innermost hovered `overflow: scroll` container that can still move.
+pub struct ScrollSystem;
+impl<'a> System<'a> for ScrollSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Parent>,
+        ReadStorage<'a, Pseudo>,
+        WriteStorage<'a, layout::ScrollState>,
+        Read<'a, MouseEvent>,
+    );
+
+    fn run(&mut self, (entities, parents, pseudo, mut scrolls, mouse): Self::SystemData) {
+        if mouse.wheel == (0.0, 0.0) {
+            return;
+        }
+
+        let depth = |e: Entity| {
+            let mut depth = 0;
+            let mut current = parents.get(e).map(|p| p.entity);
+            while let Some(e) = current {
+                depth += 1;
+                current = parents.get(e).map(|p| p.entity);
+            }
+            depth
+        };
+        let mut hovered: Vec<(usize, Entity)> = (&*entities, &scrolls, &pseudo)
+            .join()
+            .filter(|(_, _, pseudo)| pseudo.hover)
+            .map(|(e, _, _)| (depth(e), e))
+            .collect();
+        hovered.sort_by(|a, b| b.0.cmp(&a.0));
+
+        // the wheel moves the content up when scrolling down
+        let delta = (-mouse.wheel.0, -mouse.wheel.1);
+        for (_, e) in hovered {
+            if scrolls.get_mut(e).unwrap().scroll_by(delta) {
+                break;
+            }
+        }
+    }
+}
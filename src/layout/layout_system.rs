@@ -1,6 +1,6 @@
-use crate::transform::GlobalTransform;
-use crate::transform::{Parent, ParentHierarchy};
+use crate::transform::{Parent, ParentHierarchy, Transform};
 
+use cgmath::{Matrix4, SquareMatrix};
 use specs::prelude::*;
 use stretch::layout::Node as LayoutNode;
 use stretch::{
@@ -9,13 +9,30 @@ use stretch::{
     style::*,
 };
 
-use crate::manager::*;
-use crate::style_system::EElement;
+use crate::layout::ScrollState;
+use crate::rendering::Image;
+use crate::style_system::{EElement, StyleTransform};
+use crate::text::ShapedText;
+
+/// The css `position` of an element. Stretch only lays out relative and
+/// absolute boxes: a static box is a relative one which ignores its offsets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::Static
+    }
+}
 
 pub struct Dimensions {
     pub display: Display,
 
-    pub position_type: PositionType,
+    pub position_type: Position,
     pub direction: Direction,
     pub flex_direction: FlexDirection,
 
@@ -28,6 +45,8 @@ pub struct Dimensions {
 
     pub justify_content: JustifyContent,
 
+    /// The `left`, `right`, `top` and `bottom` offsets: `start` is the left
+    /// one whatever the direction.
     pub position: Rect<Dimension>,
     pub margin: Rect<Dimension>,
     pub padding: Rect<Dimension>,
@@ -49,9 +68,29 @@ impl Component for Dimensions {
 }
 
 impl Dimensions {
+    /// Resolves `Direction::Inherit` by walking up the parents; the root defaults to LTR.
+    pub fn resolve_direction(
+        e: Entity,
+        dimensions: &ReadStorage<'_, Dimensions>,
+        parents: &ReadStorage<'_, Parent>,
+    ) -> Direction {
+        let mut current = Some(e);
+        while let Some(e) = current {
+            match dimensions.get(e).map(|d| d.direction) {
+                Some(Direction::Inherit) | None => {}
+                Some(direction) => return direction,
+            }
+            current = parents.get(e).map(|p| p.entity);
+        }
+        Direction::LTR
+    }
+
     pub fn fill_node(&self, node: &mut stretch::style::Node) {
         node.display = self.display;
-        node.position_type = self.position_type;
+        node.position_type = match self.position_type {
+            Position::Static | Position::Relative => PositionType::Relative,
+            Position::Absolute => PositionType::Absolute,
+        };
         node.direction = self.direction;
         node.flex_direction = self.flex_direction;
         node.flex_wrap = self.flex_wrap;
@@ -60,7 +99,10 @@ impl Dimensions {
         node.align_self = self.align_self;
         node.align_content = self.align_content;
         node.justify_content = self.justify_content;
-        node.position = self.position;
+        node.position = match self.position_type {
+            Position::Static => Default::default(),
+            _ => self.position,
+        };
         node.margin = self.margin;
         node.padding = self.padding;
         node.border = self.border;
@@ -110,85 +152,280 @@ impl Default for Dimensions {
     }
 }
 
+/// The box of a laid out element, relative to its parent's. The
+/// `TransformSystem` composes it into the `GlobalTransform`.
+#[derive(Debug, Clone)]
+pub struct LayoutBox {
+    /// Top left corner, once the parent is scrolled.
+    pub location: (f32, f32),
+    pub size: (f32, f32),
+    /// The css transform, relative to the top left corner.
+    pub transform: Matrix4<f32>,
+}
+
+impl Component for LayoutBox {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Lays out the elements with stretch and stores their `LayoutBox`.
+///
+/// A layout tree starts at each element whose parent is not an element: it
+/// fills the screen, or the size of the free floating entity it belongs to.
 pub struct LayoutSystem;
 impl<'a> System<'a> for LayoutSystem {
     type SystemData = (
         Entities<'a>,
-        ReadExpect<'a, crate::manager::ResourceManager>,
         ReadExpect<'a, crate::rendering::Screen>,
         ReadExpect<'a, ParentHierarchy>,
         ReadStorage<'a, EElement>,
         ReadStorage<'a, Parent>,
         ReadStorage<'a, Dimensions>,
-        WriteStorage<'a, GlobalTransform>,
-        ReadStorage<'a, crate::rendering::Text>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, LayoutBox>,
+        WriteStorage<'a, ScrollState>,
+        ReadStorage<'a, ShapedText>,
+        ReadStorage<'a, Image>,
+        ReadStorage<'a, StyleTransform>,
     );
 
     fn run(
         &mut self,
-        (entities, store, screen, hierarchy, eelements, parents, dimensions, mut globals, text): Self::SystemData,
+        (
+            entities,
+            screen,
+            hierarchy,
+            eelements,
+            parents,
+            dimensions,
+            locals,
+            mut boxes,
+            mut scrolls,
+            text,
+            images,
+            transforms,
+        ): Self::SystemData,
     ) {
-        let mut root = Node {
-            justify_content: JustifyContent::Center,
-            flex_direction: FlexDirection::Row,
-            size: Size {
-                width: Dimension::Points(screen.size.0 as f32),
-                height: Dimension::Points(screen.size.1 as f32),
-            },
-            ..Default::default()
-        };
+        // scroll containers keep their state as long as they are `overflow: scroll`
+        let stale: Vec<Entity> = (&*entities, &scrolls, &dimensions)
+            .join()
+            .filter(|(_, _, d)| d.overflow != Overflow::Scroll)
+            .map(|(e, _, _)| e)
+            .collect();
+        for e in stale {
+            scrolls.remove(e);
+        }
+        let missing: Vec<Entity> = (&*entities, !&scrolls, &dimensions)
+            .join()
+            .filter(|(_, _, d)| d.overflow == Overflow::Scroll)
+            .map(|(e, _, _)| e)
+            .collect();
+        for e in missing {
+            scrolls.insert(e, Default::default()).unwrap();
+        }
+        let unlaid: Vec<Entity> = (&*entities, &boxes, !&eelements)
+            .join()
+            .map(|(e, _, _)| e)
+            .collect();
+        for e in unlaid {
+            boxes.remove(e);
+        }
 
-        for (entity, _, _) in (&*entities, &eelements, !&parents).join() {
-            let branch = Self::make(&hierarchy, entity, &dimensions, &text, &store);
+        // the roots of each layout tree, by the entity they float in
+        let mut trees: Vec<(Option<Entity>, Vec<Entity>)> = Vec::new();
+        for (entity, _) in (&*entities, &eelements).join() {
+            let parent = parents.get(entity).map(|p| p.entity);
+            if parent.map_or(false, |p| eelements.contains(p)) {
+                continue;
+            }
+            match trees.iter_mut().find(|(p, _)| *p == parent) {
+                Some((_, roots)) => roots.push(entity),
+                None => trees.push((parent, vec![entity])),
+            }
+        }
 
-            root.children.push(branch);
+        let elements = Elements {
+            hierarchy: &hierarchy,
+            eelements: &eelements,
+            dimensions: &dimensions,
+            locals: &locals,
+        };
+        for (parent, roots) in trees {
+            let (width, height) = parent
+                .and_then(|p| locals.get(p))
+                .and_then(|t| t.size)
+                .map_or((screen.size.0 as f32, screen.size.1 as f32), |size| {
+                    (size.x, size.y)
+                });
+            let root = Node {
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Row,
+                size: Size {
+                    width: Dimension::Points(width),
+                    height: Dimension::Points(height),
+                },
+                children: roots
+                    .iter()
+                    .map(|e| Self::make(&elements, *e, Direction::LTR, &text, &images))
+                    .collect(),
+                ..Default::default()
+            };
+
+            // println!("layout {:?}", root);
+            let layout = stretch::compute(&root, Size::undefined()).unwrap();
+
+            for (entity, n) in roots.iter().zip(layout.children.iter()) {
+                Self::apply(
+                    &elements,
+                    *entity,
+                    &mut boxes,
+                    &mut scrolls,
+                    &transforms,
+                    n,
+                    ParentFrame::root(),
+                );
+            }
+            // println!("computed {:#?}", layout);
         }
+    }
+}
 
-        // println!("layout {:?}", root);
-        let layout = stretch::compute(&root, Size::undefined()).unwrap();
+/// The storages describing the element tree.
+struct Elements<'s, 'a> {
+    hierarchy: &'s ParentHierarchy,
+    eelements: &'s ReadStorage<'a, EElement>,
+    dimensions: &'s ReadStorage<'a, Dimensions>,
+    locals: &'s ReadStorage<'a, Transform>,
+}
 
-        let mut i = 0;
-        for (entity, _, _) in (&*entities, &eelements, !&parents).join() {
-            let n = &layout.children[i];
-            Self::apply(&hierarchy, entity, &mut globals, n);
-            i += 1;
-        }
+impl<'s, 'a> Elements<'s, 'a> {
+    /// The children laid out with their parent; others float.
+    fn children(&self, e: Entity) -> Vec<Entity> {
+        self.hierarchy
+            .children(e)
+            .iter()
+            .cloned()
+            .filter(|c| self.eelements.contains(*c))
+            .collect()
+    }
+}
 
-        // println!("computed {:#?}", layout);
+/// What a node inherits from its parent when its layout is applied.
+#[derive(Clone, Copy)]
+struct ParentFrame {
+    direction: Direction,
+    /// Width of the parent when it lays out right to left: stretch computes
+    /// every layout left to right, so the x positions are flipped.
+    mirror: Option<f32>,
+    /// Scroll offset of the parent, subtracted from the children positions.
+    scroll: (f32, f32),
+}
+
+impl ParentFrame {
+    fn root() -> Self {
+        ParentFrame {
+            direction: Direction::LTR,
+            mirror: None,
+            scroll: (0.0, 0.0),
+        }
     }
 }
+
 impl LayoutSystem {
     fn apply(
-        hierarchy: &ParentHierarchy,
+        elements: &Elements,
         e: Entity,
-        mut globals: &mut WriteStorage<'_, GlobalTransform>,
+        mut boxes: &mut WriteStorage<'_, LayoutBox>,
+        mut scrolls: &mut WriteStorage<'_, ScrollState>,
+        transforms: &ReadStorage<'_, StyleTransform>,
         node: &LayoutNode,
+        parent: ParentFrame,
     ) {
-        let t: &mut GlobalTransform = globals.get_mut(e).unwrap();
-        t.0 = cgmath::Matrix4::from_translation([node.location.x, node.location.y, 0.0f32].into());
-        t.1 = (node.size.width, node.size.height);
-        // println!("Layout {:?}: {:?}", e, t);
-        for (i, c) in hierarchy.children(e).iter().enumerate() {
-            Self::apply(&hierarchy, *c, &mut globals, &node.children[i]);
+        let direction = match elements.dimensions.get(e).map(|d| d.direction) {
+            Some(Direction::Inherit) | None => parent.direction,
+            Some(direction) => direction,
+        };
+        let x = match parent.mirror {
+            Some(parent_width) => parent_width - node.location.x - node.size.width,
+            None => node.location.x,
+        };
+
+        let size = (node.size.width, node.size.height);
+        boxes
+            .insert(
+                e,
+                LayoutBox {
+                    location: (x - parent.scroll.0, node.location.y - parent.scroll.1),
+                    size,
+                    transform: transforms
+                        .get(e)
+                        .map_or(Matrix4::identity(), |t| t.matrix(size)),
+                },
+            )
+            .unwrap();
+        // println!("Layout {:?}: {:?}", e, size);
+
+        let scroll = match scrolls.get_mut(e) {
+            Some(state) => {
+                state.viewport = size;
+                state.content_size = node.children.iter().fold((0.0f32, 0.0f32), |(w, h), c| {
+                    (
+                        w.max(c.location.x + c.size.width),
+                        h.max(c.location.y + c.size.height),
+                    )
+                });
+                state.clamp();
+                state.offset
+            }
+            None => (0.0, 0.0),
+        };
+
+        let frame = ParentFrame {
+            direction,
+            mirror: if direction == Direction::RTL {
+                Some(node.size.width)
+            } else {
+                None
+            },
+            scroll,
+        };
+        for (c, n) in elements.children(e).into_iter().zip(node.children.iter()) {
+            Self::apply(elements, c, &mut boxes, &mut scrolls, transforms, n, frame);
         }
     }
 
+    /// `parent_direction` is the resolved direction of the parent of `e`.
     fn make(
-        hierarchy: &ParentHierarchy,
+        elements: &Elements,
         e: Entity,
-        dimensions: &ReadStorage<'_, Dimensions>,
-        text: &ReadStorage<'_, crate::rendering::Text>,
-        store: &ReadExpect<crate::manager::ResourceManager>,
+        parent_direction: Direction,
+        text: &ReadStorage<'_, ShapedText>,
+        images: &ReadStorage<'_, Image>,
     ) -> Node {
         let mut n: Node = Default::default();
-        if let Some(dimensions) = dimensions.get(e) {
+        if let Some(dimensions) = elements.dimensions.get(e) {
             dimensions.fill_node(&mut n);
         }
+        // `apply` mirrors the children of a right to left parent, with their
+        // left and right offsets
+        if parent_direction == Direction::RTL {
+            std::mem::swap(&mut n.position.start, &mut n.position.end);
+        }
+        let direction = match n.direction {
+            Direction::Inherit => parent_direction,
+            direction => direction,
+        };
+        // the size of the transform stands for an `auto` width or height
+        if let Some(size) = elements.locals.get(e).and_then(|t| t.size) {
+            if let Dimension::Auto = n.size.width {
+                n.size.width = Dimension::Points(size.x);
+            }
+            if let Dimension::Auto = n.size.height {
+                n.size.height = Dimension::Points(size.y);
+            }
+        }
 
         if let Some(text) = text.get(e) {
-            let key = SimpleKey::Path(("style/NotoSans-Regular.ttf").into());
-            let font = store.get::<crate::layout::BitmapFont>(&key).unwrap();
-            let measured = font.borrow().measure(&text.text);
+            let measured = text.run.size;
             // let e = e.clone();
             n.measure = Some(Box::new(move |_s| {
                 // println!("measure input {:?} {:?}", e, s);
@@ -196,9 +433,30 @@ impl LayoutSystem {
             }));
         }
 
-        for c in hierarchy.children(e) {
+        if let Some((width, height)) = images.get(e).and_then(|image| image.size) {
+            n.measure = Some(Box::new(move |s| {
+                // a single constrained side keeps the aspect ratio of the picture
+                Ok(match (s.width, s.height) {
+                    (Number::Defined(w), Number::Defined(h)) => Size {
+                        width: w,
+                        height: h,
+                    },
+                    (Number::Defined(w), _) if width > 0.0 => Size {
+                        width: w,
+                        height: w * height / width,
+                    },
+                    (_, Number::Defined(h)) if height > 0.0 => Size {
+                        width: h * width / height,
+                        height: h,
+                    },
+                    _ => Size { width, height },
+                })
+            }));
+        }
+
+        for c in elements.children(e) {
             n.children
-                .push(Self::make(hierarchy, *c, dimensions, text, store));
+                .push(Self::make(elements, c, direction, text, images));
         }
 
         n
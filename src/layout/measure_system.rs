@@ -5,35 +5,58 @@
 // use cgmath::Point2;
 // use hashbrown::HashMap;
 use stretch::geometry::Size;
+use stretch::style::Direction;
 
 use crate::manager::*;
 
 #[derive(Debug)]
-pub struct BitmapFont(pub gfx_text::BitmapFont);
+pub struct BitmapFont(pub gfx_text::BitmapFont, Kerning);
+
+/// Pair kerning read from the font face; the bitmap only stores advances.
+pub struct Kerning {
+    // declared first so the face is dropped before its library
+    face: Option<freetype::Face>,
+    _library: freetype::Library,
+}
+
+impl std::fmt::Debug for Kerning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Kerning({})", self.face.is_some())
+    }
+}
+
+impl Kerning {
+    fn from_bytes(bytes: Vec<u8>, size: u8) -> Result<Self, freetype::Error> {
+        let library = freetype::Library::init()?;
+        let face = library.new_memory_face(bytes, 0)?;
+        face.set_pixel_sizes(0, u32::from(size))?;
+        Ok(Kerning {
+            face: if face.has_kerning() { Some(face) } else { None },
+            _library: library,
+        })
+    }
+}
 
 impl BitmapFont {
+    pub fn has_char(&self, ch: char) -> bool {
+        self.0.find_char(ch).is_some()
+    }
+
+    /// Horizontal adjustment in pixels between two glyphs, in visual order.
+    pub fn kerning(&self, left: char, right: char) -> i32 {
+        let face = match self.1.face {
+            Some(ref face) => face,
+            None => return 0,
+        };
+        let left = face.get_char_index(left as usize);
+        let right = face.get_char_index(right as usize);
+        face.get_kerning(left, right, freetype::face::KerningMode::KerningDefault)
+            .map(|v| (v.x >> 6) as i32)
+            .unwrap_or(0)
+    }
+
     pub fn measure(&self, text: &str) -> Size<f32> {
-        let mut width = 0;
-        let mut last_char = None;
-
-        for ch in text.chars() {
-            let ch_info = match self.0.find_char(ch) {
-                Some(info) => info,
-                None => continue,
-            };
-            last_char = Some(ch_info);
-
-            width += ch_info.x_advance;
-        }
-
-        if let Some(info) = last_char {
-            width += info.x_offset + info.width - info.x_advance;
-        }
-
-        Size {
-            width: width as f32,
-            height: f32::from(self.0.get_font_height()),
-        }
+        crate::text::shape(self, text, Direction::LTR).size
     }
 }
 
@@ -43,19 +66,54 @@ impl Load<Ctx, SimpleKey> for BitmapFont {
     fn load(
         key: SimpleKey,
         _storage: &mut warmy::Storage<Ctx, SimpleKey>,
+        ctx: &mut Ctx,
+    ) -> Result<Loaded<Self, SimpleKey>, Error> {
+        let bitmap = match ctx.take_prepared::<gfx_text::BitmapFont>() {
+            Some(bitmap) => bitmap,
+            None => Self::prepare(&key, ctx)?,
+        };
+        // the face is not Send, it is opened on the calling thread
+        let bytes = ctx.sources().read(&key)?.into_owned();
+        let kerning =
+            Kerning::from_bytes(bytes, crate::text::FONT_SIZE).map_err(Error::FreetypeError)?;
+        Ok(Loaded::without_dep(BitmapFont(bitmap, kerning)))
+    }
+
+    fn reload(
+        &self,
+        key: SimpleKey,
+        storage: &mut warmy::Storage<Ctx, SimpleKey>,
+        ctx: &mut Ctx,
+    ) -> Result<Self, Error> {
+        let res = <Self as Load<Ctx, SimpleKey>>::load(key.clone(), storage, ctx)?.res;
+        ctx.reloaded::<Self>(key);
+        Ok(res)
+    }
+}
+
+impl Load<Ctx, SimpleKey, AlwaysFail> for BitmapFont {
+    type Error = Error;
+
+    fn load(
+        _key: SimpleKey,
+        _storage: &mut warmy::Storage<Ctx, SimpleKey>,
         _ctx: &mut Ctx,
     ) -> Result<Loaded<Self, SimpleKey>, Error> {
-        match key {
-            SimpleKey::Path(path) => {
-                println!("Load BitmapFont {}", path.display());
-                let bitmap = gfx_text::BitmapFont::from_path(path.to_str().unwrap(), 16, None)
-                    .map_err(Error::FontError)?;
-                // storage.get::<ShaderSet>(&dep, ctx).unwrap();
-                Ok(Loaded::without_dep(BitmapFont(bitmap)))
-            }
-
-            SimpleKey::Logical(_) => Err(Error::CannotLoadFromLogical),
-        }
+        Err(Error::CannotLoadFromFS)
+    }
+}
+
+/// Rasterizing the charset is the slow part: it runs on the loading thread.
+impl Prepare for BitmapFont {
+    type Prepared = gfx_text::BitmapFont;
+
+    fn prepare(key: &SimpleKey, ctx: &Ctx) -> Result<gfx_text::BitmapFont, Error> {
+        use crate::text::{CHARSET, FONT_SIZE};
+
+        debug!("load bitmap font {}", key);
+        let bytes = ctx.sources().read(key)?;
+        gfx_text::BitmapFont::from_bytes(&bytes, FONT_SIZE, Some(&CHARSET))
+            .map_err(Error::FontError)
     }
 }
 
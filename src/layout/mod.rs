@@ -1,5 +1,39 @@
 mod layout_system;
 mod measure_system;
+mod scroll;
 
 pub use layout_system::*;
 pub use measure_system::*;
+pub use scroll::*;
+
+use specs::prelude::*;
+
+/// Shapes the text and sizes the images of the elements, lays them out and
+/// makes their global transforms, once they are styled.
+pub struct LayoutBundle;
+
+impl<'a, 'b> crate::UiBundle<'a, 'b> for LayoutBundle {
+    fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>) {
+        world.register::<crate::transform::Transform>();
+        world.register::<crate::text::ShapedText>();
+        dispatcher.add(
+            crate::text::TextShapingSystem::default(),
+            "sys_text_shaping",
+            &["sys_style"],
+        );
+        dispatcher.add(
+            crate::input::ScrollSystem,
+            "sys_scroll",
+            &["sys_text_shaping"],
+        );
+        // images are laid out at their intrinsic size
+        dispatcher.add(crate::rendering::ImageSystem, "sys_image", &[]);
+        dispatcher.add(LayoutSystem, "sys_layout", &["sys_scroll", "sys_image"]);
+        // layout boxes, local transforms and css transforms make the globals
+        dispatcher.add(
+            crate::transform::TransformSystem::new(),
+            "transform_system",
+            &["sys_layout"],
+        );
+    }
+}
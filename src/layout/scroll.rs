@@ -0,0 +1,41 @@
+use specs::prelude::*;
+
+/// Scroll position of an `overflow: scroll` container, maintained by the layout.
+#[derive(Debug, Default, Clone)]
+pub struct ScrollState {
+    pub offset: (f32, f32),
+    /// Extent of the children, relative to the container origin.
+    pub content_size: (f32, f32),
+    /// Size of the container itself.
+    pub viewport: (f32, f32),
+}
+
+impl Component for ScrollState {
+    type Storage = DenseVecStorage<Self>;
+}
+
+impl ScrollState {
+    pub fn max_offset(&self) -> (f32, f32) {
+        (
+            (self.content_size.0 - self.viewport.0).max(0.0),
+            (self.content_size.1 - self.viewport.1).max(0.0),
+        )
+    }
+
+    /// Keeps the offset within the scrollable range, e.g. after the content shrank.
+    pub fn clamp(&mut self) {
+        let (max_x, max_y) = self.max_offset();
+        self.offset = (
+            self.offset.0.max(0.0).min(max_x),
+            self.offset.1.max(0.0).min(max_y),
+        );
+    }
+
+    /// Scrolls by `delta` pixels and returns true if the offset changed.
+    pub fn scroll_by(&mut self, delta: (f32, f32)) -> bool {
+        let previous = self.offset;
+        self.offset = (self.offset.0 + delta.0, self.offset.1 + delta.1);
+        self.clamp();
+        previous != self.offset
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A user interface library: elements are entities, styled by CSS, laid out
+//! with flexbox and drawn with gfx. `UiApp` builds an application out of them.
+
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate matches;
+
+extern crate cgmath;
+#[macro_use]
+extern crate gfx;
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate gfx_device_gl;
+extern crate gfx_window_glutin;
+extern crate glutin;
+extern crate winit;
+// extern crate gfx_window_glfw;
+
+#[cfg(target_os = "windows")]
+extern crate gfx_device_dx11;
+#[cfg(target_os = "windows")]
+extern crate gfx_window_dxgi;
+
+mod gfx_app;
+mod shade;
+
+pub mod manager;
+
+pub mod rendering;
+pub mod transform;
+
+pub mod color;
+pub mod layout;
+pub mod style_system;
+pub mod styling;
+pub mod text;
+pub mod vfs;
+
+mod app;
+mod bundle;
+pub mod input;
+
+pub use app::{UiApp, WindowOptions};
+pub use bundle::UiBundle;
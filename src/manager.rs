@@ -1,11 +1,23 @@
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{self, Read};
+use std::marker::PhantomData;
 pub use warmy::{Load, Loaded, Storage};
 pub use warmy::{Store, StoreOpt};
 
 use std::fmt::Display;
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+
+use specs::shrev::EventChannel;
+
+use crate::shade;
+use crate::vfs::Vfs;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum SimpleKey {
@@ -26,10 +38,10 @@ impl SimpleKey {
 
 impl<'a> From<&'a Path> for SimpleKey {
     fn from(path: &Path) -> Self {
-        match path.extension().and_then(|x| x.to_str()) {
+        // editing any source of a shader set reloads the set
+        match shader_set_name(path) {
+            Some(name) => SimpleKey::Logical(name),
             None => SimpleKey::from_path(path),
-            Some("hlsl") => SimpleKey::Logical(path.to_path_buf()),
-            _ => SimpleKey::from_path(path),
         }
     }
 }
@@ -88,15 +100,82 @@ pub enum Error {
     CannotLoadFromLogical,
     IOError(io::Error),
     FontError(gfx_text::FontError),
+    FreetypeError(freetype::Error),
+    ImageError(image::ImageError),
+    ShaderSelect(shade::SelectError),
+    Shader(Vec<ShaderError>),
+    Store(warmy::StoreError<SimpleKey>),
+    /// A loaded resource the GPU did not take.
+    Upload(String),
+    /// The loading thread ended before preparing the resource.
+    LoaderStopped,
+    /// `cause` happened while loading `key`.
+    Load {
+        key: SimpleKey,
+        cause: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The error, as met while loading `key`.
+    pub fn context(self, key: &SimpleKey) -> Error {
+        match self {
+            Error::Load { .. } => self,
+            cause => Error::Load {
+                key: key.clone(),
+                cause: Box::new(cause),
+            },
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Error::CannotLoadFromFS => f.write_str("cannot load from file system"),
-            Error::CannotLoadFromLogical => f.write_str("cannot load from logical"),
+            Error::CannotLoadFromLogical => {
+                f.write_str("nothing registered under this logical key")
+            }
             Error::IOError(ref e) => write!(f, "IO error: {}", e),
-            ref e => f.write_fmt(format_args!("{}", e)),
+            Error::FontError(ref e) => write!(f, "font error: {:?}", e),
+            Error::FreetypeError(ref e) => write!(f, "freetype error: {}", e),
+            Error::ImageError(ref e) => write!(f, "image error: {}", e),
+            Error::ShaderSelect(ref e) => write!(f, "shader error: {}", e),
+            Error::Shader(ref errors) => {
+                for e in errors {
+                    writeln!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            Error::Store(ref e) => write!(f, "store error: {:?}", e),
+            Error::Upload(ref e) => write!(f, "cannot upload to the GPU: {}", e),
+            Error::LoaderStopped => f.write_str("the resource loader stopped"),
+            Error::Load { ref key, ref cause } => write!(f, "cannot load {}: {}", key, cause),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IOError(ref e) => Some(e),
+            Error::FreetypeError(ref e) => Some(e),
+            Error::ImageError(ref e) => Some(e),
+            Error::ShaderSelect(ref e) => Some(e),
+            Error::Load { ref cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<warmy::StoreErrorOr<T, Ctx, SimpleKey>> for Error
+where
+    T: Load<Ctx, SimpleKey, Error = Error>,
+{
+    fn from(e: warmy::StoreErrorOr<T, Ctx, SimpleKey>) -> Self {
+        match e {
+            warmy::StoreErrorOr::ResError(e) => e,
+            warmy::StoreErrorOr::StoreError(e) => Error::Store(e),
         }
     }
 }
@@ -105,23 +184,306 @@ impl fmt::Display for Error {
 #[derive(Debug, Clone)]
 pub struct FromFS {
     pub bytes: Vec<u8>,
-    pub version: u8,
+    /// Incremented by each reload, starting from 1.
+    pub generation: u64,
 }
 
 // The resource we want to compute from memory.
 #[derive(Debug)]
 pub struct ShaderSet {
-    pub version: u8,
+    /// Incremented by each reload, starting from 1.
+    pub generation: u64,
     pub vx: Vec<u8>,
     pub px: Vec<u8>,
+    /// The vertex and pixel source files, where errors of the driver point.
+    pub files: [PathBuf; 2],
+    /// Why the programs could not be built; empty when they were.
+    pub errors: Vec<ShaderError>,
 }
 
-pub struct Ctx {}
+impl ShaderSet {
+    pub fn new(vx: (PathBuf, Vec<u8>), px: (PathBuf, Vec<u8>)) -> Self {
+        ShaderSet {
+            generation: 1,
+            vx: vx.1,
+            px: px.1,
+            files: [vx.0, px.0],
+            errors: Vec::new(),
+        }
+    }
+
+    /// A set without programs, for the shaders of `name` that did not compile.
+    fn failed(name: &Path, error: Error) -> Self {
+        let errors = match error {
+            Error::Shader(errors) => errors,
+            e => vec![ShaderError {
+                file: name.to_owned(),
+                line: None,
+                message: e.to_string(),
+            }],
+        };
+        ShaderSet {
+            generation: 1,
+            vx: Vec::new(),
+            px: Vec::new(),
+            files: [name.to_owned(), name.to_owned()],
+            errors,
+        }
+    }
+}
+
+/// An error of a shader compiler, located in its source when the compiler
+/// said where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderError {
+    pub file: PathBuf,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl ShaderError {
+    /// The errors in the output of a compiler that read `file`. Lines are
+    /// located in the formats of fxc, `file(12,5): error X3000: ...`, of
+    /// mesa, `0:12(5): error: ...`, and of other drivers, `0(12) : error ...`
+    /// and `ERROR: 0:12: ...`.
+    pub fn parse_log(file: &Path, log: &str) -> Vec<ShaderError> {
+        log.lines()
+            .map(str::trim)
+            .filter(|line| line.to_lowercase().contains("error"))
+            .map(|line| {
+                let (line, message) = match Self::locate(line) {
+                    Some((n, message)) => (Some(n), message),
+                    None => (None, line),
+                };
+                ShaderError {
+                    file: file.to_owned(),
+                    line,
+                    message: message.to_owned(),
+                }
+            })
+            .collect()
+    }
+
+    fn locate(text: &str) -> Option<(u32, &str)> {
+        fn rest(s: &str) -> &str {
+            s.trim_start_matches(|c| c == ' ' || c == ':').trim()
+        }
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+        // `0:12`, at the start or after the severity
+        let colon = text
+            .match_indices("0:")
+            .map(|(at, _)| at)
+            .find(|at| *at == 0 || text[..*at].ends_with(' '));
+        if let Some(at) = colon {
+            let after = &text[at + 2..];
+            let end = digits(after);
+            if let Ok(line) = after[..end].parse() {
+                let mut after = &after[end..];
+                // mesa then gives the column
+                if after.starts_with('(') {
+                    after = &after[after.find(')').map_or(0, |close| close + 1)..];
+                }
+                return Some((line, rest(after)));
+            }
+        }
+
+        // `(12,5)` or `(12)` after the source name
+        let open = text.find('(')?;
+        let after = &text[open + 1..];
+        let end = digits(after);
+        let line = after[..end].parse().ok()?;
+        let close = after.find(')')?;
+        Some((line, rest(&after[close + 1..])))
+    }
+}
+
+/// Problems met while building resources, for the app to show. The log gets
+/// them as they change.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    /// Errors of the shader sets that failed, by name.
+    pub shaders: HashMap<PathBuf, Vec<ShaderError>>,
+    /// Resources that failed to load, with their error.
+    pub resources: HashMap<SimpleKey, String>,
+}
+
+impl Diagnostics {
+    /// Replaces the errors of the shader set `name`; no errors means it builds again.
+    pub fn report_shader(&mut self, name: &Path, errors: Vec<ShaderError>) {
+        if errors.is_empty() {
+            if self.shaders.remove(name).is_some() {
+                info!("shader set {} fixed", name.display());
+            }
+            return;
+        }
+        error!(
+            "shader set {} failed, the previous pipeline stays active",
+            name.display()
+        );
+        for e in errors.iter() {
+            error!("  {}", e);
+        }
+        self.shaders.insert(name.to_owned(), errors);
+    }
+
+    /// Records whether `key` loaded: a failure is logged when it changes, and
+    /// the recovery.
+    pub fn report_resource(&mut self, key: &SimpleKey, result: Result<(), &Error>) {
+        match result {
+            Ok(()) => {
+                if self.resources.remove(key).is_some() {
+                    info!("{} loaded", key);
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if self.resources.get(key) != Some(&message) {
+                    error!("{}", message);
+                    self.resources.insert(key.clone(), message);
+                }
+            }
+        }
+    }
+}
+
+pub struct Ctx {
+    shader_compiler: Arc<dyn ShaderCompiler>,
+    sources: Sources,
+    /// What the loading thread prepared for the resource being loaded.
+    prepared: Option<Box<dyn Any + Send>>,
+    /// The resources reloaded by `ResourceManager::sync`.
+    reloaded: Vec<ResourceReloaded>,
+}
 
 impl Ctx {
-    pub fn new() -> Self {
-        Ctx {}
+    pub fn new(shader_compiler: Arc<dyn ShaderCompiler>, sources: Sources) -> Self {
+        Ctx {
+            shader_compiler,
+            sources,
+            prepared: None,
+            reloaded: Vec::new(),
+        }
+    }
+
+    /// Records that `key` was reloaded, for `ResourceManager::sync` to publish.
+    /// Every `reload` calls it once it succeeded.
+    pub fn reloaded<T: 'static>(&mut self, key: SimpleKey) {
+        self.reloaded.push(ResourceReloaded {
+            key,
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+        });
     }
+
+    /// Where the bytes of resources are read.
+    pub fn sources(&self) -> &Sources {
+        &self.sources
+    }
+
+    /// The prepared part of the resource being loaded, when it was requested
+    /// with `ResourceManager::get_async`.
+    pub fn take_prepared<P: 'static>(&mut self) -> Option<P> {
+        self.prepared
+            .take()
+            .and_then(|prepared| prepared.downcast::<P>().ok())
+            .map(|prepared| *prepared)
+    }
+}
+
+/// Where loaders read the bytes of resources: the VFS for `Path` keys, and the
+/// bytes registered in memory for `Logical` keys, such as generated pictures.
+#[derive(Clone)]
+pub struct Sources {
+    /// The root of the store, which keys are prepared against.
+    root: PathBuf,
+    vfs: Arc<RwLock<Vfs>>,
+    memory: Arc<RwLock<HashMap<PathBuf, Cow<'static, [u8]>>>>,
+}
+
+impl Sources {
+    /// The VFS starts with the root of the store mounted at its root.
+    fn new(root: &Path) -> Self {
+        let mut vfs = Vfs::default();
+        vfs.mount_dir("", root);
+        Sources {
+            root: root.to_owned(),
+            vfs: Arc::new(RwLock::new(vfs)),
+            memory: Arc::default(),
+        }
+    }
+
+    fn insert(&self, name: &Path, bytes: Cow<'static, [u8]>) {
+        // named like the keys once prepared by the store
+        let name = vfs_substitute_path(name, &self.root);
+        self.memory.write().unwrap().insert(name, bytes);
+    }
+
+    pub fn get(&self, name: &Path) -> Option<Cow<'static, [u8]>> {
+        self.memory.read().unwrap().get(name).cloned()
+    }
+
+    /// The file `path` of a prepared key, from the VFS when it is under the
+    /// root of the store.
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        match path.strip_prefix(&self.root) {
+            Ok(path) => self.vfs.read().unwrap().read(path),
+            Err(_) => std::fs::read(path),
+        }
+        .map_err(Error::IOError)
+    }
+
+    /// The bytes of `key`: the file of a `Path` key, the registered bytes of a
+    /// `Logical` one.
+    pub fn read(&self, key: &SimpleKey) -> Result<Cow<'static, [u8]>, Error> {
+        match key {
+            SimpleKey::Path(path) => self.read_file(path).map(Cow::Owned),
+            SimpleKey::Logical(name) => self.get(name).ok_or(Error::CannotLoadFromLogical),
+        }
+    }
+}
+
+/// Published in the `EventChannel<ResourceReloaded>` resource when a
+/// resource changed on disk and was loaded again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceReloaded {
+    pub key: SimpleKey,
+    pub type_id: TypeId,
+    /// The name of the type, for the logs.
+    pub type_name: &'static str,
+}
+
+impl ResourceReloaded {
+    /// Whether the reloaded resource is a `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+}
+
+impl Display for ResourceReloaded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{} ({})", self.key, self.type_name)
+    }
+}
+
+/// A resource whose slow part, such as decoding a file, can run on the
+/// loading thread. Its `load` finishes the work on the calling thread, from
+/// `Ctx::take_prepared`, or prepares it there when nothing was.
+pub trait Prepare:
+    Load<Ctx, SimpleKey, Error = Error> + Load<Ctx, SimpleKey, AlwaysFail> + 'static
+{
+    type Prepared: Send + 'static;
+
+    fn prepare(key: &SimpleKey, ctx: &Ctx) -> Result<Self::Prepared, Error>;
 }
 
 impl Load<Ctx, SimpleKey> for FromFS {
@@ -130,27 +492,21 @@ impl Load<Ctx, SimpleKey> for FromFS {
     fn load(
         key: SimpleKey,
         _storage: &mut Storage<Ctx, SimpleKey>,
-        _ctx: &mut Ctx,
+        ctx: &mut Ctx,
     ) -> Result<Loaded<Self, SimpleKey>, Self::Error> {
-        // as we only accept filesystem here, we’ll ensure the key is a filesystem one
         match key {
-            SimpleKey::Path(path) => {
-                println!("Load Physical {}", path.display());
-                let mut fh = File::open(path).map_err(Error::IOError)?;
-                let mut buf = Vec::default();
-                fh.read_to_end(&mut buf).expect("Load failed");
-                // storage.get::<ShaderSet>(&dep, ctx).unwrap();
-                Ok(Loaded::without_dep(
-                    FromFS {
-                        bytes: buf,
-                        version: 1,
-                    }
-                    .into(),
-                ))
-            }
-
-            SimpleKey::Logical(_) => Err(Error::CannotLoadFromLogical),
+            SimpleKey::Path(ref path) => debug!("load physical {}", path.display()),
+            SimpleKey::Logical(ref name) => debug!("load logical {}", name.display()),
         }
+        let bytes = ctx.sources().read(&key)?.into_owned();
+        // storage.get::<ShaderSet>(&dep, ctx).unwrap();
+        Ok(Loaded::without_dep(
+            FromFS {
+                bytes,
+                generation: 1,
+            }
+            .into(),
+        ))
     }
     fn reload(
         &self,
@@ -159,18 +515,21 @@ impl Load<Ctx, SimpleKey> for FromFS {
         ctx: &mut Ctx,
     ) -> Result<Self, Error> {
         let prev = storage.get_by::<FromFS, AlwaysFail>(&key, ctx, AlwaysFail);
-        let prev_version = prev.map(|x| x.borrow().version).unwrap_or(0);
+        let prev_generation = prev.map(|x| x.borrow().generation).unwrap_or(0);
         let l: Result<Loaded<Self, SimpleKey>, Error> =
-            <FromFS as warmy::load::Load<Ctx, SimpleKey, ()>>::load(key, storage, ctx);
+            <FromFS as warmy::load::Load<Ctx, SimpleKey, ()>>::load(key.clone(), storage, ctx);
         l.map(|mut lr| {
-            lr.res.version = prev_version + 1;
-            println!("  new version {}", lr.res.version);
+            lr.res.generation = prev_generation + 1;
+            debug!("  new generation {}", lr.res.generation);
+            ctx.reloaded::<Self>(key);
             lr.res
         })
     }
 }
 
-struct AlwaysFail;
+/// Loading method used in `reload` to fetch the previous version of a resource
+/// without loading it again.
+pub struct AlwaysFail;
 impl Load<Ctx, SimpleKey, AlwaysFail> for FromFS {
     type Error = Error;
 
@@ -220,12 +579,19 @@ fn compile(shader_type: ShaderType, path: &Path) -> Result<Vec<u8>, Error> {
 
     let fxc = "C:\\Program Files (x86)\\Windows Kits\\10\\bin\\x64\\fxc.exe";
     let output_path = get_output_path(&shader_type, path);
-    println!(
-        "  output path for {:?} {}: {}",
+    debug!(
+        "output path for {:?} {}: {}",
         shader_type,
         path.display(),
         output_path.display()
     );
+    // fxc takes its paths as arguments, which must be valid UTF-8
+    let utf8 = |p: &Path| {
+        p.to_str()
+            .map(str::to_owned)
+            .ok_or_else(|| Error::CannotLoadFromFS.context(&SimpleKey::Path(p.to_owned())))
+    };
+    let (output_arg, input_arg) = (utf8(&output_path)?, utf8(path)?);
     let args = match shader_type {
         ShaderType::Vertex => [
             "-nologo",
@@ -234,8 +600,8 @@ fn compile(shader_type: ShaderType, path: &Path) -> Result<Vec<u8>, Error> {
             "/E",
             "Vertex",
             "/Fo",
-            output_path.to_str().unwrap(),
-            path.to_str().unwrap(),
+            output_arg.as_str(),
+            input_arg.as_str(),
         ],
         ShaderType::Pixel => [
             "-nologo",
@@ -244,23 +610,34 @@ fn compile(shader_type: ShaderType, path: &Path) -> Result<Vec<u8>, Error> {
             "/E",
             "Pixel",
             "/Fo",
-            output_path.to_str().unwrap(),
-            path.to_str().unwrap(),
+            output_arg.as_str(),
+            input_arg.as_str(),
         ],
     };
 
     let mut cmd = Command::new(fxc);
     let cmd = cmd.args(&args);
-    // let output = cmd
-    //   .output()
-    //   .map_err(Error::IOError)?;
-
-    let status = cmd.status().map_err(Error::IOError)?;
+    let output = cmd.output().map_err(Error::IOError)?;
 
-    println!("Shader compilation status: {}", status);
+    debug!("shader compilation status: {}", output.status);
 
-    // io::stdout().write_all(&output.stdout).map_err(Error::IOError)?;
-    // io::stderr().write_all(&output.stderr).map_err(Error::IOError)?;
+    // the output file of a failed compilation is the one of the last success
+    if !output.status.success() {
+        let log = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stderr),
+            String::from_utf8_lossy(&output.stdout)
+        );
+        let mut errors = ShaderError::parse_log(path, &log);
+        if errors.is_empty() {
+            errors.push(ShaderError {
+                file: path.to_owned(),
+                line: None,
+                message: format!("fxc exited with {}", output.status),
+            });
+        }
+        return Err(Error::Shader(errors));
+    }
 
     let mut fh = File::open(output_path).map_err(Error::IOError)?;
     let mut vx = Vec::default();
@@ -268,30 +645,128 @@ fn compile(shader_type: ShaderType, path: &Path) -> Result<Vec<u8>, Error> {
     Ok(vx)
 }
 
+/// Builds the programs of a `ShaderSet` for the backend of the device.
+pub trait ShaderCompiler: Send + Sync {
+    /// The vertex and pixel programs of the set `name`, a path without
+    /// extension such as `shader/cube`, reading its files from `sources`.
+    fn compile(&self, name: &Path, sources: &Sources) -> Result<ShaderSet, Error>;
+}
+
+/// Compiles `<name>.hlsl` with fxc, from the Windows SDK.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub struct Fxc;
+
+/// fxc only reads files: sources out of the store root directory, in packs
+/// or in memory, are ignored.
+impl ShaderCompiler for Fxc {
+    fn compile(&self, name: &Path, _sources: &Sources) -> Result<ShaderSet, Error> {
+        let path = name.with_extension("hlsl");
+        let vx = compile(ShaderType::Vertex, &path)?;
+        let px = compile(ShaderType::Pixel, &path)?;
+        Ok(ShaderSet::new((path.clone(), vx), (path, px)))
+    }
+}
+
+/// Suffixes of the GLSL variants of a shader set, as in `cube_150_core.glslv`,
+/// in the order of `GlslSources`.
+const GLSL_VARIANTS: [&str; 9] = [
+    "120", "130", "140", "150_core", "400_core", "430_core", "100_es", "200_es", "300_es",
+];
+
+/// Reads `<name>_<version>.glslv` and `.glslf` and keeps, for each stage, the
+/// highest version the backend supports.
+pub struct Glsl(pub shade::Backend);
+
+impl Glsl {
+    fn select(
+        &self,
+        name: &Path,
+        extension: &str,
+        sources: &Sources,
+    ) -> Result<(PathBuf, Vec<u8>), Error> {
+        let files: Vec<PathBuf> = GLSL_VARIANTS
+            .iter()
+            .map(|variant| {
+                let mut file = name.as_os_str().to_owned();
+                file.push(format!("_{}.{}", variant, extension));
+                file.into()
+            })
+            .collect();
+        let contents: Vec<Vec<u8>> = files
+            .iter()
+            .map(|file| sources.read_file(file).unwrap_or_default())
+            .collect();
+        let source = shade::Source {
+            glsl_120: &contents[0],
+            glsl_130: &contents[1],
+            glsl_140: &contents[2],
+            glsl_150: &contents[3],
+            glsl_400: &contents[4],
+            glsl_430: &contents[5],
+            glsl_es_100: &contents[6],
+            glsl_es_200: &contents[7],
+            glsl_es_300: &contents[8],
+            ..shade::Source::empty()
+        };
+        let selected = source.select(self.0).map_err(Error::ShaderSelect)?;
+        let index = contents
+            .iter()
+            .position(|s| std::ptr::eq(s.as_slice(), selected))
+            .unwrap_or(0);
+        Ok((files[index].clone(), selected.to_vec()))
+    }
+}
+
+impl ShaderCompiler for Glsl {
+    fn compile(&self, name: &Path, sources: &Sources) -> Result<ShaderSet, Error> {
+        Ok(ShaderSet::new(
+            self.select(name, "glslv", sources)?,
+            self.select(name, "glslf", sources)?,
+        ))
+    }
+}
+
+/// The compiler producing programs for `backend`.
+pub fn shader_compiler(backend: shade::Backend) -> Arc<dyn ShaderCompiler> {
+    match backend {
+        #[cfg(target_os = "windows")]
+        shade::Backend::Hlsl(_) => Arc::new(Fxc),
+        backend => Arc::new(Glsl(backend)),
+    }
+}
+
+/// The shader set a source file belongs to: `shader/cube` for both
+/// `shader/cube.hlsl` and `shader/cube_150_core.glslv`.
+fn shader_set_name(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let name = match path.extension()?.to_str()? {
+        "hlsl" => stem,
+        "glslv" | "glslf" => GLSL_VARIANTS.iter().find_map(|variant| {
+            let suffix = format!("_{}", variant);
+            if stem.ends_with(&suffix) {
+                Some(&stem[..stem.len() - suffix.len()])
+            } else {
+                None
+            }
+        })?,
+        _ => return None,
+    };
+    Some(path.with_file_name(name))
+}
+
 impl Load<Ctx, SimpleKey> for ShaderSet {
     type Error = Error;
 
     fn load(
         key: SimpleKey,
         _storage: &mut Storage<Ctx, SimpleKey>,
-        _ctx: &mut Ctx,
+        ctx: &mut Ctx,
     ) -> Result<Loaded<Self, SimpleKey>, Error> {
-        match key {
-            SimpleKey::Logical(key) => {
-                println!("Load logical {}", key.display());
-
-                let vx = compile(ShaderType::Vertex, &key)?;
-                let px = compile(ShaderType::Pixel, &key)?;
-
-                Ok(Loaded::without_dep(ShaderSet {
-                    version: 1,
-                    vx: vx,
-                    px: px,
-                }))
-            }
-
-            SimpleKey::Path(_) => Err(Error::CannotLoadFromFS),
-        }
+        let set = match ctx.take_prepared::<ShaderSet>() {
+            Some(set) => set,
+            None => Self::prepare(&key, ctx)?,
+        };
+        Ok(Loaded::without_dep(set))
     }
 
     fn reload(
@@ -300,47 +775,396 @@ impl Load<Ctx, SimpleKey> for ShaderSet {
         storage: &mut Storage<Ctx, SimpleKey>,
         ctx: &mut Ctx,
     ) -> Result<Self, Error> {
-        println!("reload shader set");
+        debug!("reload shader set {}", key);
         let prev = storage.get_by::<ShaderSet, AlwaysFail>(&key, ctx, AlwaysFail);
-        let prev_version = prev.map(|x| x.borrow().version).unwrap_or(0);
+        let prev_generation = prev.map(|x| x.borrow().generation).unwrap_or(0);
         let l: Result<Loaded<Self, SimpleKey>, Error> =
-            <ShaderSet as warmy::load::Load<Ctx, SimpleKey, ()>>::load(key, storage, ctx);
+            <ShaderSet as warmy::load::Load<Ctx, SimpleKey, ()>>::load(key.clone(), storage, ctx);
         l.map(|mut lr| {
-            lr.res.version = prev_version + 1;
-            println!("  new version {}", lr.res.version);
+            lr.res.generation = prev_generation + 1;
+            debug!("  new generation {}", lr.res.generation);
+            ctx.reloaded::<Self>(key);
             lr.res
         })
     }
 }
 
+/// Compiling is the slow part: it runs on the loading thread.
+impl Prepare for ShaderSet {
+    type Prepared = ShaderSet;
+
+    fn prepare(key: &SimpleKey, ctx: &Ctx) -> Result<ShaderSet, Error> {
+        match key {
+            SimpleKey::Logical(key) => {
+                debug!("load logical {}", key.display());
+
+                // a set that failed stays loaded, to be reloaded once fixed
+                Ok(ctx
+                    .shader_compiler
+                    .compile(key, &ctx.sources)
+                    .unwrap_or_else(|e| ShaderSet::failed(key, e)))
+            }
+
+            SimpleKey::Path(_) => Err(Error::CannotLoadFromFS),
+        }
+    }
+}
+
 use std::cell::Cell;
 use std::sync::Mutex;
-struct StoreImpl(pub Store<Ctx, SimpleKey>);
-pub struct ResourceManager(Mutex<Cell<StoreImpl>>);
+
+/// A resource of type `T` registered with `ResourceManager::handle`. It is
+/// resolved without hashing its key, and stays valid across reloads.
+pub struct Handle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+/// The key of a handle, and its resource once loaded: warmy reloads it in
+/// place, so the `Res` is kept for good.
+struct Slot {
+    key: SimpleKey,
+}
+
+/// The keys of the handles, locked apart from the store: making a handle
+/// does not wait for a load.
+#[derive(Default)]
+struct Slots {
+    slots: Vec<Slot>,
+    handles: HashMap<(TypeId, SimpleKey), usize>,
+}
+
+struct StoreImpl {
+    store: Store<Ctx, SimpleKey>,
+    /// The resources of the loaded handles, by index. `warmy::Res` counts its
+    /// references without atomics: they are only cloned under the store lock.
+    resolved: Vec<Option<Box<dyn Any>>>,
+}
+
+impl StoreImpl {
+    fn resolved<T: 'static>(&self, handle: Handle<T>) -> Option<warmy::Res<T>> {
+        self.resolved
+            .get(handle.index)?
+            .as_ref()?
+            .downcast_ref::<warmy::Res<T>>()
+            .cloned()
+    }
+
+    fn set_resolved<T: 'static>(&mut self, handle: Handle<T>, res: &warmy::Res<T>) {
+        if self.resolved.len() <= handle.index {
+            self.resolved.resize_with(handle.index + 1, || None);
+        }
+        self.resolved[handle.index] = Some(Box::new(res.clone()));
+    }
+}
+
+type Prepared = Result<Box<dyn Any + Send>, Error>;
+type Job = Box<dyn FnOnce() -> Prepared + Send>;
+
+/// The thread preparing the resources requested with `get_async`.
+struct Loader {
+    jobs: Mutex<mpsc::Sender<(SimpleKey, Job)>>,
+    /// Sent to the thread, not yet taken from `done`.
+    pending: Mutex<HashSet<SimpleKey>>,
+    done: Arc<Mutex<HashMap<SimpleKey, Prepared>>>,
+    /// Cleared when the thread ends, by a panic too: its pending keys never
+    /// get to `done`.
+    alive: Arc<AtomicBool>,
+}
+
+/// Clears `Loader::alive` when dropped by the loading thread.
+struct AliveGuard(Arc<AtomicBool>);
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Loader {
+    fn new() -> Self {
+        let (jobs, receiver) = mpsc::channel::<(SimpleKey, Job)>();
+        let done = Arc::new(Mutex::new(HashMap::new()));
+        let finished = done.clone();
+        let alive = Arc::new(AtomicBool::new(true));
+        let guard = AliveGuard(alive.clone());
+        std::thread::Builder::new()
+            .name("resource loader".into())
+            .spawn(move || {
+                let _guard = guard;
+                for (key, job) in receiver {
+                    let prepared = job();
+                    finished.lock().unwrap().insert(key, prepared);
+                }
+            })
+            .expect("loader thread creation");
+        Loader {
+            jobs: Mutex::new(jobs),
+            pending: Mutex::new(HashSet::new()),
+            done,
+            alive,
+        }
+    }
+}
+
+/// The resource store, the compiler its shader sets are built with and the
+/// thread loading resources in the background.
+pub struct ResourceManager {
+    store: Mutex<Cell<StoreImpl>>,
+    slots: Mutex<Slots>,
+    shader_compiler: Arc<dyn ShaderCompiler>,
+    sources: Sources,
+    loader: Loader,
+}
 
 impl ResourceManager {
-    pub fn new() -> Self {
-        ResourceManager(Mutex::new(Cell::new(StoreImpl(
-            Store::new(StoreOpt::default()).expect("store creation"),
-        ))))
+    pub fn new(shader_compiler: Arc<dyn ShaderCompiler>) -> Self {
+        let store = Store::new(StoreOpt::default()).expect("store creation");
+        let sources = Sources::new(store.root());
+        ResourceManager {
+            store: Mutex::new(Cell::new(StoreImpl {
+                store,
+                resolved: Vec::new(),
+            })),
+            slots: Mutex::new(Slots::default()),
+            shader_compiler,
+            sources,
+            loader: Loader::new(),
+        }
     }
 
-    pub fn get<T>(
+    fn ctx(&self) -> Ctx {
+        Ctx::new(self.shader_compiler.clone(), self.sources.clone())
+    }
+
+    /// Registers `bytes` under the logical key `name`, for instance a
+    /// generated picture. The bytes are read when the resource is first
+    /// loaded.
+    pub fn insert<P, B>(&self, name: P, bytes: B)
+    where
+        P: AsRef<Path>,
+        B: Into<Cow<'static, [u8]>>,
+    {
+        self.sources.insert(name.as_ref(), bytes.into());
+    }
+
+    /// The bytes of `k`, as loaders read them.
+    pub fn read(&self, k: &SimpleKey) -> Result<Cow<'static, [u8]>, Error> {
+        let prepared = warmy::Key::prepare_key(k.clone(), &self.sources.root);
+        self.sources.read(&prepared).map_err(|e| e.context(k))
+    }
+
+    /// Mounts the directory `dir` at the virtual path `at`, over the previous
+    /// mounts. Only the files of the store root directory are hot reloaded.
+    pub fn mount_dir<A, D>(&self, at: A, dir: D)
+    where
+        A: Into<PathBuf>,
+        D: Into<PathBuf>,
+    {
+        self.sources.vfs.write().unwrap().mount_dir(at, dir);
+    }
+
+    /// Mounts the pack `file`, written by the `packer` binary, at the virtual
+    /// path `at`, over the previous mounts.
+    pub fn mount_pack<A, P>(&self, at: A, file: P) -> io::Result<()>
+    where
+        A: Into<PathBuf>,
+        P: AsRef<Path>,
+    {
+        self.sources.vfs.write().unwrap().mount_pack(at, file)
+    }
+
+    /// Mounts `files` at the virtual path `at`, over the previous mounts.
+    pub fn mount_memory<A, I>(&self, at: A, files: I)
+    where
+        A: Into<PathBuf>,
+        I: IntoIterator<Item = (PathBuf, Cow<'static, [u8]>)>,
+    {
+        self.sources.vfs.write().unwrap().mount_memory(at, files);
+    }
+
+    pub fn get<T>(&self, k: &SimpleKey) -> Result<warmy::Res<T>, Error>
+    where
+        T: Load<Ctx, SimpleKey, (), Error = Error>,
+    {
+        let mut ctx = self.ctx();
+
+        self.store
+            .lock()
+            .unwrap()
+            .get_mut()
+            .store
+            .get(k, &mut ctx)
+            .map_err(|e| Error::from(e).context(k))
+    }
+
+    /// Like `get`, without blocking: the first request sends the slow part of
+    /// the loading to the loading thread, and `Ok(None)` is returned until it
+    /// is done. Callers show a placeholder meanwhile.
+    pub fn get_async<T>(&self, k: &SimpleKey) -> Result<Option<warmy::Res<T>>, Error>
+    where
+        T: Prepare,
+    {
+        let mut store = self.store.lock().unwrap();
+        self.get_async_in(&mut store.get_mut().store, k)
+    }
+
+    fn get_async_in<T>(
         &self,
+        store: &mut Store<Ctx, SimpleKey>,
         k: &SimpleKey,
-    ) -> Result<warmy::Res<T>, warmy::StoreErrorOr<T, Ctx, SimpleKey>>
+    ) -> Result<Option<warmy::Res<T>>, Error>
+    where
+        T: Prepare,
+    {
+        let mut ctx = self.ctx();
+        if let Ok(res) = store.get_by(k, &mut ctx, AlwaysFail) {
+            return Ok(Some(res));
+        }
+
+        // read first: what the thread prepared before it ended is in `done`
+        let alive = self.loader.alive.load(Ordering::SeqCst);
+        let prepared = self.loader.done.lock().unwrap().remove(k);
+        match prepared {
+            Some(Ok(prepared)) => {
+                self.loader.pending.lock().unwrap().remove(k);
+                ctx.prepared = Some(prepared);
+                store
+                    .get(k, &mut ctx)
+                    .map(Some)
+                    .map_err(|e| Error::from(e).context(k))
+            }
+            Some(Err(e)) => {
+                // the next request tries again, as `get` does
+                self.loader.pending.lock().unwrap().remove(k);
+                Err(e.context(k))
+            }
+            None if !alive => {
+                self.loader.pending.lock().unwrap().remove(k);
+                Err(Error::LoaderStopped.context(k))
+            }
+            None => {
+                if self.loader.pending.lock().unwrap().insert(k.clone()) {
+                    // loaders are given prepared keys, as `get` does
+                    let key = warmy::Key::prepare_key(k.clone(), store.root());
+                    let ctx = self.ctx();
+                    let job: Job = Box::new(move || {
+                        T::prepare(&key, &ctx).map(|p| Box::new(p) as Box<dyn Any + Send>)
+                    });
+                    if self
+                        .loader
+                        .jobs
+                        .lock()
+                        .unwrap()
+                        .send((k.clone(), job))
+                        .is_err()
+                    {
+                        self.loader.pending.lock().unwrap().remove(k);
+                        return Err(Error::LoaderStopped.context(k));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// The handle of the resource `k` of type `T`, the same for every call.
+    /// Nothing is loaded until it is resolved.
+    pub fn handle<T: 'static>(&self, k: &SimpleKey) -> Handle<T> {
+        let mut slots = self.slots.lock().unwrap();
+        let Slots { slots, handles } = &mut *slots;
+        let index = *handles
+            .entry((TypeId::of::<T>(), k.clone()))
+            .or_insert_with(|| {
+                slots.push(Slot { key: k.clone() });
+                slots.len() - 1
+            });
+        Handle {
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The key `handle` was created with.
+    pub fn key<T>(&self, handle: Handle<T>) -> SimpleKey {
+        self.slots.lock().unwrap().slots[handle.index].key.clone()
+    }
+
+    /// Like `get`, the key of `handle` is only looked up until it is loaded.
+    pub fn resolve<T>(&self, handle: Handle<T>) -> Result<warmy::Res<T>, Error>
     where
-        T: Load<Ctx, SimpleKey, ()>,
+        T: Load<Ctx, SimpleKey, (), Error = Error>,
     {
-        let mut ctx = Ctx::new();
+        let mut store = self.store.lock().unwrap();
+        let store = store.get_mut();
+        if let Some(res) = store.resolved(handle) {
+            return Ok(res);
+        }
+        let key = self.key(handle);
+        let res = store
+            .store
+            .get(&key, &mut self.ctx())
+            .map_err(|e| Error::from(e).context(&key))?;
+        store.set_resolved(handle, &res);
+        Ok(res)
+    }
 
-        self.0.lock().unwrap().get_mut().0.get(k, &mut ctx)
+    /// Like `get_async`, the key of `handle` is only looked up until it is
+    /// loaded.
+    pub fn resolve_async<T>(&self, handle: Handle<T>) -> Result<Option<warmy::Res<T>>, Error>
+    where
+        T: Prepare,
+    {
+        let mut store = self.store.lock().unwrap();
+        let store = store.get_mut();
+        if let Some(res) = store.resolved(handle) {
+            return Ok(Some(res));
+        }
+        let key = self.key(handle);
+        let res = self.get_async_in::<T>(&mut store.store, &key)?;
+        if let Some(ref res) = res {
+            store.set_resolved(handle, res);
+        }
+        Ok(res)
     }
 
-    pub fn sync(&self) {
-        let mut ctx = Ctx::new();
+    /// Reloads the resources changed on disk, and publishes them in `events`.
+    pub fn sync(&self, events: &mut EventChannel<ResourceReloaded>) {
+        let mut ctx = self.ctx();
 
-        self.0.lock().unwrap().get_mut().0.sync(&mut ctx)
+        self.store.lock().unwrap().get_mut().store.sync(&mut ctx);
+        for reloaded in &ctx.reloaded {
+            info!("reloaded {}", reloaded);
+        }
+        events.drain_vec_write(&mut ctx.reloaded);
     }
 }
 // impl  std::ops::Deref for ResourceManager {
@@ -356,3 +1180,159 @@ impl ResourceManager {
 // }
 
 unsafe impl Send for StoreImpl {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_sources_reload_their_set() {
+        let set = SimpleKey::Logical("shader/cube".into());
+        assert_eq!(SimpleKey::from(Path::new("shader/cube.hlsl")), set);
+        assert_eq!(
+            SimpleKey::from(Path::new("shader/cube_150_core.glslv")),
+            set
+        );
+        assert_eq!(SimpleKey::from(Path::new("shader/cube_300_es.glslf")), set);
+        assert_eq!(
+            SimpleKey::from(Path::new("style/test.css")),
+            SimpleKey::Path("style/test.css".into())
+        );
+    }
+
+    #[test]
+    fn locate_compiler_errors() {
+        let file = Path::new("shader/cube.hlsl");
+        let log = "C:\\dodui\\shader\\cube.hlsl(12,5-9): error X3004: undeclared identifier 'foo'
+compilation failed; no code produced
+0:7(3): error: `bar' undeclared
+0(9) : error C1008: undefined variable \"baz\"
+ERROR: 0:3: 'qux' : syntax error";
+        let located: Vec<(Option<u32>, String)> = ShaderError::parse_log(file, log)
+            .into_iter()
+            .map(|e| (e.line, e.message))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (
+                    Some(12),
+                    "error X3004: undeclared identifier 'foo'".to_owned()
+                ),
+                (Some(7), "error: `bar' undeclared".to_owned()),
+                (
+                    Some(9),
+                    "error C1008: undefined variable \"baz\"".to_owned()
+                ),
+                (Some(3), "'qux' : syntax error".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_are_per_key_and_type() {
+        let manager = ResourceManager::new(Arc::new(Fxc));
+        let key = SimpleKey::from_path("style/style.css");
+        let a = manager.handle::<FromFS>(&key);
+        assert_eq!(manager.handle::<FromFS>(&key), a);
+        assert_ne!(manager.handle::<ShaderSet>(&key).index, a.index);
+        assert_ne!(
+            manager.handle::<FromFS>(&SimpleKey::from_path("style/test.css")),
+            a
+        );
+        assert_eq!(manager.key(a), key);
+    }
+
+    #[test]
+    fn logical_keys_load_registered_bytes() {
+        let manager = ResourceManager::new(Arc::new(Fxc));
+        manager.insert("generated/hello", &b"hello"[..]);
+        let key = SimpleKey::Logical("generated/hello".into());
+        let res = manager.get::<FromFS>(&key).unwrap();
+        assert_eq!(res.borrow().bytes, b"hello");
+        let missing = SimpleKey::Logical("generated/missing".into());
+        assert_eq!(
+            manager.get::<FromFS>(&missing).unwrap_err().to_string(),
+            "cannot load generated/missing: nothing registered under this logical key"
+        );
+    }
+
+    #[test]
+    fn loaded_handles_resolve_from_other_threads() {
+        fn send<T: Send>() {}
+        // the handles hold no resource, only their keys
+        send::<Slots>();
+
+        let manager = Arc::new(ResourceManager::new(Arc::new(Fxc)));
+        manager.insert("generated/hello", &b"hello"[..]);
+        let handle = manager.handle::<FromFS>(&SimpleKey::Logical("generated/hello".into()));
+        manager.resolve(handle).unwrap();
+
+        let (done, resolved) = mpsc::channel();
+        let resolver = manager.clone();
+        std::thread::spawn(move || {
+            let bytes = resolver
+                .resolve(handle)
+                .map(|res| res.borrow().bytes.clone());
+            let _ = done.send(bytes.ok());
+        });
+        let resolved = resolved.recv_timeout(std::time::Duration::from_secs(5));
+        assert_eq!(resolved, Ok(Some(b"hello".to_vec())));
+    }
+
+    /// A resource whose preparation panics, ending the loading thread.
+    struct Panics;
+
+    impl Load<Ctx, SimpleKey> for Panics {
+        type Error = Error;
+
+        fn load(
+            _key: SimpleKey,
+            _storage: &mut Storage<Ctx, SimpleKey>,
+            _ctx: &mut Ctx,
+        ) -> Result<Loaded<Self, SimpleKey>, Error> {
+            Ok(Loaded::without_dep(Panics))
+        }
+    }
+
+    impl Load<Ctx, SimpleKey, AlwaysFail> for Panics {
+        type Error = Error;
+
+        fn load(
+            _key: SimpleKey,
+            _storage: &mut Storage<Ctx, SimpleKey>,
+            _ctx: &mut Ctx,
+        ) -> Result<Loaded<Self, SimpleKey>, Error> {
+            Err(Error::CannotLoadFromFS)
+        }
+    }
+
+    impl Prepare for Panics {
+        type Prepared = ();
+
+        fn prepare(_key: &SimpleKey, _ctx: &Ctx) -> Result<(), Error> {
+            panic!("preparing fails");
+        }
+    }
+
+    #[test]
+    fn pending_loads_fail_once_the_loader_stopped() {
+        let manager = ResourceManager::new(Arc::new(Fxc));
+        let key = SimpleKey::Logical("generated/panics".into());
+        let mut tries = 0;
+        let result = loop {
+            match manager.get_async::<Panics>(&key) {
+                Ok(None) if tries < 500 => tries += 1,
+                result => break result,
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!(
+            result.err().map(|e| e.to_string()).unwrap(),
+            "cannot load generated/panics: the resource loader stopped"
+        );
+        // later requests fail too, instead of waiting forever
+        let set = SimpleKey::Logical("shader/cube".into());
+        assert!(manager.get_async::<ShaderSet>(&set).is_err());
+    }
+}
@@ -0,0 +1,762 @@
+use cgmath::Matrix4;
+use specs::prelude::*;
+use stretch::style::{Dimension, Overflow};
+
+use crate::layout::{BitmapFont, Dimensions, ScrollState};
+use crate::rendering::{BackgroundImage, Image, ImageFit, Material, Screen};
+use crate::style_system::{StyleBorder, StyleOutline, StyleScrollbar, StyleShadow, StyleStacking};
+use crate::styling::BorderStyle;
+use crate::text::{ShapedGlyph, ShapedText};
+use crate::transform::{GlobalTransform, Parent, ParentHierarchy};
+
+/// An axis aligned rectangle in screen pixels, used as scissor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    /// The rotated or skewed box the rectangle bounds, clipped exactly by
+    /// the quad shader.
+    pub shape: Option<ClipShape>,
+}
+
+/// A transformed box clipping the items under it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipShape {
+    /// From screen pixels to the pixels of the box.
+    pub inverse: Matrix4<f32>,
+    pub size: (f32, f32),
+}
+
+/// The clip of a quad drawn with `transform`, in its pixels: left, top, right
+/// and bottom. Scissor rects clip the axis aligned boxes, so it only bounds
+/// shapes; it is exact when the quad is translated or scaled relative to the
+/// shape, as the descendants of a rotated box are.
+pub fn local_clip(transform: &Matrix4<f32>, clip: Option<ClipRect>) -> [f32; 4] {
+    use cgmath::SquareMatrix;
+
+    const UNCLIPPED: [f32; 4] = [-1e9, -1e9, 1e9, 1e9];
+    let shape = match clip.and_then(|clip| clip.shape) {
+        Some(shape) => shape,
+        None => return UNCLIPPED,
+    };
+    match (shape.inverse * transform).invert() {
+        Some(to_quad) => {
+            let bounds = ClipRect::from_transform(&to_quad, shape.size);
+            [bounds.x, bounds.y, bounds.x + bounds.w, bounds.y + bounds.h]
+        }
+        None => UNCLIPPED,
+    }
+}
+
+/// How much of the pixel at `local` a `local_clip` keeps, as the quad shader
+/// computes it.
+pub fn clip_coverage(local: (f32, f32), clip: [f32; 4]) -> f32 {
+    let inside = |v: f32, min: f32, max: f32| ((v - min).min(max - v) + 0.5).max(0.0).min(1.0);
+    inside(local.0, clip[0], clip[2]) * inside(local.1, clip[1], clip[3])
+}
+
+impl ClipRect {
+    /// The screen space bounds of a transformed box. Rotated or skewed boxes
+    /// are clipped to their bounding rectangle.
+    pub fn from_transform(transform: &Matrix4<f32>, (w, h): (f32, f32)) -> Self {
+        use cgmath::{Point3, Transform};
+
+        let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)];
+        let (mut min_x, mut min_y) = (std::f32::MAX, std::f32::MAX);
+        let (mut max_x, mut max_y) = (std::f32::MIN, std::f32::MIN);
+        for (x, y) in corners.iter() {
+            let p = transform.transform_point(Point3::new(*x, *y, 0.0));
+            min_x = min_x.min(p.x);
+            min_y = min_y.min(p.y);
+            max_x = max_x.max(p.x);
+            max_y = max_y.max(p.y);
+        }
+        ClipRect {
+            x: min_x,
+            y: min_y,
+            w: max_x - min_x,
+            h: max_y - min_y,
+            shape: None,
+        }
+    }
+
+    /// The clip of a transformed box: its bounds, and the box itself when it
+    /// is rotated or skewed.
+    pub fn from_box(transform: &Matrix4<f32>, size: (f32, f32)) -> Self {
+        use cgmath::SquareMatrix;
+
+        let mut clip = ClipRect::from_transform(transform, size);
+        if transform.x.y != 0.0 || transform.y.x != 0.0 {
+            clip.shape = transform
+                .invert()
+                .map(|inverse| ClipShape { inverse, size });
+        }
+        clip
+    }
+
+    /// The overlap of both rectangles. A single shape is kept, the one of
+    /// `self` first: other shapes only clip to their bounds.
+    pub fn intersect(&self, other: &ClipRect) -> ClipRect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        ClipRect {
+            x,
+            y,
+            w: ((self.x + self.w).min(other.x + other.w) - x).max(0.0),
+            h: ((self.y + self.h).min(other.y + other.h) - y).max(0.0),
+            shape: self.shape.or(other.shape),
+        }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        use cgmath::{Point3, Transform};
+
+        let in_shape = |shape: &ClipShape| {
+            let p = shape.inverse.transform_point(Point3::new(x, y, 0.0));
+            p.x >= 0.0 && p.x <= shape.size.0 && p.y >= 0.0 && p.y <= shape.size.1
+        };
+        x >= self.x
+            && x <= self.x + self.w
+            && y >= self.y
+            && y <= self.y + self.h
+            && self.shape.as_ref().map_or(true, in_shape)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w <= 0.0 || self.h <= 0.0
+    }
+}
+
+/// A box with an optional border and rounded corners, drawn with the instanced
+/// quad pipeline.
+#[derive(Clone, Debug)]
+pub struct RectItem {
+    pub transform: Matrix4<f32>,
+    pub size: (f32, f32),
+    pub color: [f32; 4],
+    /// Painted over `color`.
+    pub image: Option<BackgroundImage>,
+    /// Placement of a picture `image`.
+    pub image_fit: ImageFit,
+    /// Top left, top right, bottom right and bottom left radii.
+    pub radius: [f32; 4],
+    /// Top, right, bottom and left widths, in pixels.
+    pub border: [f32; 4],
+    pub border_color: [f32; 4],
+    pub border_style: BorderStyle,
+    /// Set when the item is a box shadow rather than a box.
+    pub shadow: Option<ShadowShape>,
+    pub clip: Option<ClipRect>,
+}
+
+/// The shape casting a shadow, relative to the item's quad.
+///
+/// An outer shadow is the quad shrunk by `shrink`, with the item's radii,
+/// and covers the quad with its blur, except under the box casting it. An
+/// inset shadow is drawn inside the item's box, around a hole offset by
+/// `offset` and shrunk by `shrink`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowShape {
+    pub offset: (f32, f32),
+    pub shrink: f32,
+    /// Standard deviation of the gaussian blur, half the css blur radius.
+    pub sigma: f32,
+    pub inset: bool,
+    /// The box casting an outer shadow: its top, right, bottom and left
+    /// distances to the edges of the quad.
+    pub mask: [f32; 4],
+    /// The radii of the box casting an outer shadow.
+    pub mask_radius: [f32; 4],
+}
+
+impl RectItem {
+    /// A plain box, without border nor radius.
+    pub fn solid(
+        transform: Matrix4<f32>,
+        size: (f32, f32),
+        color: [f32; 4],
+        clip: Option<ClipRect>,
+    ) -> Self {
+        RectItem {
+            transform,
+            size,
+            color,
+            image: None,
+            image_fit: ImageFit::Auto,
+            radius: [0.0; 4],
+            border: [0.0; 4],
+            border_color: [0.0; 4],
+            border_style: BorderStyle::None,
+            shadow: None,
+            clip,
+        }
+    }
+}
+
+/// A shaped glyph run, drawn through the font atlas.
+#[derive(Clone, Debug)]
+pub struct TextItem {
+    /// Places the top left corner of the run, like the transform of a rect.
+    pub transform: Matrix4<f32>,
+    pub glyphs: Vec<ShapedGlyph>,
+    pub color: [f32; 4],
+    pub clip: Option<ClipRect>,
+}
+
+/// A glyph of a `TextItem` at whole pixels from its origin, with its texels
+/// in the bitmap of the font.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphQuad {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub tex_x: i32,
+    pub tex_y: i32,
+}
+
+impl GlyphQuad {
+    /// Places the top left corner of the glyph, in a run placed by `origin`.
+    pub fn transform(&self, origin: &Matrix4<f32>) -> Matrix4<f32> {
+        origin * Matrix4::from_translation([self.x as f32, self.y as f32, 0.0].into())
+    }
+}
+
+impl TextItem {
+    /// The glyphs of the run found in `font`.
+    pub fn quads<'f>(&'f self, font: &'f BitmapFont) -> impl Iterator<Item = GlyphQuad> + 'f {
+        let (atlas_w, atlas_h) = (
+            f32::from(font.0.get_width()),
+            f32::from(font.0.get_height()),
+        );
+        self.glyphs.iter().filter_map(move |glyph| {
+            let info = font.0.find_char(glyph.ch)?;
+            Some(GlyphQuad {
+                x: glyph.x as i32 + info.x_offset,
+                y: glyph.y as i32 + info.y_offset,
+                width: info.width,
+                height: info.height,
+                tex_x: (info.tex[0] * atlas_w).round() as i32,
+                tex_y: (info.tex[1] * atlas_h).round() as i32,
+            })
+        })
+    }
+}
+
+/// A group of items painted offscreen, then blended at once with `opacity`.
+#[derive(Clone, Debug)]
+pub struct LayerItem {
+    pub opacity: f32,
+    /// Where the group is composited, in screen pixels.
+    pub bounds: ClipRect,
+    pub clip: Option<ClipRect>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DisplayItem {
+    Rect(RectItem),
+    Text(TextItem),
+    /// The following items, up to the matching `PopLayer`, go to a new layer.
+    PushLayer,
+    /// Ends the current layer and composites it over the previous one.
+    PopLayer(LayerItem),
+}
+
+impl DisplayItem {
+    pub fn clip(&self) -> Option<ClipRect> {
+        match self {
+            DisplayItem::Rect(rect) => rect.clip,
+            DisplayItem::Text(text) => text.clip,
+            DisplayItem::PushLayer => None,
+            DisplayItem::PopLayer(layer) => layer.clip,
+        }
+    }
+
+    /// The number of instances of the quad pipeline the item is drawn with.
+    pub fn quads(&self, font: Option<&BitmapFont>) -> usize {
+        match self {
+            DisplayItem::Rect(_) | DisplayItem::PopLayer(_) => 1,
+            DisplayItem::Text(text) => font.map_or(0, |font| text.quads(font).count()),
+            DisplayItem::PushLayer => 0,
+        }
+    }
+}
+
+/// A laid out entity and the clip inherited from its ancestors.
+#[derive(Clone, Debug)]
+pub struct Painted {
+    pub entity: Entity,
+    pub clip: Option<ClipRect>,
+}
+
+/// Everything to draw this frame, in paint order.
+#[derive(Debug, Default)]
+pub struct DisplayList {
+    pub items: Vec<DisplayItem>,
+    /// Every visible laid out entity, back to front. Hit testing walks it in
+    /// reverse.
+    pub order: Vec<Painted>,
+}
+
+impl DisplayList {
+    pub fn rects(&self) -> impl Iterator<Item = &RectItem> {
+        self.items.iter().filter_map(|item| match item {
+            DisplayItem::Rect(rect) => Some(rect),
+            _ => None,
+        })
+    }
+}
+
+pub fn color_to_f32(color: cgmath::Vector4<u8>) -> [f32; 4] {
+    [
+        f32::from(color.x) / 255.0,
+        f32::from(color.y) / 255.0,
+        f32::from(color.z) / 255.0,
+        f32::from(color.w) / 255.0,
+    ]
+}
+
+/// Radii of a box grown by `amount` on every side; square corners stay square.
+fn radius_grown(radius: [f32; 4], amount: f32) -> [f32; 4] {
+    let mut grown = radius;
+    for r in grown.iter_mut() {
+        if *r > 0.0 {
+            *r = (*r + amount).max(0.0);
+        }
+    }
+    grown
+}
+
+/// Rebuilds the `DisplayList` from the laid out entities.
+///
+/// Entities are painted in tree order. A positioned entity with a `z-index`
+/// starts a stacking context: it is painted with its whole subtree, after the
+/// contexts of lower `z-index` and the rest of the parent context. A context
+/// with an `opacity` is painted in its own layer, so that its descendants
+/// cover each other before the group is made translucent.
+pub struct DisplayListSystem;
+
+struct StackingContext {
+    z_index: i32,
+    entity: Entity,
+}
+
+impl DisplayListSystem {
+    /// Track and thumb of the vertical and horizontal scrollbars of a scroll
+    /// container, along its right and bottom edges.
+    fn scrollbars(
+        global: &GlobalTransform,
+        scroll: &ScrollState,
+        style: &StyleScrollbar,
+        clip: Option<ClipRect>,
+    ) -> Vec<DisplayItem> {
+        let mut items = Vec::new();
+        if style.width <= 0.0 {
+            return items;
+        }
+        let (w, h) = global.1;
+        let (max_x, max_y) = scroll.max_offset();
+        let rect = |x: f32, y: f32, size: (f32, f32), color| {
+            DisplayItem::Rect(RectItem::solid(
+                global.0 * Matrix4::from_translation([x, y, 0.0].into()),
+                size,
+                color_to_f32(color),
+                clip,
+            ))
+        };
+
+        if max_y > 0.0 {
+            let track = h;
+            let thumb = (track * h / scroll.content_size.1).max(style.width);
+            let y = (track - thumb) * scroll.offset.1 / max_y;
+            items.push(rect(
+                w - style.width,
+                0.0,
+                (style.width, track),
+                style.track,
+            ));
+            items.push(rect(w - style.width, y, (style.width, thumb), style.thumb));
+        }
+        if max_x > 0.0 {
+            // leave the corner to the vertical scrollbar
+            let track = if max_y > 0.0 { w - style.width } else { w };
+            let thumb = (track * w / scroll.content_size.0).max(style.width);
+            let x = (track - thumb) * scroll.offset.0 / max_x;
+            items.push(rect(
+                0.0,
+                h - style.width,
+                (track, style.width),
+                style.track,
+            ));
+            items.push(rect(x, h - style.width, (thumb, style.width), style.thumb));
+        }
+        items
+    }
+
+    /// Shadows of a box, outer ones first as they go behind it. Within each
+    /// group the first shadow ends up on top.
+    fn shadows(
+        global: &GlobalTransform,
+        radius: [f32; 4],
+        shadows: &StyleShadow,
+        clip: Option<ClipRect>,
+    ) -> (Vec<DisplayItem>, Vec<DisplayItem>) {
+        let (w, h) = global.1;
+        let mut outer = Vec::new();
+        let mut inset = Vec::new();
+        for shadow in shadows.shadows.iter().rev() {
+            let sigma = shadow.blur / 2.0;
+            let color = color_to_f32(shadow.color.into());
+            if shadow.inset {
+                let mut rect = RectItem::solid(global.0, global.1, color, clip);
+                rect.radius = radius;
+                rect.shadow = Some(ShadowShape {
+                    offset: (shadow.x, shadow.y),
+                    shrink: shadow.spread,
+                    sigma,
+                    inset: true,
+                    mask: [0.0; 4],
+                    mask_radius: [0.0; 4],
+                });
+                inset.push(DisplayItem::Rect(rect));
+            } else {
+                // the quad extends three deviations past the shape, where the blur fades out
+                let margin = 3.0 * sigma;
+                let grow = shadow.spread + margin;
+                let translation = [shadow.x - grow, shadow.y - grow, 0.0];
+                let mut rect = RectItem::solid(
+                    global.0 * Matrix4::from_translation(translation.into()),
+                    ((w + 2.0 * grow).max(0.0), (h + 2.0 * grow).max(0.0)),
+                    color,
+                    clip,
+                );
+                rect.radius = radius_grown(radius, shadow.spread);
+                rect.shadow = Some(ShadowShape {
+                    offset: (0.0, 0.0),
+                    shrink: margin,
+                    sigma,
+                    inset: false,
+                    mask: [
+                        grow - shadow.y,
+                        grow + shadow.x,
+                        grow + shadow.y,
+                        grow - shadow.x,
+                    ],
+                    mask_radius: radius,
+                });
+                outer.push(DisplayItem::Rect(rect));
+            }
+        }
+        (outer, inset)
+    }
+
+    fn outline(
+        global: &GlobalTransform,
+        radius: [f32; 4],
+        outline: &StyleOutline,
+        clip: Option<ClipRect>,
+    ) -> Option<DisplayItem> {
+        if outline.style == BorderStyle::None || outline.width <= 0.0 {
+            return None;
+        }
+        let (w, h) = global.1;
+        let grow = outline.offset + outline.width;
+        let mut rect = RectItem::solid(
+            global.0 * Matrix4::from_translation([-grow, -grow, 0.0].into()),
+            (w + 2.0 * grow, h + 2.0 * grow),
+            [0.0; 4],
+            clip,
+        );
+        rect.border = [outline.width; 4];
+        rect.border_color = color_to_f32(outline.color);
+        rect.border_style = outline.style;
+        rect.radius = radius_grown(radius, grow);
+        Some(DisplayItem::Rect(rect))
+    }
+
+    fn is_ancestor(ancestor: Entity, e: Entity, parents: &ReadStorage<'_, Parent>) -> bool {
+        let mut current = parents.get(e).map(|p| p.entity);
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = parents.get(parent).map(|p| p.entity);
+        }
+        false
+    }
+
+    /// Intersection of the boxes of every ancestor that does not let its
+    /// content overflow. `None` means unclipped.
+    fn clip(
+        e: Entity,
+        parents: &ReadStorage<'_, Parent>,
+        dimensions: &ReadStorage<'_, Dimensions>,
+        pos: &ReadStorage<'_, GlobalTransform>,
+    ) -> Option<ClipRect> {
+        let mut clip: Option<ClipRect> = None;
+        let mut current = parents.get(e).map(|p| p.entity);
+        while let Some(ancestor) = current {
+            let clips = dimensions
+                .get(ancestor)
+                .map_or(false, |d| d.overflow != Overflow::Visible);
+            if let (true, Some(global)) = (clips, pos.get(ancestor)) {
+                let bounds = ClipRect::from_box(&global.0, global.1);
+                clip = Some(match clip {
+                    Some(clip) => clip.intersect(&bounds),
+                    None => bounds,
+                });
+            }
+            current = parents.get(ancestor).map(|p| p.entity);
+        }
+        clip
+    }
+
+    /// Splits `children` and their descendants into the ones painted in tree order in the
+    /// current context and the nested stacking contexts.
+    fn collect(
+        hierarchy: &ParentHierarchy,
+        children: &[Entity],
+        stacking: &ReadStorage<'_, StyleStacking>,
+        flow: &mut Vec<Entity>,
+        contexts: &mut Vec<StackingContext>,
+    ) {
+        for c in children {
+            match stacking.get(*c).filter(|s| s.starts_context()) {
+                Some(s) => contexts.push(StackingContext {
+                    z_index: s.z_index.unwrap_or(0),
+                    entity: *c,
+                }),
+                None => {
+                    flow.push(*c);
+                    Self::collect(hierarchy, hierarchy.children(*c), stacking, flow, contexts);
+                }
+            }
+        }
+    }
+
+    /// Appends a stacking context rooted at `root` (if any) with the given children to `order`.
+    fn paint_context(
+        hierarchy: &ParentHierarchy,
+        root: Option<Entity>,
+        children: &[Entity],
+        stacking: &ReadStorage<'_, StyleStacking>,
+        order: &mut Vec<Entity>,
+    ) {
+        let mut flow = Vec::new();
+        let mut contexts = Vec::new();
+        Self::collect(hierarchy, children, stacking, &mut flow, &mut contexts);
+        // stable: contexts with the same z-index keep their tree order
+        contexts.sort_by_key(|c| c.z_index);
+
+        order.extend(root);
+        let mut contexts = contexts.into_iter().peekable();
+        while let Some(c) = contexts.peek() {
+            if c.z_index >= 0 {
+                break;
+            }
+            Self::paint_context(
+                hierarchy,
+                Some(c.entity),
+                hierarchy.children(c.entity),
+                stacking,
+                order,
+            );
+            contexts.next();
+        }
+        order.extend(flow);
+        for c in contexts {
+            Self::paint_context(
+                hierarchy,
+                Some(c.entity),
+                hierarchy.children(c.entity),
+                stacking,
+                order,
+            );
+        }
+    }
+}
+
+impl<'a> System<'a> for DisplayListSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Screen>,
+        ReadExpect<'a, ParentHierarchy>,
+        ReadStorage<'a, Parent>,
+        ReadStorage<'a, StyleStacking>,
+        ReadStorage<'a, Dimensions>,
+        ReadStorage<'a, GlobalTransform>,
+        ReadStorage<'a, Material>,
+        ReadStorage<'a, ShapedText>,
+        ReadStorage<'a, Image>,
+        ReadStorage<'a, ScrollState>,
+        ReadStorage<'a, StyleScrollbar>,
+        ReadStorage<'a, StyleBorder>,
+        ReadStorage<'a, StyleShadow>,
+        ReadStorage<'a, StyleOutline>,
+        Write<'a, DisplayList>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            screen,
+            hierarchy,
+            parents,
+            stacking,
+            dimensions,
+            pos,
+            mat,
+            text,
+            images,
+            scrolls,
+            scrollbars,
+            borders,
+            shadows,
+            outlines,
+            mut list,
+        ): Self::SystemData,
+    ) {
+        let roots: Vec<Entity> = (&*entities, &pos, !&parents)
+            .join()
+            .map(|(e, _, _)| e)
+            .collect();
+        let mut order = Vec::with_capacity(list.order.len());
+        Self::paint_context(&hierarchy, None, &roots, &stacking, &mut order);
+        order.retain(|e| pos.get(*e).is_some());
+
+        list.items.clear();
+        list.order.clear();
+        // scrollbars and outlines are painted once the subtree of their element is done
+        let mut pending: Vec<(Entity, Vec<DisplayItem>)> = Vec::new();
+        for e in order {
+            while let Some((container, _)) = pending.last() {
+                if Self::is_ancestor(*container, e, &parents) {
+                    break;
+                }
+                let (_, items) = pending.pop().unwrap();
+                list.items.extend(items);
+            }
+
+            let clip = Self::clip(e, &parents, &dimensions, &pos);
+            // hidden elements keep their room, but neither paint nor catch the mouse
+            let visible = StyleStacking::resolve_visible(e, &stacking, &parents);
+            if visible {
+                list.order.push(Painted { entity: e, clip });
+            }
+
+            let mut layer = None;
+            if let Some(style) = stacking.get(e).filter(|s| s.opacity < 1.0) {
+                let screen = ClipRect {
+                    x: 0.0,
+                    y: 0.0,
+                    w: screen.size.0 as f32,
+                    h: screen.size.1 as f32,
+                    shape: None,
+                };
+                list.items.push(DisplayItem::PushLayer);
+                layer = Some(DisplayItem::PopLayer(LayerItem {
+                    opacity: style.opacity,
+                    bounds: clip.map_or(screen, |clip| clip.intersect(&screen)),
+                    clip,
+                }));
+            }
+
+            // drawn over the descendants
+            let mut over = Vec::new();
+            let painted = match (pos.get(e), mat.get(e)) {
+                (Some(pos), Some(mat)) if visible && !clip.map_or(false, |c| c.is_empty()) => {
+                    Some((pos, mat))
+                }
+                _ => None,
+            };
+            if let Some((pos, mat)) = painted {
+                let mut rect =
+                    RectItem::solid(pos.0, pos.1, color_to_f32(mat.background.color), clip);
+                rect.image = mat.background.image.clone();
+                rect.image_fit = mat.background.size;
+                if let (Some(border), Some(dimensions)) = (borders.get(e), dimensions.get(e)) {
+                    let (box_width, _) = pos.1;
+                    let width = |d: Dimension| match d {
+                        Dimension::Points(x) => x,
+                        Dimension::Percent(x) => x * box_width,
+                        _ => 0.0,
+                    };
+                    let b = &dimensions.border;
+                    rect.border = [width(b.top), width(b.end), width(b.bottom), width(b.start)];
+                    rect.border_color = color_to_f32(border.color);
+                    rect.border_style = border.style;
+                    rect.radius = border.radius;
+                }
+                let border_image = borders.get(e).and_then(|b| b.image.as_ref());
+                if border_image.is_some() {
+                    // the picture replaces the border style
+                    rect.border_style = BorderStyle::None;
+                }
+                let (radius, border) = (rect.radius, rect.border);
+
+                let (outer, inset) = match shadows.get(e) {
+                    Some(shadows) => Self::shadows(pos, radius, shadows, clip),
+                    None => (Vec::new(), Vec::new()),
+                };
+                list.items.extend(outer);
+                list.items.push(DisplayItem::Rect(rect));
+                list.items.extend(inset);
+
+                if let Some(image) = border_image {
+                    let mut sliced = RectItem::solid(pos.0, pos.1, [0.0; 4], clip);
+                    sliced.border = border;
+                    sliced.image = Some(BackgroundImage::Texture(image.path.clone()));
+                    sliced.image_fit = ImageFit::Slice {
+                        slice: image.slice,
+                        fill: image.fill,
+                        repeat: image.repeat,
+                    };
+                    list.items.push(DisplayItem::Rect(sliced));
+                }
+
+                // the picture of an image element fills its padding box
+                if let Some(image) = images.get(e) {
+                    let [top, right, bottom, left] = border;
+                    let (w, h) = pos.1;
+                    let mut picture = RectItem::solid(
+                        pos.0 * Matrix4::from_translation([left, top, 0.0].into()),
+                        ((w - left - right).max(0.0), (h - top - bottom).max(0.0)),
+                        [0.0; 4],
+                        clip,
+                    );
+                    picture.image = Some(BackgroundImage::Texture(image.path.clone()));
+                    picture.image_fit = ImageFit::Fill;
+                    picture.radius = radius_grown(radius, -top.max(right).max(bottom).max(left));
+                    list.items.push(DisplayItem::Rect(picture));
+                }
+
+                if let Some(text) = text.get(e) {
+                    list.items.push(DisplayItem::Text(TextItem {
+                        transform: pos.0,
+                        glyphs: text.run.glyphs.clone(),
+                        color: color_to_f32(Material::resolve_foreground(e, &mat, &parents)),
+                        clip,
+                    }));
+                }
+
+                if let (Some(scroll), Some(style)) = (scrolls.get(e), scrollbars.get(e)) {
+                    over.extend(Self::scrollbars(pos, scroll, style, clip));
+                }
+                if let Some(outline) = outlines.get(e) {
+                    over.extend(Self::outline(pos, radius, outline, clip));
+                }
+            }
+            // the layer ends with the subtree, once its scrollbars and outline are in
+            over.extend(layer);
+            if !over.is_empty() {
+                pending.push((e, over));
+            }
+        }
+        while let Some((_, items)) = pending.pop() {
+            list.items.extend(items);
+        }
+    }
+}
@@ -1,18 +1,195 @@
 #![allow(dead_code)]
 
+use std::path::PathBuf;
+
 use specs::prelude::*;
 
 use cgmath::Vector4;
 
+use crate::transform::Parent;
+
+/// A color at a position along a gradient, from 0 to 1.
 #[derive(Clone, Debug, PartialEq)]
-pub struct Material {
+pub struct GradientStop {
+    pub color: Vector4<u8>,
+    pub position: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientShape {
+    /// Angle in radians, clockwise from the top as in css.
+    Linear { angle: f32 },
+    /// Centered, sized to the farthest corner.
+    Radial { circle: bool },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub shape: GradientShape,
+    /// Sorted by position.
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    /// Color at `t`, from 0 to 1 along the gradient line.
+    pub fn sample(&self, t: f32) -> Vector4<u8> {
+        let first = match self.stops.first() {
+            Some(first) => first,
+            None => return Vector4::new(0, 0, 0, 0),
+        };
+        if t <= first.position {
+            return first.color;
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if t <= b.position {
+                let span = b.position - a.position;
+                let f = if span > 0.0 {
+                    (t - a.position) / span
+                } else {
+                    1.0
+                };
+                let lerp = |x: u8, y: u8| (f32::from(x) + (f32::from(y) - f32::from(x)) * f) as u8;
+                return Vector4::new(
+                    lerp(a.color.x, b.color.x),
+                    lerp(a.color.y, b.color.y),
+                    lerp(a.color.z, b.color.z),
+                    lerp(a.color.w, b.color.w),
+                );
+            }
+        }
+        self.stops.last().unwrap().color
+    }
+
+    /// The gradient sampled at `width` evenly spaced positions.
+    pub fn ramp(&self, width: usize) -> Vec<[u8; 4]> {
+        (0..width)
+            .map(|i| {
+                let c = self.sample(i as f32 / (width - 1).max(1) as f32);
+                [c.x, c.y, c.z, c.w]
+            })
+            .collect()
+    }
+}
+
+/// How a picture is scaled into a box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFit {
+    /// Natural size, at the top left corner.
+    Auto,
+    /// Covers the whole box, centered and cropped.
+    Cover,
+    /// Fits entirely in the box, centered.
+    Contain,
+    /// Stretched to the size of the box.
+    Fill,
+    /// Split in nine regions by `slice`, in picture pixels (top, right,
+    /// bottom, left): the corners go in the corners of the box's border,
+    /// the edges along its sides and the middle, if `fill`, in the center.
+    Slice {
+        slice: [f32; 4],
+        fill: bool,
+        repeat: BorderRepeat,
+    },
+}
+
+/// How the edges and middle of a sliced picture cover their region.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BorderRepeat {
+    Stretch,
+    /// Tiled from the center of the region.
+    Repeat,
+    /// Tiled, with the tiles scaled to fit a whole number of times.
+    Round,
+}
+
+impl Default for BorderRepeat {
+    fn default() -> Self {
+        BorderRepeat::Stretch
+    }
+}
+
+/// A picture drawn over the border of a box, instead of its border style.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorderImage {
+    pub path: PathBuf,
+    pub slice: [f32; 4],
+    pub fill: bool,
+    pub repeat: BorderRepeat,
+}
+
+impl Default for ImageFit {
+    fn default() -> Self {
+        ImageFit::Auto
+    }
+}
+
+impl ImageFit {
+    /// Position and size of an `image` sized picture placed in a `size` box.
+    pub fn place(self, image: (f32, f32), size: (f32, f32)) -> (f32, f32, f32, f32) {
+        let scale = match self {
+            ImageFit::Auto => 1.0,
+            ImageFit::Fill | ImageFit::Slice { .. } => return (0.0, 0.0, size.0, size.1),
+            _ if image.0 <= 0.0 || image.1 <= 0.0 => return (0.0, 0.0, 0.0, 0.0),
+            ImageFit::Cover => (size.0 / image.0).max(size.1 / image.1),
+            ImageFit::Contain => (size.0 / image.0).min(size.1 / image.1),
+        };
+        let (w, h) = (image.0 * scale, image.1 * scale);
+        match self {
+            ImageFit::Auto => (0.0, 0.0, w, h),
+            _ => ((size.0 - w) / 2.0, (size.1 - h) / 2.0, w, h),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackgroundImage {
+    Gradient(Gradient),
+    /// A picture loaded as a `Texture` resource.
+    Texture(PathBuf),
+}
+
+/// What is painted behind the content of a box: a color, with an optional
+/// image over it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Background {
     pub color: Vector4<u8>,
+    pub image: Option<BackgroundImage>,
+    /// Placement of a picture image.
+    pub size: ImageFit,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            color: Vector4::new(0, 0, 0, 0),
+            image: None,
+            size: ImageFit::Auto,
+        }
+    }
+}
+
+impl Background {
+    pub fn from_color(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Background {
+            color: Vector4::new(r, g, b, a),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub background: Background,
+    /// The color of the text, inherited from the parent when none.
+    pub foreground: Option<Vector4<u8>>,
 }
 
 impl Default for Material {
     fn default() -> Self {
         Material {
-            color: Vector4::new(255, 255, 255, 255),
+            background: Background::from_color(255, 255, 255, 255),
+            foreground: None,
         }
     }
 }
@@ -20,11 +197,69 @@ impl Default for Material {
 impl Material {
     pub fn from_color(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self {
-            color: Vector4::new(r, g, b, a),
+            background: Background::from_color(r, g, b, a),
+            foreground: None,
+        }
+    }
+
+    /// Resolves the inherited text color by walking up the parents.
+    pub fn resolve_foreground(
+        e: Entity,
+        materials: &ReadStorage<'_, Material>,
+        parents: &ReadStorage<'_, Parent>,
+    ) -> Vector4<u8> {
+        let mut current = Some(e);
+        while let Some(e) = current {
+            if let Some(color) = materials.get(e).and_then(|m| m.foreground) {
+                return color;
+            }
+            current = parents.get(e).map(|p| p.entity);
         }
+        Vector4::new(230, 230, 230, 255)
     }
 }
 
 impl Component for Material {
     type Storage = VecStorage<Self>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_between_stops() {
+        let g = Gradient {
+            shape: GradientShape::Linear { angle: 0.0 },
+            stops: vec![
+                GradientStop {
+                    color: Vector4::new(0, 0, 0, 255),
+                    position: 0.25,
+                },
+                GradientStop {
+                    color: Vector4::new(200, 100, 0, 255),
+                    position: 0.75,
+                },
+            ],
+        };
+        assert_eq!(g.sample(0.0), Vector4::new(0, 0, 0, 255));
+        assert_eq!(g.sample(0.5), Vector4::new(100, 50, 0, 255));
+        assert_eq!(g.sample(1.0), Vector4::new(200, 100, 0, 255));
+    }
+
+    #[test]
+    fn place_image() {
+        let image = (200.0, 100.0);
+        let size = (100.0, 100.0);
+        assert_eq!(ImageFit::Auto.place(image, size), (0.0, 0.0, 200.0, 100.0));
+        assert_eq!(ImageFit::Fill.place(image, size), (0.0, 0.0, 100.0, 100.0));
+        assert_eq!(
+            ImageFit::Cover.place(image, size),
+            (-50.0, 0.0, 200.0, 100.0)
+        );
+        assert_eq!(
+            ImageFit::Contain.place(image, size),
+            (0.0, 25.0, 100.0, 50.0)
+        );
+    }
+}
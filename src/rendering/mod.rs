@@ -1,15 +1,26 @@
+mod display_list;
 mod material;
+mod software;
+mod textures;
+pub use display_list::*;
 pub use material::*;
+pub use software::{Canvas, SoftwareRenderer};
+pub use textures::*;
 
 use crate::gfx_app;
 use crate::gfx_app::{ColorFormat, DepthFormat};
+use crate::layout::BitmapFont;
+use crate::manager::{Diagnostics, Handle, ResourceReloaded, ShaderError, ShaderSet};
 use crate::shade;
+use crate::styling::BorderStyle;
 use cgmath::{Matrix4, Point3, Vector3};
 use gfx;
 use gfx::texture;
 
-use crate::transform::GlobalTransform;
 use specs::prelude::*;
+use specs::shrev::EventChannel;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Default)]
 pub struct Screen {
@@ -25,109 +36,573 @@ impl Component for Text {
     type Storage = DenseVecStorage<Self>;
 }
 
-pub struct SysRender<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Clone + gfx::Factory<R>> {
+/// The components the `Renderer` draws, and the display list it draws them
+/// in, once they are laid out.
+pub struct RenderBundle;
+
+impl<'a, 'b> crate::UiBundle<'a, 'b> for RenderBundle {
+    fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>) {
+        world.register::<Material>();
+        world.register::<Text>();
+        world.register::<Image>();
+        world.add_resource(Screen::default());
+        dispatcher.add(DisplayListSystem, "sys_display_list", &["transform_system"]);
+    }
+
+    fn style(&self) -> Option<&'static str> {
+        Some("* { scrollbar-color: #a0a0a0 #282828; scrollbar-width: 12; outline-width: 3; }")
+    }
+}
+
+pub struct SysRender<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>> {
     slice: &'a gfx::Slice<R>,
-    data: &'a pipe::Data<R>,
+    data: &'a mut pipe::Data<R>,
+    gradients: &'a gfx::handle::Texture<R, gfx::format::R8_G8_B8_A8>,
+    gradient_rows: usize,
+    /// The ramps in `gradients`, uploaded again only when they change.
+    ramps: &'a mut Vec<[u8; 4]>,
+    pictures: &'a HashMap<PathBuf, Handle<Texture>>,
+    textures: &'a HashMap<Handle<Texture>, GpuTexture<R>>,
+    white: &'a gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    layers: &'a [Layer<R>],
     pso: &'a gfx::PipelineState<R, pipe::Meta>,
     encoder: &'a mut gfx::Encoder<R, C>,
-    text: &'a mut Option<gfx_text::Renderer<R, F>>,
+    /// The font the glyphs are drawn from, and its bitmap once uploaded.
+    font: Option<(&'a BitmapFont, &'a GpuTexture<R>)>,
+}
+
+/// What a batch of quads samples as `t_Color`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Binding {
+    None,
+    Picture(Handle<Texture>),
+    /// The layer at this nesting level.
+    Layer(usize),
+}
+
+/// Quads waiting to be drawn together.
+struct Batch {
+    first: u32,
+    rects: u32,
+    clip: Option<ClipRect>,
+    binding: Binding,
+}
+
+impl<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>> SysRender<'a, R, C> {
+    /// Draws `count` quads of the instance buffer starting at `first`, in a single call.
+    fn flush_rects(&mut self, first: u32, count: u32, clip: Option<ClipRect>, binding: Binding) {
+        if count == 0 {
+            return;
+        }
+        self.data.scissor = scissor(clip, self.data.screen);
+        self.data.color.0 = match binding {
+            Binding::None => self.white,
+            Binding::Picture(picture) => self
+                .textures
+                .get(&picture)
+                .map_or(self.white, |texture| &texture.view),
+            Binding::Layer(level) => &self.layers[level].view,
+        }
+        .clone();
+        let mut slice = self.slice.clone();
+        slice.instances = Some((count, first));
+        self.encoder.draw(&slice, self.pso, self.data);
+    }
+
+    /// The handle of the picture of `rect`, registered by the `Renderer`.
+    fn picture(&self, rect: &RectItem) -> Option<Handle<Texture>> {
+        texture_path(rect).and_then(|path| self.pictures.get(path).cloned())
+    }
+
+    fn flush(&mut self, batch: &mut Batch) {
+        self.flush_rects(batch.first, batch.rects, batch.clip, batch.binding);
+        batch.first += batch.rects;
+        batch.rects = 0;
+    }
+
+    /// Adds `count` quads to the batch, drawing it first if they cannot join it.
+    fn push(&mut self, batch: &mut Batch, count: u32, clip: Option<ClipRect>, binding: Binding) {
+        if batch.rects > 0 && (batch.clip != clip || batch.binding != binding) {
+            self.flush(batch);
+        }
+        batch.clip = clip;
+        batch.binding = binding;
+        batch.rects += count;
+    }
 }
 
-impl<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>, F: Clone + gfx::Factory<R>> System<'a>
-    for SysRender<'a, R, C, F>
-{
-    type SystemData = (
-        ReadStorage<'a, GlobalTransform>,
-        ReadStorage<'a, Material>,
-        ReadStorage<'a, Text>,
-        // Read<'a, Screen>,
-    );
-    fn run(&mut self, (pos, mat, text): Self::SystemData) {
-        self.encoder
-            .clear(&self.data.out_color, [0.1, 0.2, 0.3, 1.0]);
+impl<'a, R: gfx::Resources, C: gfx::CommandBuffer<R>> System<'a> for SysRender<'a, R, C> {
+    type SystemData = Read<'a, DisplayList>;
+
+    fn run(&mut self, list: Self::SystemData) {
+        self.encoder.clear(&self.data.out_color, CLEAR_COLOR);
         self.encoder.clear_depth(&self.data.out_depth, 1.0);
         let vp: cgmath::Matrix4<f32> = self.data.transform.into();
+        // glyphs sample their own texture, and join the batch of any picture
+        self.data.font.0 = self
+            .font
+            .map_or(self.white, |(_, atlas)| &atlas.view)
+            .clone();
+
+        // all quads are uploaded at once, in paint order, and each gradient
+        // gets a row of the ramp texture
+        let mut ramps: Vec<[u8; 4]> = Vec::new();
+        let mut instances: Vec<Instance> = Vec::new();
+        // the number of instances of each item
+        let mut counts: Vec<u32> = Vec::with_capacity(list.items.len());
+        for item in list.items.iter() {
+            let first = instances.len();
+            match item {
+                DisplayItem::Rect(rect) => {
+                    let row = match rect.image {
+                        Some(BackgroundImage::Gradient(ref gradient))
+                            if ramps.len() < GRADIENT_WIDTH * self.gradient_rows =>
+                        {
+                            let row = ramps.len() / GRADIENT_WIDTH;
+                            ramps.extend(gradient.ramp(GRADIENT_WIDTH));
+                            Some((row as f32 + 0.5) / self.gradient_rows as f32)
+                        }
+                        _ => None,
+                    };
+                    let image_size = self
+                        .picture(rect)
+                        .and_then(|picture| self.textures.get(&picture))
+                        .map(|texture| texture.size);
+                    instances.push(Instance::new(vp * rect.transform, rect, row, image_size));
+                }
+                DisplayItem::Text(text) => {
+                    if let Some((font, atlas)) = self.font {
+                        let glyphs = text
+                            .quads(font)
+                            .map(|quad| Instance::glyph(vp, text, &quad, atlas.size));
+                        instances.extend(glyphs);
+                    }
+                }
+                DisplayItem::PopLayer(layer) => instances.push(Instance::layer(vp, layer)),
+                DisplayItem::PushLayer => {}
+            }
+            counts.push((instances.len() - first) as u32);
+        }
+        if !ramps.is_empty() && ramps != *self.ramps {
+            let mut info = self.gradients.get_info().to_image_info(0);
+            info.height = (ramps.len() / GRADIENT_WIDTH) as u16;
+            if let Err(e) = self
+                .encoder
+                .update_texture::<gfx::format::R8_G8_B8_A8, gfx::format::Rgba8>(
+                    self.gradients,
+                    None,
+                    info,
+                    &ramps,
+                )
+            {
+                error!("cannot upload the gradients: {:?}", e);
+            }
+            *self.ramps = ramps;
+        }
+        if let Err(e) = self
+            .encoder
+            .update_buffer(&self.data.instances, &instances, 0)
+        {
+            error!("cannot upload the instances: {:?}", e);
+            return;
+        }
 
-        for (pos, mat, text) in (&pos, &mat, text.maybe()).join() {
-            let m = pos.0;
-            let locals = Locals {
-                transform: (vp * m).into(),
-                color: [
-                    mat.color.x as f32 / 255.0,
-                    mat.color.y as f32 / 255.0,
-                    mat.color.z as f32 / 255.0,
-                    mat.color.w as f32 / 255.0,
-                ],
-                size: [(pos.1).0, (pos.1).1],
-            };
-            self.encoder
-                .update_constant_buffer(&self.data.locals, &locals);
-            self.encoder.draw(self.slice, self.pso, self.data);
-
-            if let Some(text_renderer) = self.text {
-                if let Some(text) = text {
-                    use cgmath::Transform;
-                    let p = m.transform_point(cgmath::Point3::new(0.0, 0.0, 0.0));
-                    text_renderer.add(
-                        &text.text,               // Text to add
-                        [p.x as i32, p.y as i32], // Position
-                        [0.9, 0.9, 0.9, 1.0],     // Text color
-                    );
-                    if let Err(e) = text_renderer.draw(self.encoder, &self.data.out_color) {
-                        match e {
-                            gfx_text::Error::PipelineError(p) => {
-                                println!("{}", p);
-                            }
-                            _ => println!("{:?}", e),
+        // consecutive quads of the same clip and binding are drawn together, the
+        // glyphs of every run included; switching flushes the pending batch so the
+        // paint order is kept
+        let mut batch = Batch {
+            first: 0,
+            rects: 0,
+            clip: None,
+            binding: Binding::None,
+        };
+        // the targets under the open layers, `None` when a layer could not be allocated
+        let mut targets = Vec::new();
+        let layers = self.layers;
+        for (item, count) in list.items.iter().zip(counts) {
+            match item {
+                DisplayItem::Rect(rect) => {
+                    let binding = self.picture(rect).map_or(Binding::None, Binding::Picture);
+                    self.push(&mut batch, count, rect.clip, binding);
+                }
+                DisplayItem::Text(text) => {
+                    if count > 0 {
+                        let binding = batch.binding;
+                        self.push(&mut batch, count, text.clip, binding);
+                    }
+                }
+                DisplayItem::PushLayer => {
+                    self.flush(&mut batch);
+                    match layers.get(targets.len()) {
+                        Some(layer) => {
+                            let target =
+                                std::mem::replace(&mut self.data.out_color, layer.target.clone());
+                            self.encoder.clear(&self.data.out_color, [0.0; 4]);
+                            targets.push(Some(target));
                         }
+                        // drawn straight to the current target, without the group opacity
+                        None => targets.push(None),
+                    }
+                }
+                DisplayItem::PopLayer(layer) => {
+                    self.flush(&mut batch);
+                    if let Some(Some(target)) = targets.pop() {
+                        self.data.out_color = target;
+                        self.flush_rects(batch.first, 1, layer.clip, Binding::Layer(targets.len()));
                     }
+                    batch.first += 1;
                 }
             }
         }
+        self.flush(&mut batch);
+    }
+}
+
+/// The errors of a pipeline built from `set`, located in its sources when the
+/// driver compiled them.
+fn pipeline_errors(
+    name: &Path,
+    set: &ShaderSet,
+    error: &gfx::PipelineStateError<String>,
+) -> Vec<ShaderError> {
+    use gfx_core::shade::{CreateShaderError, ProgramError};
+
+    let (file, log) = match error {
+        gfx::PipelineStateError::Program(ProgramError::Vertex(
+            CreateShaderError::CompilationFailed(log),
+        )) => (&set.files[0], log),
+        gfx::PipelineStateError::Program(ProgramError::Pixel(
+            CreateShaderError::CompilationFailed(log),
+        )) => (&set.files[1], log),
+        e => {
+            return vec![ShaderError {
+                file: name.to_owned(),
+                line: None,
+                message: e.to_string(),
+            }]
+        }
+    };
+    let errors = ShaderError::parse_log(file, log);
+    if errors.is_empty() {
+        vec![ShaderError {
+            file: file.clone(),
+            line: None,
+            message: log.trim().to_owned(),
+        }]
+    } else {
+        errors
+    }
+}
+
+fn texture_path(rect: &RectItem) -> Option<&PathBuf> {
+    match rect.image {
+        Some(BackgroundImage::Texture(ref path)) => Some(path),
+        _ => None,
+    }
+}
+
+/// The scissor rect for a clip, or the whole screen when unclipped.
+fn scissor(clip: Option<ClipRect>, screen: [f32; 2]) -> gfx::Rect {
+    let clip = clip.unwrap_or(ClipRect {
+        x: 0.0,
+        y: 0.0,
+        w: screen[0],
+        h: screen[1],
+        shape: None,
+    });
+    let x = clip.x.max(0.0).min(screen[0]);
+    let y = clip.y.max(0.0).min(screen[1]);
+    let right = (clip.x + clip.w).max(x).min(screen[0]);
+    let bottom = (clip.y + clip.h).max(y).min(screen[1]);
+    gfx::Rect {
+        x: x as u16,
+        y: y as u16,
+        w: (right - x) as u16,
+        h: (bottom - y) as u16,
     }
 }
 
 gfx_defines! {
     vertex Vertex {
         pos: [f32; 4] = "a_Pos",
-        tex_coord: [f32; 2] = "a_TexCoord",
     }
 
-    constant Locals {
-        transform: [[f32; 4]; 4] = "u_Transform",
-        color: [f32; 4] = "u_Color",
-        size: [f32; 2] = "u_Size",
+    vertex Instance {
+        transform_x: [f32; 4] = "a_TransformX",
+        transform_y: [f32; 4] = "a_TransformY",
+        transform_z: [f32; 4] = "a_TransformZ",
+        transform_w: [f32; 4] = "a_TransformW",
+        color: [f32; 4] = "a_Color",
+        size: [f32; 2] = "a_Size",
+        radius: [f32; 4] = "a_Radius",
+        border: [f32; 4] = "a_Border",
+        border_color: [f32; 4] = "a_BorderColor",
+        gradient: [f32; 4] = "a_Gradient",
+        image: [f32; 4] = "a_Image",
+        border_style: f32 = "a_BorderStyle",
+        shadow: [f32; 4] = "a_Shadow",
+        kind: f32 = "a_Kind",
+        clip: [f32; 4] = "a_Clip",
     }
 
     pipeline pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
         transform: gfx::Global<[[f32; 4]; 4]> = "u_Transform",
         screen: gfx::Global<[f32; 2]> = "u_Screen",
-        locals: gfx::ConstantBuffer<Locals> = "Locals",
+        // the picture of the current batch
         color: gfx::TextureSampler<[f32; 4]> = "t_Color",
-        out_color: gfx::RenderTarget<ColorFormat> = "Target0",
+        gradients: gfx::TextureSampler<[f32; 4]> = "t_Gradients",
+        font: gfx::TextureSampler<[f32; 4]> = "t_Font",
+        scissor: gfx::Scissor = (),
+        // antialiased edges are blended over what is behind
+        out_color: gfx::BlendTarget<ColorFormat> =
+            ("Target0", gfx::state::ColorMask::all(), BLEND_OVER),
         out_depth: gfx::DepthTarget<DepthFormat> =
             gfx::preset::depth::LESS_EQUAL_WRITE,
     }
 }
 
+/// Alpha blending that also composes the alpha channel: drawn over a cleared
+/// layer, it leaves premultiplied colors with their coverage as alpha.
+const BLEND_OVER: gfx::state::Blend = gfx::state::Blend {
+    color: gfx::state::BlendChannel {
+        equation: gfx::state::Equation::Add,
+        source: gfx::state::Factor::ZeroPlus(gfx::state::BlendValue::SourceAlpha),
+        destination: gfx::state::Factor::OneMinus(gfx::state::BlendValue::SourceAlpha),
+    },
+    alpha: gfx::state::BlendChannel {
+        equation: gfx::state::Equation::Add,
+        source: gfx::state::Factor::One,
+        destination: gfx::state::Factor::OneMinus(gfx::state::BlendValue::SourceAlpha),
+    },
+};
+
 impl Vertex {
-    fn new(p: [i8; 3], t: [i8; 2]) -> Vertex {
+    fn new(p: [i8; 3]) -> Vertex {
         Vertex {
             pos: [p[0] as f32, p[1] as f32, p[2] as f32, 1.0],
-            tex_coord: [t[0] as f32, t[1] as f32],
         }
     }
 }
 
+impl Instance {
+    /// `transform` already includes the view projection. `gradient_row` is the
+    /// texture coordinate of the row of the ramp texture holding the gradient
+    /// of the rect and `image_size` the size of its picture, once uploaded.
+    fn new(
+        transform: Matrix4<f32>,
+        rect: &RectItem,
+        gradient_row: Option<f32>,
+        image_size: Option<(f32, f32)>,
+    ) -> Instance {
+        let mut image = [0.0; 4];
+        let gradient = match (&rect.image, gradient_row, image_size) {
+            (Some(BackgroundImage::Gradient(gradient)), Some(v), _) => match gradient.shape {
+                GradientShape::Linear { angle } => [1.0, angle, v, 0.0],
+                GradientShape::Radial { circle: true } => [2.0, 0.0, v, 0.0],
+                GradientShape::Radial { circle: false } => [3.0, 0.0, v, 0.0],
+            },
+            (Some(BackgroundImage::Texture(_)), _, Some(size)) => match rect.image_fit {
+                ImageFit::Slice {
+                    slice,
+                    fill,
+                    repeat,
+                } => {
+                    image = slice;
+                    let mode = match repeat {
+                        BorderRepeat::Stretch => 5.0,
+                        BorderRepeat::Repeat => 6.0,
+                        BorderRepeat::Round => 7.0,
+                    };
+                    [mode, if fill { 1.0 } else { 0.0 }, 0.0, 0.0]
+                }
+                fit => {
+                    let (x, y, w, h) = fit.place(size, rect.size);
+                    image = [x, y, w, h];
+                    [4.0, 0.0, 0.0, 0.0]
+                }
+            },
+            _ => [0.0; 4],
+        };
+        // an outer shadow passes the box it is masked by as its border
+        let (border, border_color) = match rect.shadow {
+            Some(ShadowShape {
+                inset: false,
+                mask,
+                mask_radius,
+                ..
+            }) => (mask, mask_radius),
+            _ => (rect.border, rect.border_color),
+        };
+        Instance {
+            transform_x: transform.x.into(),
+            transform_y: transform.y.into(),
+            transform_z: transform.z.into(),
+            transform_w: transform.w.into(),
+            color: rect.color,
+            size: [rect.size.0, rect.size.1],
+            radius: rect.radius,
+            border,
+            border_color,
+            gradient,
+            image,
+            border_style: match rect.border_style {
+                BorderStyle::None => 0.0,
+                BorderStyle::Solid => 1.0,
+                BorderStyle::Dashed => 2.0,
+            },
+            shadow: rect
+                .shadow
+                .map_or([0.0; 4], |s| [s.offset.0, s.offset.1, s.shrink, s.sigma]),
+            kind: match rect.shadow {
+                None => 0.0,
+                Some(ShadowShape { inset: false, .. }) => 1.0,
+                Some(ShadowShape { inset: true, .. }) => 2.0,
+            },
+            clip: local_clip(&rect.transform, rect.clip),
+        }
+    }
+}
+
+impl Instance {
+    /// The quad compositing a layer over its bounds, at the opacity of the group.
+    fn layer(vp: Matrix4<f32>, layer: &LayerItem) -> Instance {
+        let bounds = layer.bounds;
+        let rect = RectItem::solid(
+            Matrix4::from_translation([bounds.x, bounds.y, 0.0].into()),
+            (bounds.w, bounds.h),
+            [1.0, 1.0, 1.0, layer.opacity],
+            layer.clip,
+        );
+        let mut instance = Instance::new(vp * rect.transform, &rect, None, None);
+        instance.kind = 3.0;
+        instance
+    }
+
+    /// A glyph of `text`, sampled from a font bitmap of `atlas_size` texels.
+    fn glyph(
+        vp: Matrix4<f32>,
+        text: &TextItem,
+        quad: &GlyphQuad,
+        (atlas_w, atlas_h): (f32, f32),
+    ) -> Instance {
+        let rect = RectItem::solid(
+            quad.transform(&text.transform),
+            (quad.width as f32, quad.height as f32),
+            text.color,
+            text.clip,
+        );
+        let mut instance = Instance::new(vp * rect.transform, &rect, None, None);
+        instance.image = [
+            quad.tex_x as f32 / atlas_w,
+            quad.tex_y as f32 / atlas_h,
+            quad.width as f32 / atlas_w,
+            quad.height as f32 / atlas_h,
+        ];
+        instance.kind = 4.0;
+        instance
+    }
+}
+
+/// An offscreen target the size of the screen, holding a group painted with
+/// an opacity.
+pub struct Layer<R: gfx::Resources> {
+    view: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    target: gfx::handle::RenderTargetView<R, ColorFormat>,
+}
+
+/// A `Texture` resource uploaded to the GPU.
+pub struct GpuTexture<R: gfx::Resources> {
+    size: (f32, f32),
+    view: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+}
+
+/// Behind everything drawn.
+pub const CLEAR_COLOR: [f32; 4] = [0.1, 0.2, 0.3, 1.0];
+
+/// Resolution of a gradient ramp, and initial number of gradients drawn per
+/// frame, grown on demand.
+const GRADIENT_WIDTH: usize = 256;
+const GRADIENT_ROWS: usize = 64;
+
+/// Initial size of the instance buffer, grown on demand.
+const INSTANCE_CAPACITY: usize = 1024;
+
+fn create_instance_buffer<R: gfx::Resources, F: gfx::Factory<R>>(
+    factory: &mut F,
+    capacity: usize,
+) -> gfx::handle::Buffer<R, Instance> {
+    factory
+        .create_buffer(
+            capacity,
+            gfx::buffer::Role::Vertex,
+            gfx::memory::Usage::Dynamic,
+            gfx::memory::Bind::empty(),
+        )
+        .expect("instance buffer creation")
+}
+
+/// A texture of `rows` gradient ramps, and its view.
+fn create_gradient_texture<R: gfx::Resources, F: gfx::Factory<R>>(
+    factory: &mut F,
+    rows: usize,
+) -> (
+    gfx::handle::Texture<R, gfx::format::R8_G8_B8_A8>,
+    gfx::handle::ShaderResourceView<R, [f32; 4]>,
+) {
+    let gradients = factory
+        .create_texture::<gfx::format::R8_G8_B8_A8>(
+            texture::Kind::D2(GRADIENT_WIDTH as u16, rows as u16, texture::AaMode::Single),
+            1,
+            gfx::memory::Bind::SHADER_RESOURCE,
+            gfx::memory::Usage::Dynamic,
+            Some(gfx::format::ChannelType::Unorm),
+        )
+        .expect("gradient texture creation");
+    let view = factory
+        .view_texture_as_shader_resource::<gfx::format::Rgba8>(
+            &gradients,
+            (0, 0),
+            gfx::format::Swizzle::new(),
+        )
+        .expect("gradient texture view");
+    (gradients, view)
+}
+
 pub struct Renderer<R: gfx::Resources, F: Clone + gfx::Factory<R>> {
     factory: F,
     slice: gfx::Slice<R>,
     data: pipe::Data<R>,
     pso: Option<gfx::PipelineState<R, pipe::Meta>>,
-    version: u8,
-    text_version: u8,
-    text: Option<gfx_text::Renderer<R, F>>,
+    instance_capacity: usize,
+    gradients: gfx::handle::Texture<R, gfx::format::R8_G8_B8_A8>,
+    gradient_rows: usize,
+    /// The ramps uploaded to `gradients`.
+    ramps: Vec<[u8; 4]>,
+    /// The handle of each picture drawn, registered by the first frame
+    /// showing it.
+    pictures: HashMap<PathBuf, Handle<Texture>>,
+    /// Pictures uploaded from `Texture` resources, dropped when reloaded.
+    textures: HashMap<Handle<Texture>, GpuTexture<R>>,
+    reloads: Option<ReaderId<ResourceReloaded>>,
+    /// Pictures that failed to load or upload: tried again once reloaded.
+    missing: HashSet<Handle<Texture>>,
+    /// Bound when a batch has no picture.
+    white: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    /// One per nesting level of layers, allocated on demand.
+    layers: Vec<Layer<R>>,
+    /// The cube shader set, registered by the first frame.
+    shaders: Option<Handle<ShaderSet>>,
+    /// Whether `pso` is to be built from `shaders`: until it first loads,
+    /// then when it is reloaded.
+    shaders_changed: bool,
+    /// The font glyphs are drawn with, the built-in one once `FONT_PATH` fails.
+    font: Option<Handle<BitmapFont>>,
+    /// The bitmap of `font`, dropped when it is reloaded.
+    atlas: Option<GpuTexture<R>>,
+    /// Whether uploading `atlas` failed: tried again once `font` is reloaded.
+    atlas_failed: bool,
 }
 
 impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
@@ -137,24 +612,24 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
         window_targets: gfx_app::WindowTargets<R>,
     ) -> Self {
         use gfx::traits::FactoryExt;
-        println!(
+        info!(
             "size {:?} dpi factor {}",
             window_targets.size, window_targets.dpi_factor
         );
 
         let v = 1;
         let vertex_data = [
-            Vertex::new([0, 0, 0], [0, 0]),
-            Vertex::new([v, 0, 0], [1, 0]),
-            Vertex::new([v, v, 0], [1, 1]),
-            Vertex::new([0, v, 0], [0, 1]),
+            Vertex::new([0, 0, 0]),
+            Vertex::new([v, 0, 0]),
+            Vertex::new([v, v, 0]),
+            Vertex::new([0, v, 0]),
         ];
 
         let index_data: &[u16] = &[0, 1, 2, 2, 3, 0];
 
         let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertex_data, index_data);
 
-        let texels = [[0x20, 0xA0, 0xC0, 0xFF]];
+        let texels = [[0xFF, 0xFF, 0xFF, 0xFF]];
         let (_, texture_view) = factory
             .create_texture_immutable::<gfx::format::Rgba8>(
                 texture::Kind::D2(1, 1, texture::AaMode::Single),
@@ -166,14 +641,22 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
         let sinfo =
             texture::SamplerInfo::new(texture::FilterMethod::Bilinear, texture::WrapMode::Clamp);
 
+        let (gradients, gradients_view) = create_gradient_texture(&mut factory, GRADIENT_ROWS);
+
         let proj = cam(window_targets.size);
 
         let data = pipe::Data {
             vbuf: vbuf,
+            instances: create_instance_buffer(&mut factory, INSTANCE_CAPACITY),
             transform: (proj * default_view()).into(),
             screen: [window_targets.size.0 as f32, window_targets.size.1 as f32],
-            locals: factory.create_constant_buffer(1),
-            color: (texture_view, factory.create_sampler(sinfo)),
+            color: (texture_view.clone(), factory.create_sampler(sinfo)),
+            font: (texture_view.clone(), factory.create_sampler(sinfo)),
+            gradients: (gradients_view, factory.create_sampler(sinfo)),
+            scissor: scissor(
+                None,
+                [window_targets.size.0 as f32, window_targets.size.1 as f32],
+            ),
             out_color: window_targets.color,
             out_depth: window_targets.depth,
         };
@@ -183,9 +666,21 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
             slice,
             data,
             pso: None,
-            version: 0,
-            text_version: 0,
-            text: None,
+            instance_capacity: INSTANCE_CAPACITY,
+            gradients,
+            gradient_rows: GRADIENT_ROWS,
+            ramps: Vec::new(),
+            pictures: HashMap::new(),
+            textures: HashMap::new(),
+            reloads: None,
+            missing: HashSet::new(),
+            white: texture_view,
+            layers: Vec::new(),
+            shaders: None,
+            shaders_changed: true,
+            font: None,
+            atlas: None,
+            atlas_failed: false,
             // bundle: Bundle::new(slice, pso, data),
         }
     }
@@ -201,40 +696,126 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
 
         let store = res.fetch_mut::<crate::manager::ResourceManager>();
 
-        let dep = SimpleKey::Logical(("shader/cube.hlsl").into());
-        match store.get::<ShaderSet>(&dep) {
-            Ok(set) => {
-                let set = set.borrow_mut();
-                if set.version != self.version {
-                    self.version = set.version;
-                    self.pso = self
-                        .factory
-                        .create_pipeline_simple(&set.vx, &set.px, crate::rendering::pipe::new())
-                        .ok();
-                }
+        let mut diagnostics = res.fetch_mut::<Diagnostics>();
+
+        let cube_set = *self
+            .shaders
+            .get_or_insert_with(|| store.handle(&SimpleKey::Logical("shader/cube".into())));
+
+        let reloads = self.reloads.get_or_insert_with(|| {
+            res.fetch_mut::<EventChannel<ResourceReloaded>>()
+                .register_reader()
+        });
+        for reloaded in res.fetch::<EventChannel<ResourceReloaded>>().read(reloads) {
+            if reloaded.is::<ShaderSet>() && store.handle::<ShaderSet>(&reloaded.key) == cube_set {
+                self.shaders_changed = true;
+            }
+            if reloaded.is::<Texture>() {
+                let picture = store.handle::<Texture>(&reloaded.key);
+                self.textures.remove(&picture);
+                self.missing.remove(&picture);
             }
-            e => {
-                println!("Error {:?}", e);
+            if reloaded.is::<BitmapFont>() {
+                self.atlas = None;
+                self.atlas_failed = false;
             }
         }
 
-        let dep = SimpleKey::Logical(("shader/text.hlsl").into());
-
-        match store.get::<ShaderSet>(&dep) {
-            Ok(set) => {
-                let set = set.borrow_mut();
-                if set.version != self.text_version {
-                    self.text_version = set.version;
-
-                    let text = gfx_text::new(self.factory.clone()).build(&set.vx, &set.px);
-                    if text.is_err() {
-                        println!("{:?}", text.as_ref().err());
+        let name = Path::new("shader/cube");
+        if self.shaders_changed {
+            match store.resolve_async(cube_set) {
+                // the previous pipeline draws until the set is compiled
+                Ok(None) => (),
+                Ok(Some(set)) => {
+                    self.shaders_changed = false;
+                    let set = set.borrow();
+                    let mut errors = set.errors.clone();
+                    if errors.is_empty() {
+                        // a pipeline that fails leaves the previous one drawing
+                        match self.factory.create_pipeline_simple(
+                            &set.vx,
+                            &set.px,
+                            crate::rendering::pipe::new(),
+                        ) {
+                            Ok(pso) => self.pso = Some(pso),
+                            Err(e) => errors = pipeline_errors(name, &set, &e),
+                        }
                     }
-                    self.text = text.ok();
+                    diagnostics.report_shader(name, errors);
+                }
+                Err(e) => {
+                    self.shaders_changed = false;
+                    diagnostics.report_resource(&store.key(cube_set), Err(&e));
                 }
             }
-            e => {
-                println!("Error {:?}", e);
+        }
+
+        // upload the pictures of this frame that are new or were reloaded
+        let mut pictures = HashSet::new();
+        for path in res.fetch::<DisplayList>().rects().filter_map(texture_path) {
+            let picture = match self.pictures.get(path) {
+                Some(picture) => *picture,
+                None => {
+                    let picture = store.handle(&SimpleKey::Path(path.clone()));
+                    self.pictures.insert(path.clone(), picture);
+                    picture
+                }
+            };
+            pictures.insert(picture);
+        }
+        for picture in pictures {
+            self.upload_texture(&store, &mut diagnostics, picture);
+        }
+
+        let font = self.upload_font(&store, &mut diagnostics);
+        let font = font.as_ref().map(|font| font.borrow());
+        let font = font.as_ref().map(|font| &**font);
+
+        let quads = res
+            .fetch::<DisplayList>()
+            .items
+            .iter()
+            .map(|item| item.quads(font))
+            .sum::<usize>();
+        if quads > self.instance_capacity {
+            self.instance_capacity = quads.next_power_of_two();
+            self.data.instances = create_instance_buffer(&mut self.factory, self.instance_capacity);
+        }
+
+        let gradients = res
+            .fetch::<DisplayList>()
+            .rects()
+            .filter(|rect| match rect.image {
+                Some(BackgroundImage::Gradient(_)) => true,
+                _ => false,
+            })
+            .count();
+        if gradients > self.gradient_rows {
+            self.gradient_rows = gradients.next_power_of_two();
+            let (texture, view) = create_gradient_texture(&mut self.factory, self.gradient_rows);
+            self.gradients = texture;
+            self.data.gradients.0 = view;
+            self.ramps.clear();
+        }
+
+        let depth = res
+            .fetch::<DisplayList>()
+            .items
+            .iter()
+            .fold((0, 0), |(depth, max), item| match item {
+                DisplayItem::PushLayer => (depth + 1, max.max(depth + 1)),
+                DisplayItem::PopLayer(_) => (depth - 1, max),
+                _ => (depth, max),
+            })
+            .1;
+        while self.layers.len() < depth {
+            let (w, h) = (self.data.screen[0] as u16, self.data.screen[1] as u16);
+            match self.factory.create_render_target::<ColorFormat>(w, h) {
+                Ok((_, view, target)) => self.layers.push(Layer { view, target }),
+                Err(e) => {
+                    error!("cannot create a layer: {:?}", e);
+                    break;
+                }
             }
         }
 
@@ -243,9 +824,16 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
                 let mut sys = SysRender {
                     slice: &self.slice,
                     pso: pso,
-                    data: &self.data,
+                    data: &mut self.data,
+                    gradients: &self.gradients,
+                    gradient_rows: self.gradient_rows,
+                    ramps: &mut self.ramps,
+                    pictures: &self.pictures,
+                    textures: &self.textures,
+                    white: &self.white,
+                    layers: &self.layers,
                     encoder: encoder,
-                    text: &mut self.text,
+                    font: font.and_then(|font| self.atlas.as_ref().map(|atlas| (font, atlas))),
                 };
                 sys.run_now(res);
             }
@@ -253,9 +841,123 @@ impl<R: gfx::Resources, F: Clone + gfx::Factory<R>> Renderer<R, F> {
         }
     }
 
+    /// The font glyphs are drawn with, its bitmap uploaded as `atlas`; none
+    /// until it is loaded.
+    fn upload_font(
+        &mut self,
+        store: &crate::manager::ResourceManager,
+        diagnostics: &mut Diagnostics,
+    ) -> Option<warmy::Res<BitmapFont>> {
+        use crate::manager::SimpleKey;
+
+        let handle = *self
+            .font
+            .get_or_insert_with(|| store.handle(&SimpleKey::Path(crate::text::FONT_PATH.into())));
+        let font = match store.resolve_async(handle) {
+            Ok(font) => font?,
+            Err(_) => {
+                // reported by the `TextShapingSystem`, which falls back the same way
+                if store.key(handle) != crate::text::fallback_font() {
+                    self.font = Some(store.handle(&crate::text::fallback_font()));
+                }
+                return None;
+            }
+        };
+        if self.atlas.is_none() && !self.atlas_failed {
+            let font = font.borrow();
+            let bitmap = &font.0;
+            let (width, height) = (bitmap.get_width(), bitmap.get_height());
+            // the coverage is read from the red channel, like a picture
+            let pixels: Vec<u8> = bitmap
+                .get_image()
+                .iter()
+                .flat_map(|&c| vec![c, c, c, c])
+                .collect();
+            let kind = texture::Kind::D2(width, height, texture::AaMode::Single);
+            match self
+                .factory
+                .create_texture_immutable_u8::<gfx::format::Rgba8>(
+                    kind,
+                    texture::Mipmap::Provided,
+                    &[pixels.as_slice()],
+                ) {
+                Ok((_, view)) => {
+                    self.atlas = Some(GpuTexture {
+                        size: (f32::from(width), f32::from(height)),
+                        view,
+                    });
+                    diagnostics.report_resource(&store.key(handle), Ok(()));
+                }
+                Err(e) => {
+                    // the text is not drawn, rather than failing every frame
+                    self.atlas_failed = true;
+                    let e = crate::manager::Error::Upload(e.to_string());
+                    diagnostics.report_resource(&store.key(handle), Err(&e));
+                }
+            }
+        }
+        Some(font)
+    }
+
+    fn upload_texture(
+        &mut self,
+        store: &crate::manager::ResourceManager,
+        diagnostics: &mut Diagnostics,
+        picture: Handle<Texture>,
+    ) {
+        if self.textures.contains_key(&picture) || self.missing.contains(&picture) {
+            return;
+        }
+        let texture = match store.resolve_async(picture) {
+            // drawn without its picture until decoded
+            Ok(None) => return,
+            Ok(Some(texture)) => texture,
+            Err(e) => {
+                // drawn without its picture, rather than failing every frame
+                self.missing.insert(picture);
+                diagnostics.report_resource(&store.key(picture), Err(&e));
+                return;
+            }
+        };
+        let texture = texture.borrow();
+
+        // larger pictures are rejected when decoded
+        let kind = texture::Kind::D2(
+            texture.width as u16,
+            texture.height as u16,
+            texture::AaMode::Single,
+        );
+        match self
+            .factory
+            .create_texture_immutable_u8::<gfx::format::Rgba8>(
+                kind,
+                texture::Mipmap::Provided,
+                &[texture.pixels.as_slice()],
+            ) {
+            Ok((_, view)) => {
+                self.textures.insert(
+                    picture,
+                    GpuTexture {
+                        size: (texture.width as f32, texture.height as f32),
+                        view,
+                    },
+                );
+                diagnostics.report_resource(&store.key(picture), Ok(()));
+            }
+            Err(e) => {
+                self.missing.insert(picture);
+                let e = crate::manager::Error::Upload(e.to_string());
+                diagnostics.report_resource(&store.key(picture), Err(&e));
+            }
+        }
+    }
+
     pub fn on_resize(&mut self, window_targets: gfx_app::WindowTargets<R>) {
         self.data.out_color = window_targets.color;
         self.data.out_depth = window_targets.depth;
+        self.data.screen = [window_targets.size.0 as f32, window_targets.size.1 as f32];
+        // reallocated at the new size
+        self.layers.clear();
 
         // In this example the transform is static except for window resizes.
         let proj = cam(window_targets.size); // cgmath::perspective(Deg(45.0f32), window_targets.aspect_ratio, 1.0, 10.0);
@@ -0,0 +1,957 @@
+//! A CPU rasterizer for the `DisplayList`, painting the same rects, borders,
+//! shadows, pictures, glyphs and layers as `SysRender` into an RGBA8 buffer.
+//!
+//! It follows the quad shader step by step, but only with additions,
+//! multiplications, divisions, square roots and roundings, which IEEE 754
+//! defines exactly: the output is the same on every machine.
+
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::{FRAC_PI_2, PI, SQRT_2};
+use std::path::{Path, PathBuf};
+
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform};
+
+use crate::layout::BitmapFont;
+use crate::manager::{ResourceManager, SimpleKey};
+use crate::rendering::{
+    clip_coverage, color_to_f32, local_clip, texture_path, BackgroundImage, BorderRepeat, ClipRect,
+    DisplayItem, DisplayList, GradientShape, ImageFit, LayerItem, RectItem, Screen, TextItem,
+    Texture, CLEAR_COLOR,
+};
+use crate::styling::BorderStyle;
+
+type Color = [f32; 4];
+
+/// An RGBA8 picture, top row first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * self.width + x) as usize * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        image::save_buffer(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::RGBA(8),
+        )
+    }
+}
+
+/// Colors of a render target, blended like `BLEND_OVER`: straight on the
+/// screen, premultiplied in a layer cleared to transparent.
+struct Target {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Target {
+    fn new((width, height): (u32, u32), clear: Color) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        Target {
+            width,
+            height,
+            pixels: vec![clear; width * height],
+        }
+    }
+
+    fn blend(&mut self, x: usize, y: usize, src: Color) {
+        let a = saturate(src[3]);
+        let dst = &mut self.pixels[y * self.width + x];
+        for (d, s) in dst.iter_mut().zip(src.iter()).take(3) {
+            *d = saturate(*s) * a + *d * (1.0 - a);
+        }
+        dst[3] = a + dst[3] * (1.0 - a);
+    }
+
+    /// The pixels whose center is inside `bounds` and `clip`, as the first
+    /// and past the last column and row.
+    fn span(&self, bounds: &ClipRect, clip: Option<ClipRect>) -> (usize, usize, usize, usize) {
+        let screen = ClipRect {
+            x: 0.0,
+            y: 0.0,
+            w: self.width as f32,
+            h: self.height as f32,
+            shape: None,
+        };
+        let mut area = bounds.intersect(&screen);
+        if let Some(clip) = clip {
+            area = area.intersect(&clip);
+        }
+        let pixel = |v: f32| (v - 0.5).ceil().max(0.0) as usize;
+        let (x0, y0) = (pixel(area.x), pixel(area.y));
+        (
+            x0,
+            y0,
+            pixel(area.x + area.w).max(x0),
+            pixel(area.y + area.h).max(y0),
+        )
+    }
+
+    fn into_canvas(self) -> Canvas {
+        let pixels = self
+            .pixels
+            .iter()
+            .flat_map(|c| c.iter().map(|v| (saturate(*v) * 255.0 + 0.5) as u8))
+            .collect();
+        Canvas {
+            width: self.width as u32,
+            height: self.height as u32,
+            pixels,
+        }
+    }
+}
+
+/// Paints the display list without a GPU, for tests and servers.
+#[derive(Default)]
+pub struct SoftwareRenderer {
+    /// Pictures copied from `Texture` resources, with their generation.
+    pictures: HashMap<PathBuf, Texture>,
+    /// Pictures that failed to load, reported once.
+    missing: HashSet<PathBuf>,
+}
+
+impl SoftwareRenderer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Makes a picture available to the rects showing `path`, without going
+    /// through the resource manager.
+    pub fn insert_picture(&mut self, path: PathBuf, picture: Texture) {
+        self.pictures.insert(path, picture);
+    }
+
+    /// Paints the `DisplayList` at the size of the `Screen`, with the pictures
+    /// and font of the `ResourceManager`.
+    pub fn render(&mut self, res: &specs::Resources) -> Canvas {
+        let store = res.fetch::<ResourceManager>();
+        let list = res.fetch::<DisplayList>();
+
+        let paths: HashSet<PathBuf> = list.rects().filter_map(texture_path).cloned().collect();
+        for path in paths {
+            self.load_picture(&store, path);
+        }
+
+        let font = store
+            .get::<BitmapFont>(&SimpleKey::Path(crate::text::FONT_PATH.into()))
+            .or_else(|_| store.get::<BitmapFont>(&crate::text::fallback_font()))
+            .ok();
+        let font = font.as_ref().map(|font| font.borrow());
+        self.paint(
+            &list,
+            res.fetch::<Screen>().size,
+            font.as_ref().map(|font| &**font),
+        )
+    }
+
+    fn load_picture(&mut self, store: &ResourceManager, path: PathBuf) {
+        let texture = match store.get::<Texture>(&SimpleKey::Path(path.clone())) {
+            Ok(texture) => texture,
+            e => {
+                if self.missing.insert(path) {
+                    warn!("{:?}", e);
+                }
+                return;
+            }
+        };
+        self.missing.remove(&path);
+        let texture = texture.borrow();
+        if let Some(copied) = self.pictures.get(&path) {
+            if copied.generation == texture.generation {
+                return;
+            }
+        }
+        self.pictures.insert(
+            path,
+            Texture {
+                generation: texture.generation,
+                width: texture.width,
+                height: texture.height,
+                pixels: texture.pixels.clone(),
+            },
+        );
+    }
+
+    /// Paints `list` on a `size` canvas cleared to the background color.
+    /// Glyphs are skipped without a `font`.
+    pub fn paint(&self, list: &DisplayList, size: (u32, u32), font: Option<&BitmapFont>) -> Canvas {
+        // the screen, then one target per open layer
+        let mut targets = vec![Target::new(size, CLEAR_COLOR)];
+        for item in list.items.iter() {
+            match item {
+                DisplayItem::Rect(rect) => self.fill_rect(targets.last_mut().unwrap(), rect),
+                DisplayItem::Text(text) => {
+                    if let Some(font) = font {
+                        fill_text(targets.last_mut().unwrap(), text, font);
+                    }
+                }
+                DisplayItem::PushLayer => targets.push(Target::new(size, [0.0; 4])),
+                DisplayItem::PopLayer(layer) => {
+                    if targets.len() > 1 {
+                        let group = targets.pop().unwrap();
+                        composite(targets.last_mut().unwrap(), &group, layer);
+                    }
+                }
+            }
+        }
+        targets.swap_remove(0).into_canvas()
+    }
+
+    fn fill_rect(&self, target: &mut Target, rect: &RectItem) {
+        let inverse = match rect.transform.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let picture = texture_path(rect).and_then(|path| self.pictures.get(path));
+        let bounds = ClipRect::from_transform(&rect.transform, rect.size);
+        let clip = local_clip(&rect.transform, rect.clip);
+        let (x0, y0, x1, y1) = target.span(&bounds, rect.clip);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = inverse.transform_point(Point3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0));
+                if p.x < 0.0 || p.y < 0.0 || p.x > rect.size.0 || p.y > rect.size.1 {
+                    continue;
+                }
+                let mut color = shade(rect, (p.x, p.y), picture);
+                color[3] *= clip_coverage((p.x, p.y), clip);
+                target.blend(x, y, color);
+            }
+        }
+    }
+}
+
+/// Blends a layer over `target`, within its bounds, at the group opacity.
+fn composite(target: &mut Target, group: &Target, layer: &LayerItem) {
+    let (x0, y0, x1, y1) = target.span(&layer.bounds, layer.clip);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let c = group.pixels[y * group.width + x];
+            let a = c[3].max(0.0001);
+            target.blend(x, y, [c[0] / a, c[1] / a, c[2] / a, c[3] * layer.opacity]);
+        }
+    }
+}
+
+/// Copies the glyphs from the font bitmap, as the glyph quads of the quad
+/// shader: transformed and clipped like rects, down to the pixel.
+fn fill_text(target: &mut Target, text: &TextItem, font: &BitmapFont) {
+    let atlas = font.0.get_image();
+    let (atlas_w, atlas_h) = (
+        i32::from(font.0.get_width()),
+        i32::from(font.0.get_height()),
+    );
+    for quad in text.quads(font) {
+        let transform = quad.transform(&text.transform);
+        let inverse = match transform.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let size = (quad.width as f32, quad.height as f32);
+        let bounds = ClipRect::from_transform(&transform, size);
+        let clip = local_clip(&transform, text.clip);
+        let (x0, y0, x1, y1) = target.span(&bounds, text.clip);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let p = inverse.transform_point(Point3::new(px as f32 + 0.5, py as f32 + 0.5, 0.0));
+                if p.x < 0.0 || p.y < 0.0 || p.x >= size.0 || p.y >= size.1 {
+                    continue;
+                }
+                // the nearest texel, as the quad shader samples the bitmap
+                let (gx, gy) = (p.x as i32, p.y as i32);
+                let (tx, ty) = (quad.tex_x + gx, quad.tex_y + gy);
+                if tx < 0 || ty < 0 || tx >= atlas_w || ty >= atlas_h {
+                    continue;
+                }
+                let coverage = atlas[(ty * atlas_w + tx) as usize];
+                let mut color = text.color;
+                color[3] *= f32::from(coverage) / 255.0 * clip_coverage((p.x, p.y), clip);
+                target.blend(px, py, color);
+            }
+        }
+    }
+}
+
+/// The color of `rect` at `local`, in pixels from its top left corner: the
+/// `Pixel` function of the quad shader.
+fn shade(rect: &RectItem, local: (f32, f32), picture: Option<&Texture>) -> Color {
+    let (w, h) = rect.size;
+    let half = (w * 0.5, h * 0.5);
+    let centered = (local.0 - half.0, local.1 - half.1);
+    let outer = rounded_box(centered, half, rect.radius);
+
+    if let Some(shadow) = rect.shadow {
+        let shape_half = (
+            (half.0 - shadow.shrink).max(0.0),
+            (half.1 - shadow.shrink).max(0.0),
+        );
+        let mut shape_radius = rect.radius;
+        if shadow.inset {
+            for r in shape_radius.iter_mut() {
+                *r = (*r - shadow.shrink).max(0.0);
+            }
+        }
+        let shape = rounded_box(
+            (centered.0 - shadow.offset.0, centered.1 - shadow.offset.1),
+            shape_half,
+            shape_radius,
+        );
+        let mut coverage = blurred_coverage(shape, shadow.sigma);
+        if shadow.inset {
+            coverage = (1.0 - coverage) * saturate(0.5 - outer);
+        } else {
+            // not under the box casting the shadow
+            let [top, right, bottom, left] = shadow.mask;
+            let box_half = (
+                (w - right - left).max(0.0) * 0.5,
+                (h - top - bottom).max(0.0) * 0.5,
+            );
+            let inside = rounded_box(
+                (local.0 - left - box_half.0, local.1 - top - box_half.1),
+                box_half,
+                shadow.mask_radius,
+            );
+            coverage *= saturate(0.5 + inside);
+        }
+        let mut color = rect.color;
+        color[3] *= coverage;
+        return color;
+    }
+
+    // the padding box: widths are top, right, bottom, left
+    let [top, right, bottom, left] = rect.border;
+    let inner_half = (
+        (w - right - left).max(0.0) * 0.5,
+        (h - top - bottom).max(0.0) * 0.5,
+    );
+    let r = rect.radius;
+    let inner_radius = [
+        (r[0] - left.max(top)).max(0.0),
+        (r[1] - top.max(right)).max(0.0),
+        (r[2] - right.max(bottom)).max(0.0),
+        (r[3] - bottom.max(left)).max(0.0),
+    ];
+    let inner = rounded_box(
+        (local.0 - left - inner_half.0, local.1 - top - inner_half.1),
+        inner_half,
+        inner_radius,
+    );
+
+    let has_border = rect.border.iter().any(|b| *b > 0.0);
+    let mut border = if rect.border_style != BorderStyle::None && has_border {
+        saturate(0.5 + inner)
+    } else {
+        0.0
+    };
+    if rect.border_style == BorderStyle::Dashed {
+        // dashes three times as long as the border is wide, along the closest edge
+        let edges = [local.1, w - local.0, h - local.1, local.0];
+        let closest = edges[0].min(edges[2]).min(edges[1].min(edges[3]));
+        let horizontal = closest == edges[0] || closest == edges[2];
+        let width = if horizontal {
+            top.max(bottom)
+        } else {
+            right.max(left)
+        }
+        .max(1.0);
+        let along = if horizontal { local.0 } else { local.1 };
+        if along % (width * 6.0) > width * 3.0 {
+            border = 0.0;
+        }
+    }
+
+    let mut background = rect.color;
+    if let Some(g) = image_color(rect, local, picture) {
+        // the image is composited over the background color
+        let a = g[3] + background[3] * (1.0 - g[3]);
+        let coverage = background[3] * (1.0 - g[3]);
+        for (b, c) in background.iter_mut().zip(g.iter()).take(3) {
+            *b = (c * g[3] + *b * coverage) / a.max(0.0001);
+        }
+        background[3] = a;
+    }
+
+    let mut color = lerp(background, rect.border_color, border);
+    color[3] *= saturate(0.5 - outer);
+    color
+}
+
+/// The gradient or picture of `rect` at `local`, if it has one.
+fn image_color(rect: &RectItem, local: (f32, f32), picture: Option<&Texture>) -> Option<Color> {
+    let (w, h) = rect.size;
+    let half = (w * 0.5, h * 0.5);
+    let p = (local.0 - half.0, local.1 - half.1);
+    match rect.image.as_ref()? {
+        BackgroundImage::Gradient(gradient) => {
+            let t = match gradient.shape {
+                GradientShape::Linear { angle } => {
+                    // the gradient line goes through the center and reaches the corners
+                    let (sin, cos) = sin_cos(angle);
+                    let dir = (sin, -cos);
+                    let len = (w * dir.0).abs() + (h * dir.1).abs();
+                    (p.0 * dir.0 + p.1 * dir.1) / len.max(1.0) + 0.5
+                }
+                GradientShape::Radial { circle: true } => length(p) / length(half).max(1.0),
+                // an ellipse with the aspect of the box through its corners
+                GradientShape::Radial { circle: false } => length((
+                    p.0 / (half.0 * SQRT_2).max(1.0),
+                    p.1 / (half.1 * SQRT_2).max(1.0),
+                )),
+            };
+            Some(color_to_f32(gradient.sample(saturate(t))))
+        }
+        BackgroundImage::Texture(_) => {
+            let picture = picture?;
+            let size = (picture.width as f32, picture.height as f32);
+            match rect.image_fit {
+                ImageFit::Slice {
+                    slice,
+                    fill,
+                    repeat,
+                } => Some(nine_slice(rect, local, picture, slice, fill, repeat)),
+                fit => {
+                    let (x, y, pw, ph) = fit.place(size, rect.size);
+                    let u = (local.0 - x) / pw.max(1.0);
+                    let v = (local.1 - y) / ph.max(1.0);
+                    // no repeat: nothing is drawn outside the picture
+                    if u < 0.0 || v < 0.0 || u > 1.0 || v > 1.0 {
+                        Some([0.0; 4])
+                    } else {
+                        Some(sample(picture, (u, v)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A border image: `slice` in picture pixels (top, right, bottom, left).
+fn nine_slice(
+    rect: &RectItem,
+    p: (f32, f32),
+    picture: &Texture,
+    s: [f32; 4],
+    fill: bool,
+    repeat: BorderRepeat,
+) -> Color {
+    let (w, h) = (picture.width as f32, picture.height as f32);
+    let b = rect.border;
+    let (width, height) = rect.size;
+    let region = |p: f32, size: f32, b0: f32, b1: f32| {
+        if p < b0 {
+            0
+        } else if p >= size - b1 {
+            2
+        } else {
+            1
+        }
+    };
+    let rx = region(p.0, width, b[3], b[1]);
+    let ry = region(p.1, height, b[0], b[2]);
+    if rx == 1 && ry == 1 && !fill {
+        return [0.0; 4];
+    }
+
+    // tiles keep the scale of the border they run along
+    let scale_x = if ry == 2 {
+        b[2] / s[2].max(1.0)
+    } else {
+        b[0] / s[0].max(1.0)
+    };
+    let scale_y = if rx == 2 {
+        b[1] / s[1].max(1.0)
+    } else {
+        b[3] / s[3].max(1.0)
+    };
+    let u = slice_axis(
+        p.0,
+        width,
+        (b[3], b[1]),
+        (s[3], s[1]),
+        w,
+        rx,
+        scale_x,
+        repeat,
+    );
+    let v = slice_axis(
+        p.1,
+        height,
+        (b[0], b[2]),
+        (s[0], s[2]),
+        h,
+        ry,
+        scale_y,
+        repeat,
+    );
+    sample(picture, (u / w, v / h))
+}
+
+/// The picture texel along one axis of a sliced box, as `SliceAxis` in the shader.
+#[allow(clippy::too_many_arguments)]
+fn slice_axis(
+    p: f32,
+    size: f32,
+    (b0, b1): (f32, f32),
+    (s0, s1): (f32, f32),
+    picture: f32,
+    region: u8,
+    scale: f32,
+    repeat: BorderRepeat,
+) -> f32 {
+    match region {
+        0 => return p / b0 * s0,
+        2 => return picture - s1 + (p - (size - b1)) / b1.max(0.0001) * s1,
+        _ => {}
+    }
+    let span = (size - b0 - b1).max(0.0001);
+    let middle = (picture - s0 - s1).max(0.0);
+    let tile = (middle * scale).max(1.0);
+    let f = match repeat {
+        BorderRepeat::Stretch => (p - b0) / span,
+        // with a whole tile at the center
+        BorderRepeat::Repeat => fract((p - b0 - span * 0.5) / tile + 0.5),
+        BorderRepeat::Round => {
+            let count = (span / tile).round().max(1.0);
+            fract((p - b0) * count / span)
+        }
+    };
+    s0 + f * middle
+}
+
+/// Bilinear filtering with clamped edges, like the sampler of the pictures.
+fn sample(picture: &Texture, (u, v): (f32, f32)) -> Color {
+    let (w, h) = (picture.width as usize, picture.height as usize);
+    if w == 0 || h == 0 || picture.pixels.len() < w * h * 4 {
+        return [0.0; 4];
+    }
+    let x = u * w as f32 - 0.5;
+    let y = v * h as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let texel = |tx: f32, ty: f32| {
+        let tx = tx.max(0.0).min((w - 1) as f32) as usize;
+        let ty = ty.max(0.0).min((h - 1) as f32) as usize;
+        let i = (ty * w + tx) * 4;
+        let c = &picture.pixels[i..i + 4];
+        [
+            f32::from(c[0]) / 255.0,
+            f32::from(c[1]) / 255.0,
+            f32::from(c[2]) / 255.0,
+            f32::from(c[3]) / 255.0,
+        ]
+    };
+    let top = lerp(texel(x0, y0), texel(x0 + 1.0, y0), x - x0);
+    let bottom = lerp(texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0), x - x0);
+    lerp(top, bottom, y - y0)
+}
+
+/// Signed distance from `p` to a box of half extents `half_size` centered on
+/// the origin, with the radii of the top left, top right, bottom right and
+/// bottom left corners.
+fn rounded_box(p: (f32, f32), half_size: (f32, f32), radii: [f32; 4]) -> f32 {
+    let r = match (p.0 < 0.0, p.1 < 0.0) {
+        (true, true) => radii[0],
+        (false, true) => radii[1],
+        (false, false) => radii[2],
+        (true, false) => radii[3],
+    };
+    let r = r.min(half_size.0.min(half_size.1));
+    let q = (p.0.abs() - half_size.0 + r, p.1.abs() - half_size.1 + r);
+    q.0.max(q.1).min(0.0) + length((q.0.max(0.0), q.1.max(0.0))) - r
+}
+
+/// Abramowitz and Stegun approximation, good to 5e-4.
+fn erf(x: f32) -> f32 {
+    let s = x.signum();
+    let a = x.abs();
+    let mut t = 1.0 + (0.278_393 + (0.230_389 + 0.078_108 * a * a) * a) * a;
+    t *= t;
+    s - s / (t * t)
+}
+
+/// Coverage of a shape at signed distance `d` once blurred by a gaussian of
+/// deviation `sigma`, blurring across the closest edge only.
+fn blurred_coverage(d: f32, sigma: f32) -> f32 {
+    0.5 - 0.5 * erf(d / (sigma.max(0.3) * SQRT_2))
+}
+
+/// Sine and cosine from a polynomial: the ones of the platform's math library
+/// may differ in their last bits.
+fn sin_cos(angle: f32) -> (f32, f32) {
+    (sin(angle), sin(angle + FRAC_PI_2))
+}
+
+fn sin(x: f32) -> f32 {
+    // within half a turn, then a quarter turn, where sin(x) = sin(pi - x)
+    let mut x = x - (x / (2.0 * PI)).round() * (2.0 * PI);
+    if x > FRAC_PI_2 {
+        x = PI - x;
+    } else if x < -FRAC_PI_2 {
+        x = -PI - x;
+    }
+    let x2 = x * x;
+    x * (1.0
+        - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0 * (1.0 - x2 / 110.0)))))
+}
+
+fn length(v: (f32, f32)) -> f32 {
+    (v.0 * v.0 + v.1 * v.1).sqrt()
+}
+
+fn saturate(v: f32) -> f32 {
+    v.max(0.0).min(1.0)
+}
+
+fn fract(v: f32) -> f32 {
+    v - v.floor()
+}
+
+fn lerp(a: Color, b: Color, t: f32) -> Color {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::Fxc;
+    use crate::rendering::{Gradient, GradientStop, ShadowShape};
+    use crate::text::ShapedGlyph;
+    use cgmath::Vector4;
+    use std::sync::Arc;
+
+    fn rect(x: f32, y: f32, size: (f32, f32), color: Color, clip: Option<ClipRect>) -> DisplayItem {
+        DisplayItem::Rect(RectItem::solid(
+            Matrix4::from_translation([x, y, 0.0].into()),
+            size,
+            color,
+            clip,
+        ))
+    }
+
+    #[test]
+    fn clipped_rect() {
+        let clip = ClipRect {
+            x: 0.0,
+            y: 0.0,
+            w: 4.0,
+            h: 8.0,
+            shape: None,
+        };
+        let list = DisplayList {
+            items: vec![rect(2.0, 2.0, (4.0, 4.0), [1.0, 0.0, 0.0, 1.0], Some(clip))],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (8, 8), None);
+        let clear = canvas.pixel(0, 0);
+        assert_eq!(canvas.pixel(3, 3), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(4, 3), clear);
+        assert_eq!(canvas.pixel(1, 3), clear);
+        assert_eq!(canvas.pixel(3, 6), clear);
+    }
+
+    #[test]
+    fn rotated_clip() {
+        // a 4x4 box turned by 45 degrees around (8, 2) clips a child turned
+        // with it, not to its bounding rectangle
+        let turned = Matrix4::from_translation([8.0, 2.0, 0.0].into())
+            * Matrix4::from_angle_z(cgmath::Deg(45.0));
+        let clip = ClipRect::from_box(&turned, (4.0, 4.0));
+        assert!(clip.shape.is_some());
+        let child = RectItem::solid(turned, (8.0, 8.0), [1.0, 0.0, 0.0, 1.0], Some(clip));
+        let list = DisplayList {
+            items: vec![DisplayItem::Rect(child)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (16, 16), None);
+        assert_eq!(canvas.pixel(8, 4), [255, 0, 0, 255]);
+        // inside the child and the bounds of the box, outside the box
+        assert_eq!(canvas.pixel(10, 6), canvas.pixel(0, 0));
+        assert!(clip.contains(8.5, 4.5));
+        assert!(!clip.contains(10.5, 6.5));
+    }
+
+    #[test]
+    fn opacity_group() {
+        let screen = ClipRect {
+            x: 0.0,
+            y: 0.0,
+            w: 8.0,
+            h: 8.0,
+            shape: None,
+        };
+        let list = DisplayList {
+            items: vec![
+                DisplayItem::PushLayer,
+                rect(0.0, 0.0, (4.0, 8.0), [0.0, 1.0, 0.0, 1.0], None),
+                rect(2.0, 0.0, (4.0, 8.0), [1.0, 0.0, 0.0, 1.0], None),
+                DisplayItem::PopLayer(LayerItem {
+                    opacity: 0.5,
+                    bounds: screen,
+                    clip: None,
+                }),
+            ],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (8, 8), None);
+        // the green rect does not show through the red one
+        assert_eq!(canvas.pixel(3, 0), canvas.pixel(5, 0));
+        assert_ne!(canvas.pixel(3, 0), [255, 0, 0, 255]);
+        assert_ne!(canvas.pixel(3, 0), canvas.pixel(7, 0));
+        assert_eq!(canvas, SoftwareRenderer::new().paint(&list, (8, 8), None));
+    }
+
+    #[test]
+    fn outer_shadow_masked() {
+        // the sharp shadow of a 4x4 box at (2, 2), cast 2 pixels down and right
+        let mut shadow = RectItem::solid(
+            Matrix4::from_translation([4.0, 4.0, 0.0].into()),
+            (4.0, 4.0),
+            [0.0, 0.0, 0.0, 1.0],
+            None,
+        );
+        shadow.shadow = Some(ShadowShape {
+            offset: (0.0, 0.0),
+            shrink: 0.0,
+            sigma: 0.0,
+            inset: false,
+            mask: [-2.0, 2.0, 2.0, -2.0],
+            mask_radius: [0.0; 4],
+        });
+        let list = DisplayList {
+            items: vec![DisplayItem::Rect(shadow)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (8, 8), None);
+        let clear = canvas.pixel(0, 0);
+        // under the box
+        assert_eq!(canvas.pixel(5, 5), clear);
+        assert_ne!(canvas.pixel(6, 6), clear);
+    }
+
+    #[test]
+    fn borders() {
+        let mut solid = RectItem::solid(
+            Matrix4::from_translation([0.0, 0.0, 0.0].into()),
+            (8.0, 8.0),
+            [1.0, 0.0, 0.0, 1.0],
+            None,
+        );
+        solid.border = [2.0; 4];
+        solid.border_color = [0.0, 0.0, 1.0, 1.0];
+        solid.border_style = BorderStyle::Solid;
+        let mut dashed = RectItem::solid(
+            Matrix4::from_translation([0.0, 8.0, 0.0].into()),
+            (12.0, 8.0),
+            [1.0, 0.0, 0.0, 1.0],
+            None,
+        );
+        dashed.border = [1.0; 4];
+        dashed.border_color = [0.0, 1.0, 0.0, 1.0];
+        dashed.border_style = BorderStyle::Dashed;
+        let list = DisplayList {
+            items: vec![DisplayItem::Rect(solid), DisplayItem::Rect(dashed)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (12, 16), None);
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        assert_eq!(canvas.pixel(0, 0), blue);
+        assert_eq!(canvas.pixel(1, 6), blue);
+        assert_eq!(canvas.pixel(2, 2), red);
+        assert_eq!(canvas.pixel(5, 5), red);
+        // dashes three pixels long, three apart
+        assert_eq!(canvas.pixel(1, 8), green);
+        assert_eq!(canvas.pixel(4, 8), red);
+        assert_eq!(canvas.pixel(7, 8), green);
+    }
+
+    #[test]
+    fn picture() {
+        let path = PathBuf::from("picture.png");
+        let mut renderer = SoftwareRenderer::new();
+        renderer.insert_picture(
+            path.clone(),
+            Texture {
+                generation: 1,
+                width: 2,
+                height: 2,
+                pixels: vec![
+                    255, 0, 0, 255, 0, 255, 0, 255, //
+                    0, 0, 255, 255, 255, 255, 255, 255,
+                ],
+            },
+        );
+        let mut rect = RectItem::solid(
+            Matrix4::from_translation([0.0, 0.0, 0.0].into()),
+            (8.0, 8.0),
+            [0.0; 4],
+            None,
+        );
+        rect.image = Some(BackgroundImage::Texture(path));
+        rect.image_fit = ImageFit::Fill;
+        let list = DisplayList {
+            items: vec![DisplayItem::Rect(rect)],
+            order: Vec::new(),
+        };
+        let canvas = renderer.paint(&list, (8, 8), None);
+        assert_eq!(canvas.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(7, 0), [0, 255, 0, 255]);
+        assert_eq!(canvas.pixel(0, 7), [0, 0, 255, 255]);
+        assert_eq!(canvas.pixel(7, 7), [255, 255, 255, 255]);
+        // filtered between the texel centers
+        assert_eq!(canvas.pixel(3, 0), [159, 96, 0, 255]);
+    }
+
+    #[test]
+    fn linear_gradient() {
+        let stop = |v: u8, position: f32| GradientStop {
+            color: Vector4::new(v, v, v, 255),
+            position,
+        };
+        let mut rect = RectItem::solid(
+            Matrix4::from_translation([0.0, 0.0, 0.0].into()),
+            (2.0, 8.0),
+            [0.0; 4],
+            None,
+        );
+        // black at the bottom, white at the top
+        rect.image = Some(BackgroundImage::Gradient(Gradient {
+            shape: GradientShape::Linear { angle: 0.0 },
+            stops: vec![stop(0, 0.0), stop(255, 1.0)],
+        }));
+        let list = DisplayList {
+            items: vec![DisplayItem::Rect(rect)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (2, 8), None);
+        assert_eq!(canvas.pixel(0, 7), [15, 15, 15, 255]);
+        assert_eq!(canvas.pixel(1, 0), [239, 239, 239, 255]);
+        assert_eq!(canvas.pixel(0, 3), canvas.pixel(1, 3));
+    }
+
+    #[test]
+    fn clipped_glyph() {
+        let store = ResourceManager::new(Arc::new(Fxc));
+        crate::text::register_fallback_font(&store);
+        let font = store
+            .get::<BitmapFont>(&crate::text::fallback_font())
+            .unwrap();
+        let font = font.borrow();
+        let glyph = ShapedGlyph {
+            ch: 'H',
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            cluster: 0,
+        };
+        let mut text = TextItem {
+            transform: Matrix4::from_translation([16.0, 16.0, 0.0].into()),
+            glyphs: vec![glyph],
+            color: [1.0; 4],
+            clip: None,
+        };
+        let mut quad = text.quads(&font).next().unwrap();
+        // on screen, with the origin of the run
+        quad.x += 16;
+        quad.y += 16;
+        // the left half of the glyph
+        let half = quad.x + quad.width / 2;
+        text.clip = Some(ClipRect {
+            x: 0.0,
+            y: 0.0,
+            w: half as f32,
+            h: 64.0,
+            shape: None,
+        });
+        let list = DisplayList {
+            items: vec![DisplayItem::Text(text)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (64, 64), Some(&font));
+        let clear = canvas.pixel(0, 0);
+
+        let atlas = font.0.get_image();
+        let atlas_w = i32::from(font.0.get_width());
+        let mut inked = 0;
+        for y in quad.y..quad.y + quad.height {
+            for x in quad.x..quad.x + quad.width {
+                let pixel = canvas.pixel(x as u32, y as u32);
+                let texel = (quad.tex_y + y - quad.y) * atlas_w + quad.tex_x + x - quad.x;
+                if x >= half {
+                    assert_eq!(pixel, clear);
+                } else if atlas[texel as usize] == 255 {
+                    assert_eq!(pixel, [255; 4]);
+                    inked += 1;
+                }
+            }
+        }
+        // the glyph is drawn up to the clip, not dropped
+        assert!(inked > 0);
+    }
+
+    #[test]
+    fn scaled_glyph() {
+        let store = ResourceManager::new(Arc::new(Fxc));
+        crate::text::register_fallback_font(&store);
+        let font = store
+            .get::<BitmapFont>(&crate::text::fallback_font())
+            .unwrap();
+        let font = font.borrow();
+        let glyph = ShapedGlyph {
+            ch: 'H',
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            cluster: 0,
+        };
+        let text = TextItem {
+            transform: Matrix4::from_translation([8.0, 8.0, 0.0].into()) * Matrix4::from_scale(2.0),
+            glyphs: vec![glyph],
+            color: [1.0; 4],
+            clip: None,
+        };
+        let quad = text.quads(&font).next().unwrap();
+        let list = DisplayList {
+            items: vec![DisplayItem::Text(text)],
+            order: Vec::new(),
+        };
+        let canvas = SoftwareRenderer::new().paint(&list, (64, 64), Some(&font));
+
+        let atlas = font.0.get_image();
+        let atlas_w = i32::from(font.0.get_width());
+        let mut inked = 0;
+        for y in 0..quad.height {
+            for x in 0..quad.width {
+                let texel = (quad.tex_y + y) * atlas_w + quad.tex_x + x;
+                if atlas[texel as usize] == 255 {
+                    // each texel covers 2 by 2 pixels
+                    let (px, py) = (8 + 2 * (quad.x + x), 8 + 2 * (quad.y + y));
+                    assert_eq!(canvas.pixel(px as u32, py as u32), [255; 4]);
+                    assert_eq!(canvas.pixel(px as u32 + 1, py as u32 + 1), [255; 4]);
+                    inked += 1;
+                }
+            }
+        }
+        assert!(inked > 0);
+    }
+}
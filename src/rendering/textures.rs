@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use specs::prelude::*;
+
+use crate::manager::*;
+
+/// An RGBA8 image decoded by the `image` crate, reloaded when the file changes.
+#[derive(Debug)]
+pub struct Texture {
+    /// Incremented by each reload, starting from 1.
+    pub generation: u64,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Load<Ctx, SimpleKey> for Texture {
+    type Error = Error;
+
+    fn load(
+        key: SimpleKey,
+        _storage: &mut Storage<Ctx, SimpleKey>,
+        ctx: &mut Ctx,
+    ) -> Result<Loaded<Self, SimpleKey>, Error> {
+        let texture = match ctx.take_prepared::<Texture>() {
+            Some(texture) => texture,
+            None => Self::prepare(&key, ctx)?,
+        };
+        Ok(Loaded::without_dep(texture))
+    }
+
+    fn reload(
+        &self,
+        key: SimpleKey,
+        storage: &mut Storage<Ctx, SimpleKey>,
+        ctx: &mut Ctx,
+    ) -> Result<Self, Error> {
+        let prev = storage.get_by::<Texture, AlwaysFail>(&key, ctx, AlwaysFail);
+        let prev_generation = prev.map(|x| x.borrow().generation).unwrap_or(0);
+        let l: Result<Loaded<Self, SimpleKey>, Error> =
+            <Texture as warmy::load::Load<Ctx, SimpleKey, ()>>::load(key.clone(), storage, ctx);
+        l.map(|mut lr| {
+            lr.res.generation = prev_generation + 1;
+            ctx.reloaded::<Self>(key);
+            lr.res
+        })
+    }
+}
+
+impl Load<Ctx, SimpleKey, AlwaysFail> for Texture {
+    type Error = Error;
+
+    fn load(
+        _key: SimpleKey,
+        _storage: &mut Storage<Ctx, SimpleKey>,
+        _ctx: &mut Ctx,
+    ) -> Result<Loaded<Self, SimpleKey>, Self::Error> {
+        Err(Error::CannotLoadFromFS)
+    }
+}
+
+/// Decoding is the slow part: it runs on the loading thread.
+impl Prepare for Texture {
+    type Prepared = Texture;
+
+    fn prepare(key: &SimpleKey, ctx: &Ctx) -> Result<Texture, Error> {
+        debug!("load texture {}", key);
+        let bytes = ctx.sources().read(key)?;
+        let image = image::load_from_memory(&bytes)
+            .map_err(Error::ImageError)?
+            .to_rgba();
+        let (width, height) = image.dimensions();
+        // the size of a GPU texture is 16 bits
+        if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+            return Err(Error::ImageError(image::ImageError::DimensionError));
+        }
+        Ok(Texture {
+            generation: 1,
+            width,
+            height,
+            pixels: image.into_raw(),
+        })
+    }
+}
+
+/// An element showing a picture. Its intrinsic size is used by the layout
+/// unless the style sets one.
+#[derive(Debug)]
+pub struct Image {
+    pub path: PathBuf,
+    /// Size of the picture once loaded.
+    pub size: Option<(f32, f32)>,
+    /// The picture at `path`, registered by `ImageSystem`.
+    texture: Option<Handle<Texture>>,
+    /// Whether the picture failed to load: it is not tried again.
+    failed: bool,
+}
+
+impl Image {
+    pub fn new(path: PathBuf) -> Self {
+        Image {
+            path,
+            size: None,
+            texture: None,
+            failed: false,
+        }
+    }
+}
+
+impl Component for Image {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Loads the picture of every `Image` to know its intrinsic size.
+pub struct ImageSystem;
+
+impl<'a> System<'a> for ImageSystem {
+    type SystemData = (
+        ReadExpect<'a, ResourceManager>,
+        Write<'a, Diagnostics>,
+        WriteStorage<'a, Image>,
+    );
+
+    fn run(&mut self, (store, mut diagnostics, mut images): Self::SystemData) {
+        for image in (&mut images).join().filter(|image| !image.failed) {
+            let path = &image.path;
+            let texture = *image
+                .texture
+                .get_or_insert_with(|| store.handle(&SimpleKey::Path(path.clone())));
+            match store.resolve_async(texture) {
+                // laid out without intrinsic size until decoded
+                Ok(None) => image.size = None,
+                Ok(Some(texture)) => {
+                    let texture = texture.borrow();
+                    image.size = Some((texture.width as f32, texture.height as f32));
+                }
+                Err(e) => {
+                    // laid out without intrinsic size, rather than reading the file every frame
+                    image.failed = true;
+                    image.size = None;
+                    diagnostics.report_resource(&store.key(texture), Err(&e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::png::PNGEncoder::new(&mut bytes)
+            .encode(
+                &vec![255; (width * height * 4) as usize],
+                width,
+                height,
+                image::ColorType::RGBA(8),
+            )
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        let manager = ResourceManager::new(Arc::new(Fxc));
+        manager.insert("generated/small.png", png(2, 1));
+        manager.insert("generated/wide.png", png(1 << 16, 1));
+        let small = manager
+            .get::<Texture>(&SimpleKey::Logical("generated/small.png".into()))
+            .unwrap();
+        assert_eq!((small.borrow().width, small.borrow().height), (2, 1));
+        match manager.get::<Texture>(&SimpleKey::Logical("generated/wide.png".into())) {
+            Err(Error::Load { cause, .. }) => match *cause {
+                Error::ImageError(image::ImageError::DimensionError) => {}
+                e => panic!("{:?}", e),
+            },
+            e => panic!("{:?}", e),
+        }
+    }
+}
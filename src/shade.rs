@@ -18,8 +18,6 @@ use std::fmt;
 #[cfg(target_os = "windows")]
 pub use gfx_device_dx11::ShaderModel as DxShaderModel;
 pub use gfx_device_gl::Version as GlslVersion;
-#[cfg(feature = "metal")]
-pub use gfx_device_metal::ShaderModel as MetalShaderModel;
 /// Shader backend with version numbers.
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[allow(dead_code)]
@@ -28,10 +26,6 @@ pub enum Backend {
     GlslEs(GlslVersion),
     #[cfg(target_os = "windows")]
     Hlsl(DxShaderModel),
-    #[cfg(feature = "metal")]
-    Msl(MetalShaderModel),
-    #[cfg(feature = "vulkan")]
-    Vulkan,
 }
 
 #[allow(dead_code)]
@@ -135,17 +129,6 @@ impl<'a> Source<'a> {
                 Source { hlsl_30: s, .. } if s != EMPTY && model >= 30 => s,
                 _ => return Err(SelectError(backend)),
             },
-            #[cfg(feature = "metal")]
-            Backend::Msl(revision) => match *self {
-                Source { msl_11: s, .. } if s != EMPTY && revision >= 11 => s,
-                Source { msl_10: s, .. } if s != EMPTY && revision >= 10 => s,
-                _ => return Err(SelectError(backend)),
-            },
-            #[cfg(feature = "vulkan")]
-            Backend::Vulkan => match *self {
-                Source { vulkan: s, .. } if s != EMPTY => s,
-                _ => return Err(SelectError(backend)),
-            },
         })
     }
 }
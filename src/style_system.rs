@@ -13,8 +13,11 @@ use selectors::parser::{
 };
 
 use std::sync::Mutex;
+use stretch::style::Dimension;
 
 use crate::layout::Dimensions;
+use crate::rendering::{Background, BackgroundImage, BorderImage};
+use crate::styling::{BorderStyle, BoxShadow, TransformFunction, Value};
 use crate::transform::Parent;
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -482,7 +485,17 @@ impl std::fmt::Debug for Selectors {
     }
 }
 
-pub struct StyleSystem;
+/// The stylesheets `StyleSystem` cascades, in order: the rules of the last
+/// one win.
+#[derive(Debug, Default)]
+pub struct Stylesheets(pub Vec<crate::manager::SimpleKey>);
+
+pub struct StyleSystem {
+    /// The keys `handles` were made for, compared with `Stylesheets` each
+    /// frame to notice a change.
+    stylesheets: Vec<crate::manager::SimpleKey>,
+    handles: Vec<crate::manager::Handle<crate::styling::Stylesheet>>,
+}
 impl<'a> System<'a> for StyleSystem {
     type SystemData = (
         Entities<'a>,
@@ -492,43 +505,69 @@ impl<'a> System<'a> for StyleSystem {
         ReadStorage<'a, EElement>,
         WriteStorage<'a, Dimensions>,
         WriteStorage<'a, StyleBackground>,
+        WriteStorage<'a, StyleStacking>,
+        WriteStorage<'a, StyleScrollbar>,
+        WriteStorage<'a, StyleBorder>,
+        WriteStorage<'a, StyleShadow>,
+        WriteStorage<'a, StyleOutline>,
+        WriteStorage<'a, StyleTransform>,
         WriteStorage<'a, crate::rendering::Material>,
+        Write<'a, crate::manager::Diagnostics>,
+        Read<'a, Stylesheets>,
     );
 
     #[allow(dead_code)]
     fn run(
         &mut self,
-        (entities, res, pseudo, parent, eelements, mut dimensions, mut bg, mut mat): Self::SystemData,
+        (entities, res, pseudo, parent, eelements, mut dimensions, mut bg, mut stacking, mut scrollbar, mut border, mut shadow, mut outline, mut transform, mut mat, mut diagnostics, cascade): Self::SystemData,
     ) {
         use crate::manager::*;
 
-        let missing_pseudos: specs::BitSet = (&entities, &eelements, !&dimensions)
-            .join()
-            .map(|(e, _, _)| e.id())
-            .collect();
-        for id in (&missing_pseudos).join() {
-            dimensions
-                .insert(entities.entity(id), Default::default())
-                .unwrap();
+        // every element gets each style component, for the rules to fill
+        Self::insert_missing(&entities, &eelements, &mut dimensions);
+        Self::insert_missing(&entities, &eelements, &mut stacking);
+        Self::insert_missing(&entities, &eelements, &mut scrollbar);
+        Self::insert_missing(&entities, &eelements, &mut border);
+        Self::insert_missing(&entities, &eelements, &mut shadow);
+        Self::insert_missing(&entities, &eelements, &mut outline);
+        Self::insert_missing(&entities, &eelements, &mut transform);
+
+        if self.stylesheets != cascade.0 {
+            self.stylesheets = cascade.0.clone();
+            self.handles = self.stylesheets.iter().map(|k| res.handle(k)).collect();
         }
-
-        let key = SimpleKey::Path(("style/style.css").into());
-        let stylesheet = match res.get::<crate::styling::Stylesheet>(&key) {
-            Ok(css) => css,
-            e => {
-                eprintln!("{:?}", e);
-                return;
+        // a stylesheet failing to load is skipped, without any the elements get the default style
+        let mut stylesheets = Vec::with_capacity(self.handles.len());
+        let mut loading = false;
+        for &handle in &self.handles {
+            match res.resolve_async(handle) {
+                Ok(Some(css)) => {
+                    diagnostics.report_resource(&res.key(handle), Ok(()));
+                    stylesheets.push(css);
+                }
+                Ok(None) => loading = true,
+                Err(e) => diagnostics.report_resource(&res.key(handle), Err(&e)),
             }
-        };
+        }
+        // the elements keep their previous style until every stylesheet is parsed
+        if loading {
+            return;
+        }
+        let stylesheets: Vec<_> = stylesheets.iter().map(|css| css.borrow()).collect();
         use specs::ParJoin;
-        let rules = &stylesheet.borrow().0;
+        let rules: Vec<&crate::styling::Rule> = stylesheets.iter().flat_map(|css| css.0.iter()).collect();
         //for (e, _, dimension) in
-        (&entities, &eelements, &mut dimensions, &mut bg).par_join().for_each(|(e, _, dimension, bg)| {
-            use cgmath::Zero;
-
+        (&entities, &eelements, &mut dimensions, &mut bg, &mut stacking, &mut scrollbar, &mut border, &mut shadow, &mut outline, &mut transform).par_join().for_each(|(e, _, dimension, bg, stacking, scrollbar, border, shadow, outline, transform)| {
             // reset properties - a rule might have been deleted from the stylesheet
-            bg.color = cgmath::Vector4::zero();
+            bg.background = Default::default();
+            bg.foreground = None;
             *dimension = Default::default();
+            *stacking = Default::default();
+            *scrollbar = Default::default();
+            *border = Default::default();
+            shadow.shadows.clear();
+            *outline = Default::default();
+            *transform = Default::default();
 
             for rule in rules.iter() {
                 if rule
@@ -537,12 +576,23 @@ impl<'a> System<'a> for StyleSystem {
                 {
                     for declaration in rule.declarations.iter() {
                         match declaration.property.as_ref() {
-                            "background" => {
-                                bg.color = declaration.value.color().unwrap().into()
+                            "background" => match &declaration.value {
+                                Value::Color(color) => bg.background = Background { color: (*color).into(), ..Default::default() },
+                                Value::Gradient(gradient) => bg.background = Background { image: Some(BackgroundImage::Gradient(gradient.clone())), ..Default::default() },
+                                Value::Url(url) => bg.background = Background { image: Some(BackgroundImage::Texture(url.into())), ..Default::default() },
+                                _ => bg.background = Default::default(),
+                            }
+                            "background-color" => { if let Some(v) = declaration.value.color() { bg.background.color = v.into(); } }
+                            "background-image" => match &declaration.value {
+                                Value::Gradient(gradient) => bg.background.image = Some(BackgroundImage::Gradient(gradient.clone())),
+                                Value::Url(url) => bg.background.image = Some(BackgroundImage::Texture(url.into())),
+                                _ => bg.background.image = None,
                             }
+                            "color" | "foreground" => { if let Some(v) = declaration.value.color() { bg.foreground = Some(v.into()); } }
+                            "background-size" => { if let Some(v) = declaration.value.image_fit() { bg.background.size = v; } }
                             "display" => { if let Some(v) = declaration.value.display() { dimension.display = v; } }
 
-                            "position-type" => { if let Some(v) = declaration.value.position_type() { dimension.position_type = v;} }  //: PositionType,
+                            "position-type" | "position" => { if let Some(v) = declaration.value.position_type() { dimension.position_type = v;} }  //: Position,
                             "direction" => { if let Some(v) = declaration.value.direction() { dimension.direction = v;} }      //: Direction,
                             "flex-direction" => { if let Some(v) = declaration.value.flex_direction() { dimension.flex_direction = v;} } //: FlexDirection,
 
@@ -555,7 +605,11 @@ impl<'a> System<'a> for StyleSystem {
 
                             "justify-content" => { if let Some(v) = declaration.value.justify_content() { dimension.justify_content = v;} } //: JustifyContent,
 
-                            // "position" => { if let Some(v) = declaration.value.position() { dimension.position = v; } } //: Rect<Dimension>,
+                            // physical edges, swapped by the layout in a right to left parent
+                            "left" => { if let Some(v) = declaration.value.dimension() { dimension.position.start = v; } } //: Dimension,
+                            "right" => { if let Some(v) = declaration.value.dimension() { dimension.position.end = v; } } //: Dimension,
+                            "top" => { if let Some(v) = declaration.value.dimension() { dimension.position.top = v; } } //: Dimension,
+                            "bottom" => { if let Some(v) = declaration.value.dimension() { dimension.position.bottom = v; } } //: Dimension,
 
                             "margin" => { if let Some(v) = declaration.value.rect_dimension() { dimension.margin = v; }   } //: Rect<Dimension>,
                             "margin-left" => { if let Some(v) = declaration.value.dimension() { dimension.margin.start = v; } } //: Dimension,
@@ -569,7 +623,24 @@ impl<'a> System<'a> for StyleSystem {
                             "padding-top" => { if let Some(v) = declaration.value.dimension() { dimension.padding.top = v; }  } //: Dimension,
                             "padding-bottom" => { if let Some(v) = declaration.value.dimension() { dimension.padding.bottom = v; }  } //: Dimension,
 
-                            // "border" => { if let Some(v) = declaration.value.border() { dimension.border = v; }   } //: Rect<Dimension>,
+                            "border" => { if let Some(v) = declaration.value.border() {
+                                if let Some(width) = v.width { dimension.border = stretch::geometry::Rect { start: width, end: width, top: width, bottom: width }; }
+                                if let Some(style) = v.style { border.style = style; }
+                                if let Some(color) = v.color { border.color = color.into(); }
+                            } } //: Rect<Dimension>,
+                            "border-width" => { if let Some(v) = declaration.value.rect_dimension() { dimension.border = v; }  } //: Rect<Dimension>,
+                            "border-left-width" => { if let Some(v) = declaration.value.dimension() { dimension.border.start = v; }  } //: Dimension,
+                            "border-right-width" => { if let Some(v) = declaration.value.dimension() { dimension.border.end = v; }  } //: Dimension,
+                            "border-top-width" => { if let Some(v) = declaration.value.dimension() { dimension.border.top = v; }  } //: Dimension,
+                            "border-bottom-width" => { if let Some(v) = declaration.value.dimension() { dimension.border.bottom = v; }  } //: Dimension,
+                            "border-color" => { if let Some(v) = declaration.value.color() { border.color = v.into(); } }
+                            "border-image" => { border.image = declaration.value.border_image().cloned(); }
+                            "border-style" => { if let Some(v) = declaration.value.border_style() { border.style = v; } }
+                            "border-radius" => { if let Some(v) = declaration.value.corners() { border.radius = v; } }
+                            "border-top-left-radius" => { if let Some(v) = declaration.value.float() { border.radius[0] = v; } }
+                            "border-top-right-radius" => { if let Some(v) = declaration.value.float() { border.radius[1] = v; } }
+                            "border-bottom-right-radius" => { if let Some(v) = declaration.value.float() { border.radius[2] = v; } }
+                            "border-bottom-left-radius" => { if let Some(v) = declaration.value.float() { border.radius[3] = v; } }
 
                             "flex-grow" => { if let Some(v) = declaration.value.float() { dimension.flex_grow = v; } }   //: f32,
                             "flex-shrink" => { if let Some(v) = declaration.value.float() { dimension.flex_shrink = v;} } //: f32,
@@ -582,30 +653,104 @@ impl<'a> System<'a> for StyleSystem {
                             "max-width" => { if let Some(v) = declaration.value.dimension() { dimension.max_size.width = v; }     } //: Size<Dimension>,
                             "max-height" => { if let Some(v) = declaration.value.dimension() { dimension.max_size.height = v; }     } //: Size<Dimension>,
 
+                            "scrollbar-color" => { if let Some(v) = declaration.value.colors() {
+                                scrollbar.thumb = v[0].into();
+                                if let Some(track) = v.get(1) { scrollbar.track = (*track).into(); }
+                            } }
+                            "scrollbar-width" => { if let Some(v) = declaration.value.scrollbar_width() { scrollbar.width = v; } }
+
+                            "box-shadow" => { if let Some(v) = declaration.value.shadows() { shadow.shadows = v.to_vec(); } }
+
+                            "outline" => { if let Some(v) = declaration.value.border() {
+                                if let Some(Dimension::Points(width)) = v.width { outline.width = width; }
+                                if let Some(style) = v.style { outline.style = style; }
+                                if let Some(color) = v.color { outline.color = color.into(); }
+                            } }
+                            "outline-width" => { if let Some(v) = declaration.value.float() { outline.width = v; } }
+                            "outline-style" => { if let Some(v) = declaration.value.border_style() { outline.style = v; } }
+                            "outline-color" => { if let Some(v) = declaration.value.color() { outline.color = v.into(); } }
+                            "outline-offset" => { if let Some(v) = declaration.value.float() { outline.offset = v; } }
+
+                            "z-index" => { if let Some(v) = declaration.value.z_index() { stacking.z_index = v; } }
+                            "transform" => { if let Some(v) = declaration.value.transform() { transform.functions = v.to_vec(); } }
+                            "transform-origin" => { if let Some(v) = declaration.value.origin() { transform.origin = v; } }
+                            "opacity" => { if let Some(v) = declaration.value.float() { stacking.opacity = v.max(0.0).min(1.0); } }
+                            "visibility" => { if let Some(v) = declaration.value.visibility() { stacking.visible = v; } }
+
                             "aspect_ratio" => { if let Some(v) = declaration.value.float() { dimension.aspect_ratio = stretch::number::Number::Defined(v);} } //: Number,
-                            x => println!("unknown css property: {}", x),
+                            x => warn!("unknown css property: {}", x),
                         }
                     }
                 }
             }
+
+            stacking.positioned = dimension.position_type != crate::layout::Position::Static;
+
+            // as in css, a border without style takes no room, unless a
+            // border image is drawn over it
+            if border.style == BorderStyle::None && border.image.is_none() {
+                dimension.border = Default::default();
+            }
         }
         );
 
         for (bg, mut mat) in (&bg, &mut mat).join() {
-            mat.color = bg.color;
+            mat.background = bg.background.clone();
+            mat.foreground = bg.foreground;
         }
     }
 }
 
 impl StyleSystem {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            stylesheets: Vec::new(),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Inserts a default `T` for the elements without one.
+    fn insert_missing<T: Component + Default>(
+        entities: &specs::world::EntitiesRes,
+        eelements: &ReadStorage<'_, EElement>,
+        storage: &mut WriteStorage<'_, T>,
+    ) {
+        let missing: specs::BitSet = (entities, eelements, !&*storage)
+            .join()
+            .map(|(e, _, _)| e.id())
+            .collect();
+        for id in (&missing).join() {
+            storage.insert(entities.entity(id), Default::default()).unwrap();
+        }
+    }
+}
+
+/// Styles the elements with the `Stylesheets`, once their hierarchy is up to
+/// date.
+pub struct StyleBundle;
+
+impl<'a, 'b> crate::UiBundle<'a, 'b> for StyleBundle {
+    fn build(self, world: &mut World, dispatcher: &mut DispatcherBuilder<'a, 'b>) {
+        world.register::<EElement>();
+        world.add_resource(Stylesheets::default());
+        dispatcher.add(
+            specs_hierarchy::HierarchySystem::<crate::transform::Parent>::new(),
+            "parent_hierarchy_system",
+            &[],
+        );
+        dispatcher.add(StyleSystem::new(), "sys_style", &["parent_hierarchy_system"]);
+    }
+
+    fn style(&self) -> Option<&'static str> {
+        Some("* { transform-origin: center; }")
     }
 }
 
 #[derive(Debug)]
 pub struct StyleBackground {
-    pub color: cgmath::Vector4<u8>,
+    pub background: Background,
+    /// The css `color`, of the text.
+    pub foreground: Option<cgmath::Vector4<u8>>,
 }
 
 impl specs::Component for StyleBackground {
@@ -615,11 +760,189 @@ impl specs::Component for StyleBackground {
 impl StyleBackground {
     pub fn from_color(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self {
-            color: cgmath::Vector4::new(r, g, b, a),
+            background: Background::from_color(r, g, b, a),
+            foreground: None,
         }
     }
 }
 
+/// Paint order properties. A positioned element with a `z-index` other than
+/// `auto`, or any element with an `opacity` below 1, starts a stacking
+/// context: it is painted with its descendants as one unit, sorted by
+/// `z-index` among its siblings contexts.
+#[derive(Debug, Clone)]
+pub struct StyleStacking {
+    pub z_index: Option<i32>,
+    /// Whether the `position` is other than `static`, which ignores `z-index`.
+    pub positioned: bool,
+    /// Applied to the whole stacking context once it is painted.
+    pub opacity: f32,
+    /// `None` inherits the visibility of the parent.
+    pub visible: Option<bool>,
+}
+
+impl Default for StyleStacking {
+    fn default() -> Self {
+        Self {
+            z_index: None,
+            positioned: false,
+            opacity: 1.0,
+            visible: None,
+        }
+    }
+}
+
+impl StyleStacking {
+    pub fn starts_context(&self) -> bool {
+        (self.positioned && self.z_index.is_some()) || self.opacity < 1.0
+    }
+
+    /// Resolves an inherited visibility by walking up the parents.
+    pub fn resolve_visible(
+        e: Entity,
+        stacking: &ReadStorage<'_, StyleStacking>,
+        parents: &ReadStorage<'_, Parent>,
+    ) -> bool {
+        let mut current = Some(e);
+        while let Some(e) = current {
+            if let Some(visible) = stacking.get(e).and_then(|s| s.visible) {
+                return visible;
+            }
+            current = parents.get(e).map(|p| p.entity);
+        }
+        true
+    }
+}
+
+impl specs::Component for StyleStacking {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Look of the scrollbars of an `overflow: scroll` element.
+#[derive(Debug, Clone)]
+pub struct StyleScrollbar {
+    pub thumb: cgmath::Vector4<u8>,
+    pub track: cgmath::Vector4<u8>,
+    pub width: f32,
+}
+
+impl Default for StyleScrollbar {
+    fn default() -> Self {
+        Self {
+            thumb: cgmath::Vector4::new(160, 160, 160, 255),
+            track: cgmath::Vector4::new(40, 40, 40, 255),
+            width: 12.0,
+        }
+    }
+}
+
+impl specs::Component for StyleScrollbar {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Border look; the border widths are part of `Dimensions` as they take part in the layout.
+#[derive(Debug, Clone)]
+pub struct StyleBorder {
+    pub color: cgmath::Vector4<u8>,
+    pub style: BorderStyle,
+    /// Top left, top right, bottom right and bottom left radii.
+    pub radius: [f32; 4],
+    pub image: Option<BorderImage>,
+}
+
+impl Default for StyleBorder {
+    fn default() -> Self {
+        Self {
+            color: cgmath::Vector4::new(0, 0, 0, 255),
+            style: BorderStyle::None,
+            radius: [0.0; 4],
+            image: None,
+        }
+    }
+}
+
+impl specs::Component for StyleBorder {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// `box-shadow` layers, the first one painted on top.
+#[derive(Debug, Default, Clone)]
+pub struct StyleShadow {
+    pub shadows: Vec<BoxShadow>,
+}
+
+impl specs::Component for StyleShadow {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A ring drawn outside the border box, over the element and its descendants.
+/// It takes no room in the layout.
+#[derive(Debug, Clone)]
+pub struct StyleOutline {
+    pub width: f32,
+    pub style: BorderStyle,
+    pub color: cgmath::Vector4<u8>,
+    /// Gap between the border box and the outline.
+    pub offset: f32,
+}
+
+impl Default for StyleOutline {
+    fn default() -> Self {
+        Self {
+            width: 3.0,
+            style: BorderStyle::None,
+            color: cgmath::Vector4::new(0, 0, 0, 255),
+            offset: 0.0,
+        }
+    }
+}
+
+impl specs::Component for StyleOutline {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A `transform`, applied over the layout to the element and its descendants.
+#[derive(Debug, Clone)]
+pub struct StyleTransform {
+    pub functions: Vec<TransformFunction>,
+    /// Point of the box the functions are applied around.
+    pub origin: (Dimension, Dimension),
+}
+
+impl Default for StyleTransform {
+    fn default() -> Self {
+        Self {
+            functions: Vec::new(),
+            origin: (Dimension::Percent(0.5), Dimension::Percent(0.5)),
+        }
+    }
+}
+
+impl StyleTransform {
+    /// The transform of a `size` box, relative to its top left corner.
+    pub fn matrix(&self, size: (f32, f32)) -> cgmath::Matrix4<f32> {
+        use cgmath::{Matrix4, SquareMatrix};
+
+        if self.functions.is_empty() {
+            return Matrix4::identity();
+        }
+        let length = |d: Dimension, size: f32| match d {
+            Dimension::Points(x) => x,
+            Dimension::Percent(x) => x * size,
+            _ => 0.0,
+        };
+        let origin = [length(self.origin.0, size.0), length(self.origin.1, size.1), 0.0];
+        let functions = self.functions.iter().fold(Matrix4::identity(), |m, f| m * f.matrix(size));
+        Matrix4::from_translation(origin.into())
+            * functions
+            * Matrix4::from_translation([-origin[0], -origin[1], 0.0].into())
+    }
+}
+
+impl specs::Component for StyleTransform {
+    type Storage = DenseVecStorage<Self>;
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Pseudo {
     pub hover: bool,
@@ -642,6 +965,27 @@ mod tests {
         w
     }
 
+    /// A world where an `A` element was styled once with `css`.
+    fn styled(css: &'static str) -> (World, Entity) {
+        use crate::manager::{Fxc, ResourceManager, SimpleKey};
+        use specs::RunNow;
+
+        let store = ResourceManager::new(std::sync::Arc::new(Fxc));
+        store.insert("test/style.css", css.as_bytes());
+        let key = SimpleKey::Logical("test/style.css".into());
+        // parsed now rather than on the loading thread
+        store.get::<crate::styling::Stylesheet>(&key).unwrap();
+
+        let mut w = world();
+        w.add_resource(store);
+        w.add_resource(Stylesheets(vec![key]));
+        let mut system = StyleSystem::new();
+        System::setup(&mut system, &mut w.res);
+        let e = w.create_entity().with(EElement::new("A".into())).build();
+        system.run_now(&w.res);
+        (w, e)
+    }
+
     fn check(
         res: bool,
         w: &mut World,
@@ -662,6 +1006,32 @@ mod tests {
         e
     }
 
+    #[test]
+    fn transform_around_origin() {
+        use cgmath::{Point3, Transform};
+
+        let t = StyleTransform {
+            functions: vec![TransformFunction::Rotate(std::f32::consts::FRAC_PI_2)],
+            ..Default::default()
+        };
+        // a quarter turn clockwise around the center of a 20x10 box
+        let p = t.matrix((20.0, 10.0)).transform_point(Point3::new(0.0, 0.0, 0.0));
+        assert!((p.x - 15.0).abs() < 1e-4 && (p.y + 5.0).abs() < 1e-4, "{:?}", p);
+    }
+
+    #[test]
+    fn stacking_contexts() {
+        let mut stacking = StyleStacking { z_index: Some(1), ..Default::default() };
+        // z-index is ignored on static elements
+        assert!(!stacking.starts_context());
+        stacking.positioned = true;
+        assert!(stacking.starts_context());
+        stacking.z_index = None;
+        assert!(!stacking.starts_context());
+        let translucent = StyleStacking { opacity: 0.5, ..Default::default() };
+        assert!(translucent.starts_context());
+    }
+
     #[test]
     fn match_hover() {
         let s = Selectors::compile(":hover").unwrap();
@@ -751,4 +1121,35 @@ mod tests {
             None,
         );
     }
+
+    #[test]
+    fn style_element() {
+        let (w, e) = styled("A { overflow: scroll; scrollbar-color: #ff0000 #0000ff; scrollbar-width: thin; border-image: url(skin.png) 8 repeat; border-width: 8; opacity: 0.25; visibility: hidden; } B { scrollbar-width: 30; }");
+        let dimensions = w.read_storage::<Dimensions>();
+        let dimensions = dimensions.get(e).unwrap();
+        assert_eq!(dimensions.overflow, stretch::style::Overflow::Scroll);
+        let scrollbars = w.read_storage::<StyleScrollbar>();
+        let scrollbar = scrollbars.get(e).unwrap();
+        assert_eq!(scrollbar.thumb, cgmath::Vector4::new(255, 0, 0, 255));
+        assert_eq!(scrollbar.track, cgmath::Vector4::new(0, 0, 255, 255));
+        assert_eq!(scrollbar.width, 6.0);
+        let borders = w.read_storage::<StyleBorder>();
+        let image = borders.get(e).unwrap().image.as_ref().unwrap();
+        assert_eq!(image.path, std::path::PathBuf::from("skin.png"));
+        assert_eq!(image.repeat, crate::rendering::BorderRepeat::Repeat);
+        // the picture is drawn over the border widths
+        assert_eq!(dimensions.border.top, Dimension::Points(8.0));
+        let stacking = w.read_storage::<StyleStacking>().get(e).unwrap().clone();
+        assert_eq!((stacking.opacity, stacking.visible), (0.25, Some(false)));
+        // a translucent element is composited as a group
+        assert!(stacking.starts_context());
+
+        // a later rule resets the picture, out of range opacities are clamped
+        let (w, e) = styled("A { border-image: url(skin.png) 8; opacity: 1.5; } A { border-image: none; }");
+        assert!(w.read_storage::<StyleBorder>().get(e).unwrap().image.is_none());
+        assert_eq!(w.read_storage::<StyleStacking>().get(e).unwrap().opacity, 1.0);
+        let (w, e) = styled("A { opacity: -1; visibility: inherit; }");
+        let stacking = w.read_storage::<StyleStacking>().get(e).unwrap().clone();
+        assert_eq!((stacking.opacity, stacking.visible), (0.0, None));
+    }
 }
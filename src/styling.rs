@@ -7,6 +7,9 @@ use cssparser::{
 };
 
 use crate::color::Color;
+use crate::rendering::{
+    BorderImage, BorderRepeat, Gradient, GradientShape, GradientStop, ImageFit,
+};
 
 use std::ops::Add;
 use std::path::Path;
@@ -252,11 +255,105 @@ pub struct Declaration {
 #[derive(Clone, Debug)]
 pub enum Value {
     UInt(u32),
+    Int(i32),
     Float(f32),
     Color(Color),
+    Colors(Vec<Color>),
     Str(String),
     Ident(String),
     Rect(Rect<Dimension>),
+    /// Top left, top right, bottom right and bottom left.
+    Corners([f32; 4]),
+    Border(BorderShorthand),
+    Shadows(Vec<BoxShadow>),
+    Gradient(Gradient),
+    Url(String),
+    BorderImage(BorderImage),
+    Transform(Vec<TransformFunction>),
+    /// Horizontal and vertical offsets of a `transform-origin`.
+    Origin(Dimension, Dimension),
+    /// A length or a percentage.
+    Dimension(Dimension),
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BorderStyle {
+    None,
+    Solid,
+    Dashed,
+}
+
+impl Default for BorderStyle {
+    fn default() -> Self {
+        BorderStyle::None
+    }
+}
+
+/// One layer of a `box-shadow` declaration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxShadow {
+    pub x: f32,
+    pub y: f32,
+    pub blur: f32,
+    pub spread: f32,
+    pub color: Color,
+    pub inset: bool,
+}
+
+/// One function of a `transform` declaration. Angles are in radians, clockwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransformFunction {
+    /// Percentages are relative to the size of the box.
+    Translate(Dimension, Dimension),
+    Scale(f32, f32),
+    Rotate(f32),
+    Skew(f32, f32),
+    /// `a b c d e f`, as in css.
+    Matrix([f32; 6]),
+}
+
+impl TransformFunction {
+    pub fn matrix(&self, (w, h): (f32, f32)) -> cgmath::Matrix4<f32> {
+        use cgmath::{Matrix4, Rad, SquareMatrix};
+
+        let length = |d: Dimension, size: f32| match d {
+            Dimension::Points(x) => x,
+            Dimension::Percent(x) => x * size,
+            _ => 0.0,
+        };
+        match *self {
+            TransformFunction::Translate(x, y) => {
+                Matrix4::from_translation([length(x, w), length(y, h), 0.0].into())
+            }
+            TransformFunction::Scale(x, y) => Matrix4::from_nonuniform_scale(x, y, 1.0),
+            TransformFunction::Rotate(angle) => Matrix4::from_angle_z(Rad(angle)),
+            TransformFunction::Skew(x, y) => {
+                let mut m = Matrix4::identity();
+                m.y.x = x.tan();
+                m.x.y = y.tan();
+                m
+            }
+            TransformFunction::Matrix([a, b, c, d, e, f]) => {
+                let mut m = Matrix4::identity();
+                m.x.x = a;
+                m.x.y = b;
+                m.y.x = c;
+                m.y.y = d;
+                m.w.x = e;
+                m.w.y = f;
+                m
+            }
+        }
+    }
+}
+
+/// The parts of a `border` or `outline` declaration, any of which can be omitted.
+#[derive(Clone, Debug, Default)]
+pub struct BorderShorthand {
+    pub width: Option<Dimension>,
+    pub style: Option<BorderStyle>,
+    pub color: Option<Color>,
 }
 
 impl Value {
@@ -272,6 +369,33 @@ impl Value {
         match *self {
             Value::Float(x) => Some(x),
             Value::UInt(x) => Some(x as f32),
+            Value::Int(x) => Some(x as f32),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self) -> Option<i32> {
+        match *self {
+            Value::Int(x) => Some(x),
+            Value::UInt(x) => Some(x as i32),
+            _ => None,
+        }
+    }
+
+    /// `None` stands for `auto`.
+    pub fn z_index(&self) -> Option<Option<i32>> {
+        match self.ident() {
+            Some("auto") => Some(None),
+            _ => self.int().map(Some),
+        }
+    }
+
+    /// `None` stands for `inherit`.
+    pub fn visibility(&self) -> Option<Option<bool>> {
+        match self.ident() {
+            Some("visible") => Some(Some(true)),
+            Some("hidden") | Some("collapse") => Some(Some(false)),
+            Some("inherit") => Some(None),
             _ => None,
         }
     }
@@ -283,6 +407,100 @@ impl Value {
         }
     }
 
+    pub fn colors(&self) -> Option<&[Color]> {
+        match self {
+            Value::Colors(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// `thin`, `auto`, `none` or a width in pixels.
+    pub fn scrollbar_width(&self) -> Option<f32> {
+        match self.ident() {
+            Some("none") => Some(0.0),
+            Some("thin") => Some(6.0),
+            Some("auto") => Some(12.0),
+            _ => self.float(),
+        }
+    }
+
+    /// A single radius applies to the four corners.
+    pub fn corners(&self) -> Option<[f32; 4]> {
+        match self {
+            Value::Corners(x) => Some(*x),
+            _ => self.float().map(|x| [x; 4]),
+        }
+    }
+
+    pub fn border_style(&self) -> Option<BorderStyle> {
+        match self.ident() {
+            Some("none") | Some("hidden") => Some(BorderStyle::None),
+            Some("solid") => Some(BorderStyle::Solid),
+            Some("dashed") => Some(BorderStyle::Dashed),
+            _ => None,
+        }
+    }
+
+    pub fn border(&self) -> Option<&BorderShorthand> {
+        match self {
+            Value::Border(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn gradient(&self) -> Option<&Gradient> {
+        match self {
+            Value::Gradient(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Value::Url(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn border_image(&self) -> Option<&BorderImage> {
+        match self {
+            Value::BorderImage(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn transform(&self) -> Option<&[TransformFunction]> {
+        match self {
+            Value::Transform(x) => Some(x),
+            Value::None => Some(&[]),
+            _ => None,
+        }
+    }
+
+    pub fn origin(&self) -> Option<(Dimension, Dimension)> {
+        match *self {
+            Value::Origin(x, y) => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    pub fn image_fit(&self) -> Option<ImageFit> {
+        match self.ident() {
+            Some("auto") => Some(ImageFit::Auto),
+            Some("cover") => Some(ImageFit::Cover),
+            Some("contain") => Some(ImageFit::Contain),
+            Some("fill") => Some(ImageFit::Fill),
+            _ => None,
+        }
+    }
+
+    pub fn shadows(&self) -> Option<&[BoxShadow]> {
+        match self {
+            Value::Shadows(x) => Some(x),
+            _ => None,
+        }
+    }
+
     pub fn string(&self) -> Option<String> {
         match self {
             Value::Str(x) => Some(x.clone()),
@@ -305,6 +523,7 @@ impl Value {
         match self {
             Value::Float(x) => Some(Dimension::Points(*x)),
             Value::UInt(x) => Some(Dimension::Points(*x as f32)),
+            Value::Dimension(d) => Some(*d),
             _ => None,
         }
     }
@@ -324,10 +543,12 @@ impl Value {
         }
     }
 
-    pub fn position_type(&self) -> Option<PositionType> {
+    pub fn position_type(&self) -> Option<crate::layout::Position> {
+        use crate::layout::Position;
         match self.ident() {
-            Some("absolute") => Some(PositionType::Absolute),
-            Some("relative") => Some(PositionType::Relative),
+            Some("absolute") => Some(Position::Absolute),
+            Some("relative") => Some(Position::Relative),
+            Some("static") => Some(Position::Static),
             _ => None,
         }
     }
@@ -523,12 +744,7 @@ impl<'i> cssparser::QualifiedRuleParser<'i> for RuleParser {
             match *decl {
                 Ok(_) => {}
                 Err(ref e) => {
-                    eprintln!("{:?}", e);
-                    // match e.0 {
-                    //     ParseError::Basic(ref e) => ,
-                    //     ParseError::Custom(ref e) => eprintln!("{:?}", e),
-                    // }
-                    println!("Error occured in `{}`", e.1); // input.slice(e.span.clone()));
+                    warn!("{:?} in `{}`", e.0, e.1);
                 }
             }
         }
@@ -577,6 +793,110 @@ impl DeclarationParser {
             ref t => return Err(location.new_unexpected_token_error(t.clone())),
         }
     }
+
+    /// `translate`, `scale`, `rotate`, `skew` and their single axis forms, or
+    /// `matrix`.
+    fn parse_transform_function<'i, 't>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<TransformFunction, ParseError<'i, CustomParseError>> {
+        let location = input.current_source_location();
+        let function = input.expect_function()?.to_lowercase();
+        input.parse_nested_block(|input| {
+            // the second argument of the two axis forms is optional
+            Ok(match &*function {
+                "translate" => {
+                    let x = self.parse_dimension(input)?;
+                    let y = input
+                        .r#try(|input| {
+                            input.expect_comma()?;
+                            self.parse_dimension(input)
+                        })
+                        .unwrap_or(Dimension::Points(0.0));
+                    TransformFunction::Translate(x, y)
+                }
+                "translatex" => TransformFunction::Translate(
+                    self.parse_dimension(input)?,
+                    Dimension::Points(0.0),
+                ),
+                "translatey" => TransformFunction::Translate(
+                    Dimension::Points(0.0),
+                    self.parse_dimension(input)?,
+                ),
+                "scale" => {
+                    let x = input.expect_number()?;
+                    let y = input
+                        .r#try(|input| {
+                            input.expect_comma()?;
+                            input.expect_number()
+                        })
+                        .unwrap_or(x);
+                    TransformFunction::Scale(x, y)
+                }
+                "scalex" => TransformFunction::Scale(input.expect_number()?, 1.0),
+                "scaley" => TransformFunction::Scale(1.0, input.expect_number()?),
+                "rotate" => TransformFunction::Rotate(parse_angle(input)?),
+                "skew" => {
+                    let x = parse_angle(input)?;
+                    let y = input
+                        .r#try(|input| {
+                            input.expect_comma()?;
+                            parse_angle(input)
+                        })
+                        .unwrap_or(0.0);
+                    TransformFunction::Skew(x, y)
+                }
+                "skewx" => TransformFunction::Skew(parse_angle(input)?, 0.0),
+                "skewy" => TransformFunction::Skew(0.0, parse_angle(input)?),
+                "matrix" => {
+                    let mut m = [0.0; 6];
+                    for (i, v) in m.iter_mut().enumerate() {
+                        if i > 0 {
+                            input.expect_comma()?;
+                        }
+                        *v = input.expect_number()?;
+                    }
+                    TransformFunction::Matrix(m)
+                }
+                _ => {
+                    return Err(location
+                        .new_unexpected_token_error(Token::Function(function.clone().into())));
+                }
+            })
+        })
+    }
+
+    /// One or two of `left`, `center`, `right`, `top`, `bottom` or lengths,
+    /// horizontal first unless the keywords tell otherwise.
+    fn parse_origin<'i, 't>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<(Dimension, Dimension), ParseError<'i, CustomParseError>> {
+        // the offset and whether it can only be vertical (true) or horizontal (false)
+        let mut component = |input: &mut Parser<'i, '_>| {
+            if let Ok(keyword) = input.r#try(|input| input.expect_ident().map(|i| i.to_lowercase()))
+            {
+                let location = input.current_source_location();
+                return match keyword.as_ref() {
+                    "left" => Ok((Dimension::Percent(0.0), Some(false))),
+                    "right" => Ok((Dimension::Percent(1.0), Some(false))),
+                    "top" => Ok((Dimension::Percent(0.0), Some(true))),
+                    "bottom" => Ok((Dimension::Percent(1.0), Some(true))),
+                    "center" => Ok((Dimension::Percent(0.5), None)),
+                    _ => Err(location.new_unexpected_token_error(Token::Ident(keyword.into()))),
+                };
+            }
+            self.parse_dimension(input).map(|d| (d, None))
+        };
+        let first = component(input)?;
+        let center = Dimension::Percent(0.5);
+        Ok(match input.r#try(|input| component(input)) {
+            Ok(second) if first.1 == Some(true) || second.1 == Some(false) => (second.0, first.0),
+            Ok(second) => (first.0, second.0),
+            Err(_) if first.1 == Some(true) => (center, first.0),
+            Err(_) => (first.0, center),
+        })
+    }
 }
 
 impl<'i> cssparser::DeclarationParser<'i> for DeclarationParser {
@@ -589,33 +909,174 @@ impl<'i> cssparser::DeclarationParser<'i> for DeclarationParser {
         input: &mut Parser<'i, 't>,
     ) -> Result<Self::Declaration, ParseError<'i, Self::Error>> {
         let value = match &*name {
-            "color" | "border-color" | "icon-color" => Value::Color(parse_basic_color(input)?),
+            "color" | "border-color" | "outline-color" | "icon-color" => {
+                Value::Color(parse_basic_color(input)?)
+            }
+
+            "foreground" | "background-color" => Value::Color(parse_basic_color(input)?),
+
+            // a color, a picture or a gradient
+            "background" | "background-image" => {
+                if input
+                    .r#try(|input| input.expect_ident_matching("none"))
+                    .is_ok()
+                {
+                    Value::None
+                } else if let Ok(color) = input.r#try(parse_basic_color) {
+                    Value::Color(color)
+                } else if let Ok(url) = input.r#try(|input| input.expect_url()) {
+                    Value::Url(url.to_string())
+                } else {
+                    Value::Gradient(parse_gradient(input)?)
+                }
+            }
 
-            "background" | "foreground" => Value::Color(parse_basic_color(input)?),
+            // thumb and track
+            "scrollbar-color" => {
+                let mut colors = vec![parse_basic_color(input)?];
+                if let Ok(track) = input.r#try(parse_basic_color) {
+                    colors.push(track);
+                }
+                Value::Colors(colors)
+            }
 
-            "font-family" | "icon-font-family" => Value::Str(parse_string(input)?),
+            "scrollbar-width" => match input.next()?.clone() {
+                Token::Number { value: x, .. } => Value::Float(x),
+                Token::Ident(ref id) => Value::Ident(id.to_string().to_lowercase()),
+                t => {
+                    return Err(input
+                        .current_source_location()
+                        .new_basic_unexpected_token_error(t.clone())
+                        .into());
+                }
+            },
+
+            // 1 to 4 radii, clockwise from the top left corner
+            "border-radius" => {
+                let mut radii = Vec::with_capacity(4);
+                while radii.len() < 4 {
+                    match input.r#try(|input| {
+                        let location = input.current_source_location();
+                        match *input.next()? {
+                            Token::Number { value, .. } if value >= 0.0 => Ok(value),
+                            ref t => Err(location.new_basic_unexpected_token_error(t.clone())),
+                        }
+                    }) {
+                        Ok(radius) => radii.push(radius),
+                        Err(_) => break,
+                    }
+                }
+                match radii[..] {
+                    [a, b, c, d] => Value::Corners([a, b, c, d]),
+                    [a, b, c] => Value::Corners([a, b, c, b]),
+                    [a, b] => Value::Corners([a, b, a, b]),
+                    [a] => Value::Corners([a; 4]),
+                    _ => {
+                        return Err(input
+                            .current_source_location()
+                            .new_basic_unexpected_token_error(input.next()?.clone())
+                            .into());
+                    }
+                }
+            }
 
-            "margin" | "padding" => {
-                let mut array: [_; 4] = [None; 4];
-                let mut i: usize = 0;
-                while let Ok(n) = self.parse_dimension(input) {
-                    array[i] = Some(n);
-                    i += 1;
+            // width, style and color in any order
+            "border" | "outline" => {
+                let mut border = BorderShorthand::default();
+                loop {
+                    if border.width.is_none() {
+                        if let Ok(width) = input.r#try(|input| self.parse_dimension(input)) {
+                            border.width = Some(width);
+                            continue;
+                        }
+                    }
+                    if border.style.is_none() {
+                        if let Ok(style) = input.r#try(parse_border_style) {
+                            border.style = Some(style);
+                            continue;
+                        }
+                    }
+                    if border.color.is_none() {
+                        if let Ok(color) = input.r#try(parse_basic_color) {
+                            border.color = Some(color);
+                            continue;
+                        }
+                    }
+                    break;
                 }
+                Value::Border(border)
+            }
 
-                match i {
-                    4 /*tlrb*/ => Value::Rect(Rect { top: array[0].unwrap(), start: array[1].unwrap(), end: array[2].unwrap(), bottom: array[3].unwrap() }),
-                    3 /*thb*/ => Value::Rect(Rect { top: array[0].unwrap(), start: array[1].unwrap(), end: array[1].unwrap(), bottom: array[2].unwrap() }),
-                    2 /*vh*/ => Value::Rect(Rect { top: array[0].unwrap(), start: array[1].unwrap(), end: array[1].unwrap(), bottom: array[0].unwrap() }),
-                    1 /*vh*/ => Value::Rect(Rect { top: array[0].unwrap(), start: array[0].unwrap(), end: array[0].unwrap(), bottom: array[0].unwrap() }),
-                    _ => unimplemented!(),
+            "font-family" | "icon-font-family" => Value::Str(parse_string(input)?),
+
+            // top, right, bottom and left, the missing sides copied from the opposite ones
+            "margin" | "padding" | "border-width" => {
+                let mut sides = Vec::with_capacity(4);
+                while sides.len() < 4 {
+                    match input.r#try(|input| self.parse_dimension(input)) {
+                        Ok(side) => sides.push(side),
+                        Err(_) => break,
+                    }
+                }
+                if sides.len() == 4 && !input.is_exhausted() {
+                    return Err(input.new_error_for_next_token());
+                }
+                // start and end are the left and right edges
+                match sides[..] {
+                    [top, right, bottom, left] => Value::Rect(Rect {
+                        start: left,
+                        end: right,
+                        top,
+                        bottom,
+                    }),
+                    [top, horizontal, bottom] => Value::Rect(Rect {
+                        start: horizontal,
+                        end: horizontal,
+                        top,
+                        bottom,
+                    }),
+                    [vertical, horizontal] => Value::Rect(Rect {
+                        start: horizontal,
+                        end: horizontal,
+                        top: vertical,
+                        bottom: vertical,
+                    }),
+                    [all] => Value::Rect(Rect {
+                        start: all,
+                        end: all,
+                        top: all,
+                        bottom: all,
+                    }),
+                    _ => return Err(input.new_error_for_next_token()),
                 }
             }
 
-            "border-radius" | "border-width" | "width" | "height" | "min-width" | "min-height"
-            | "max-width" | "max-height" | "padding-top" | "padding-right" | "padding-bottom"
-            | "padding-left" | "margin-top" | "margin-right" | "margin-bottom" | "margin-left"
-            | "font-size" | "icon-size" | "icon-margin" => match input.next()?.clone() {
+            "border-top-width"
+            | "border-right-width"
+            | "border-bottom-width"
+            | "border-left-width"
+            | "outline-width"
+            | "border-top-left-radius"
+            | "border-top-right-radius"
+            | "border-bottom-right-radius"
+            | "border-bottom-left-radius"
+            | "width"
+            | "height"
+            | "min-width"
+            | "min-height"
+            | "max-width"
+            | "max-height"
+            | "padding-top"
+            | "padding-right"
+            | "padding-bottom"
+            | "padding-left"
+            | "margin-top"
+            | "margin-right"
+            | "margin-bottom"
+            | "margin-left"
+            | "font-size"
+            | "icon-size"
+            | "icon-margin" => match input.next()?.clone() {
                 Token::Number {
                     int_value: Some(x),
                     has_sign,
@@ -629,7 +1090,81 @@ impl<'i> cssparser::DeclarationParser<'i> for DeclarationParser {
                 }
             },
 
-            "opacity" => match input.next()?.clone() {
+            "z-index" => match input.next()?.clone() {
+                Token::Number {
+                    int_value: Some(x), ..
+                } => Value::Int(x),
+                Token::Ident(ref id) if id.eq_ignore_ascii_case("auto") => {
+                    Value::Ident("auto".to_string())
+                }
+                t => {
+                    return Err(input
+                        .current_source_location()
+                        .new_basic_unexpected_token_error(t.clone())
+                        .into());
+                }
+            },
+
+            "border-image" => {
+                if input
+                    .r#try(|input| input.expect_ident_matching("none"))
+                    .is_ok()
+                {
+                    Value::None
+                } else {
+                    Value::BorderImage(parse_border_image(input)?)
+                }
+            }
+
+            "box-shadow" => {
+                if input
+                    .r#try(|input| input.expect_ident_matching("none"))
+                    .is_ok()
+                {
+                    Value::Shadows(Vec::new())
+                } else {
+                    Value::Shadows(input.parse_comma_separated(parse_box_shadow)?)
+                }
+            }
+
+            // offsets of a positioned element, which can be negative
+            "left" | "top" | "right" | "bottom" => match input.next()?.clone() {
+                Token::Number { value: x, .. } => Value::Float(x),
+                Token::Percentage { unit_value, .. } => {
+                    Value::Dimension(Dimension::Percent(unit_value))
+                }
+                Token::Ident(ref id) if id.eq_ignore_ascii_case("auto") => {
+                    Value::Ident("auto".to_string())
+                }
+                t => {
+                    return Err(input
+                        .current_source_location()
+                        .new_basic_unexpected_token_error(t.clone())
+                        .into());
+                }
+            },
+
+            "transform" => {
+                if input
+                    .r#try(|input| input.expect_ident_matching("none"))
+                    .is_ok()
+                {
+                    Value::None
+                } else {
+                    let mut functions = vec![self.parse_transform_function(input)?];
+                    while let Ok(f) = input.r#try(|input| self.parse_transform_function(input)) {
+                        functions.push(f);
+                    }
+                    Value::Transform(functions)
+                }
+            }
+
+            "transform-origin" => {
+                let (x, y) = self.parse_origin(input)?;
+                Value::Origin(x, y)
+            }
+
+            "opacity" | "outline-offset" => match input.next()?.clone() {
                 Token::Number { value: x, .. } => Value::Float(x as f32),
                 t => {
                     return Err(input
@@ -641,7 +1176,7 @@ impl<'i> cssparser::DeclarationParser<'i> for DeclarationParser {
 
             _ => match input.next()?.clone() {
                 Token::Ident(rc) => {
-                    println!("ident {}: {:?}", name, rc);
+                    debug!("ident {}: {:?}", name, rc);
                     Value::Ident(rc.to_string().to_lowercase().to_string())
                 }
                 _ => {
@@ -714,6 +1249,273 @@ fn parse_string<'i, 't>(
     })
 }
 
+/// `linear-gradient([<angle> | to <side or corner>,] <stops>)` or
+/// `radial-gradient([circle | ellipse,] <stops>)`.
+fn parse_gradient<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<Gradient, ParseError<'i, CustomParseError>> {
+    let location = input.current_source_location();
+    let function = input.expect_function()?.clone();
+    let linear = if function.eq_ignore_ascii_case("linear-gradient") {
+        true
+    } else if function.eq_ignore_ascii_case("radial-gradient") {
+        false
+    } else {
+        return Err(location.new_unexpected_token_error(Token::Function(function)));
+    };
+
+    input.parse_nested_block(|input| {
+        let shape = if linear {
+            let angle = input.r#try(parse_gradient_angle);
+            if angle.is_ok() {
+                input.expect_comma()?;
+            }
+            // towards the bottom by default
+            GradientShape::Linear {
+                angle: angle.unwrap_or(std::f32::consts::PI),
+            }
+        } else {
+            let circle = input.r#try(|input| {
+                let location = input.current_source_location();
+                let ident = input.expect_ident()?.clone();
+                if ident.eq_ignore_ascii_case("circle") {
+                    Ok(true)
+                } else if ident.eq_ignore_ascii_case("ellipse") {
+                    Ok(false)
+                } else {
+                    Err(location.new_basic_unexpected_token_error(Token::Ident(ident)))
+                }
+            });
+            if circle.is_ok() {
+                input.expect_comma()?;
+            }
+            GradientShape::Radial {
+                circle: circle.unwrap_or(false),
+            }
+        };
+
+        let stops = input.parse_comma_separated(|input| {
+            let color = parse_basic_color(input)?;
+            let position = input.r#try(|input| input.expect_percentage()).ok();
+            Ok((color, position))
+        })?;
+        if stops.len() < 2 {
+            return Err(input
+                .current_source_location()
+                .new_basic_unexpected_token_error(input.next()?.clone())
+                .into());
+        }
+        Ok(Gradient {
+            shape,
+            stops: resolve_stops(&stops),
+        })
+    })
+}
+
+/// An angle in radians, clockwise from the top.
+fn parse_gradient_angle<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<f32, ParseError<'i, CustomParseError>> {
+    let location = input.current_source_location();
+    if input
+        .r#try(|input| input.expect_ident_matching("to"))
+        .is_ok()
+    {
+        let (mut x, mut y) = (0.0f32, 0.0f32);
+        for _ in 0..2 {
+            let side = match input.r#try(|input| input.expect_ident().map(|i| i.to_lowercase())) {
+                Ok(side) => side,
+                Err(_) => break,
+            };
+            match side.as_ref() {
+                "left" => x = -1.0,
+                "right" => x = 1.0,
+                "top" => y = -1.0,
+                "bottom" => y = 1.0,
+                _ => return Err(location.new_unexpected_token_error(Token::Ident(side.into()))),
+            }
+        }
+        if x == 0.0 && y == 0.0 {
+            return Err(location.new_unexpected_token_error(Token::Ident("to".into())));
+        }
+        // corners are taken at 45 degrees, whatever the aspect ratio of the box
+        return Ok(x.atan2(-y));
+    }
+
+    parse_angle(input)
+}
+
+/// An angle in radians, `deg`, `grad`, `rad` or `turn`. A unitless zero is
+/// allowed.
+fn parse_angle<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<f32, ParseError<'i, CustomParseError>> {
+    use std::f32::consts::PI;
+
+    let location = input.current_source_location();
+    match *input.next()? {
+        Token::Number { value, .. } if value == 0.0 => Ok(0.0),
+        Token::Dimension {
+            value, ref unit, ..
+        } => {
+            let turns = match &*unit.to_lowercase() {
+                "deg" => value / 360.0,
+                "grad" => value / 400.0,
+                "rad" => value / (2.0 * PI),
+                "turn" => value,
+                _ => return Err(location.new_unexpected_token_error(Token::Ident(unit.clone()))),
+            };
+            Ok(turns * 2.0 * PI)
+        }
+        ref t => Err(location.new_unexpected_token_error(t.clone())),
+    }
+}
+
+/// Gives every stop a position: the first defaults to 0, the last to 1 and the
+/// ones in between are spread evenly. Positions never go backwards.
+fn resolve_stops(stops: &[(Color, Option<f32>)]) -> Vec<GradientStop> {
+    let mut positions: Vec<Option<f32>> = stops.iter().map(|s| s.1).collect();
+    let last = positions.len() - 1;
+    positions[0] = Some(positions[0].unwrap_or(0.0));
+    positions[last] = Some(positions[last].unwrap_or(1.0));
+
+    let mut previous = 0;
+    for i in 1..positions.len() {
+        if let Some(position) = positions[i] {
+            let start = positions[previous].unwrap();
+            let position = position.max(start);
+            positions[i] = Some(position);
+            let gap = (i - previous) as f32;
+            for j in previous + 1..i {
+                positions[j] = Some(start + (position - start) * (j - previous) as f32 / gap);
+            }
+            previous = i;
+        }
+    }
+
+    stops
+        .iter()
+        .zip(positions)
+        .map(|((color, _), position)| GradientStop {
+            color: (*color).into(),
+            position: position.unwrap(),
+        })
+        .collect()
+}
+
+/// `url(...) <1 to 4 slices> [fill] [stretch | repeat | round]`, slices in
+/// picture pixels.
+fn parse_border_image<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<BorderImage, ParseError<'i, CustomParseError>> {
+    let path = input.expect_url()?.to_string();
+
+    let mut slices = Vec::with_capacity(4);
+    while slices.len() < 4 {
+        match input.r#try(|input| input.expect_number()) {
+            Ok(x) => slices.push(x.max(0.0)),
+            Err(_) => break,
+        }
+    }
+    let slice = match slices[..] {
+        [top, right, bottom, left] => [top, right, bottom, left],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [all] => [all; 4],
+        _ => {
+            return Err(input
+                .current_source_location()
+                .new_basic_unexpected_token_error(input.next()?.clone())
+                .into());
+        }
+    };
+
+    let mut fill = false;
+    let mut repeat = None;
+    loop {
+        let location = input.current_source_location();
+        let ident = match input.r#try(|input| input.expect_ident().map(|i| i.to_lowercase())) {
+            Ok(ident) => ident,
+            Err(_) => break,
+        };
+        match ident.as_ref() {
+            "fill" if !fill => fill = true,
+            "stretch" if repeat.is_none() => repeat = Some(BorderRepeat::Stretch),
+            "repeat" if repeat.is_none() => repeat = Some(BorderRepeat::Repeat),
+            "round" if repeat.is_none() => repeat = Some(BorderRepeat::Round),
+            _ => return Err(location.new_unexpected_token_error(Token::Ident(ident.into()))),
+        }
+    }
+
+    Ok(BorderImage {
+        path: path.into(),
+        slice,
+        fill,
+        repeat: repeat.unwrap_or_default(),
+    })
+}
+
+/// `x y [blur [spread]]`, an optional color and `inset`, in any order.
+fn parse_box_shadow<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<BoxShadow, ParseError<'i, CustomParseError>> {
+    let mut lengths = Vec::with_capacity(4);
+    let mut color = None;
+    let mut inset = false;
+    loop {
+        if !inset
+            && input
+                .r#try(|input| input.expect_ident_matching("inset"))
+                .is_ok()
+        {
+            inset = true;
+            continue;
+        }
+        if color.is_none() {
+            if let Ok(c) = input.r#try(parse_basic_color) {
+                color = Some(c);
+                continue;
+            }
+        }
+        if lengths.len() < 4 {
+            if let Ok(x) = input.r#try(|input| input.expect_number()) {
+                lengths.push(x);
+                continue;
+            }
+        }
+        break;
+    }
+    if lengths.len() < 2 {
+        return Err(input
+            .current_source_location()
+            .new_basic_unexpected_token_error(input.next()?.clone())
+            .into());
+    }
+    Ok(BoxShadow {
+        x: lengths[0],
+        y: lengths[1],
+        blur: lengths.get(2).cloned().unwrap_or(0.0).max(0.0),
+        spread: lengths.get(3).cloned().unwrap_or(0.0),
+        color: color.unwrap_or(hex(0x000_000)),
+        inset,
+    })
+}
+
+fn parse_border_style<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<BorderStyle, ParseError<'i, CustomParseError>> {
+    let location = input.current_source_location();
+    let ident = input.expect_ident()?.to_lowercase();
+    match Value::Ident(ident).border_style() {
+        Some(style) => Ok(style),
+        None => Err(
+            location.new_custom_error(CustomParseError::InvalidStringName(
+                "border-style".to_string(),
+            )),
+        ),
+    }
+}
+
 fn parse_basic_color<'i, 't>(
     input: &mut Parser<'i, 't>,
 ) -> Result<Color, ParseError<'i, CustomParseError>> {
@@ -793,22 +1595,47 @@ impl Load<Ctx, SimpleKey> for Stylesheet {
     fn load(
         key: SimpleKey,
         _storage: &mut Storage<Ctx, SimpleKey>,
-        _ctx: &mut Ctx,
+        ctx: &mut Ctx,
     ) -> Result<Loaded<Self, SimpleKey>, Error> {
-        match key {
-            SimpleKey::Path(path) => {
-                println!("Load Stylesheet {}", path.display());
-                let mut fh = File::open(path).map_err(Error::IOError)?;
-                let mut buf = String::new();
-                fh.read_to_string(&mut buf).map_err(Error::IOError)?;
-
-                let stylesheet = parse(&buf);
-                // storage.get::<ShaderSet>(&dep, ctx).unwrap();
-                Ok(Loaded::without_dep(stylesheet))
-            }
+        let stylesheet = match ctx.take_prepared::<Stylesheet>() {
+            Some(stylesheet) => stylesheet,
+            None => Self::prepare(&key, ctx)?,
+        };
+        Ok(Loaded::without_dep(stylesheet))
+    }
 
-            SimpleKey::Logical(_) => Err(Error::CannotLoadFromLogical),
-        }
+    fn reload(
+        &self,
+        key: SimpleKey,
+        storage: &mut Storage<Ctx, SimpleKey>,
+        ctx: &mut Ctx,
+    ) -> Result<Self, Error> {
+        let res = <Self as Load<Ctx, SimpleKey>>::load(key.clone(), storage, ctx)?.res;
+        ctx.reloaded::<Self>(key);
+        Ok(res)
+    }
+}
+
+impl Load<Ctx, SimpleKey, AlwaysFail> for Stylesheet {
+    type Error = Error;
+
+    fn load(
+        _key: SimpleKey,
+        _storage: &mut Storage<Ctx, SimpleKey>,
+        _ctx: &mut Ctx,
+    ) -> Result<Loaded<Self, SimpleKey>, Self::Error> {
+        Err(Error::CannotLoadFromFS)
+    }
+}
+
+/// Parsing runs on the loading thread.
+impl Prepare for Stylesheet {
+    type Prepared = Stylesheet;
+
+    fn prepare(key: &SimpleKey, ctx: &Ctx) -> Result<Stylesheet, Error> {
+        debug!("load stylesheet {}", key);
+        let bytes = ctx.sources().read(key)?;
+        Ok(parse(&String::from_utf8_lossy(&bytes)))
     }
 }
 
@@ -827,7 +1654,7 @@ pub fn parse(s: &str) -> Stylesheet {
         match *rule {
             Ok(_) => {}
             Err(ref e) => {
-                eprintln!("{:?}", e);
+                warn!("{:?}", e);
                 // println!("Error occured in `{}`", parser.slice(e.span.clone()));
             }
         }
@@ -841,3 +1668,166 @@ const fn hex(data: u32) -> Color {
         data: 0xFF | (data << 8),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of the only declaration of `A { <declaration> }`, if it parses.
+    fn value(declaration: &str) -> Option<Value> {
+        let mut stylesheet = parse(&format!("A {{ {} }}", declaration));
+        assert_eq!(stylesheet.0.len(), 1);
+        stylesheet.0.remove(0).declarations.pop().map(|d| d.value)
+    }
+
+    /// Top, right, bottom and left.
+    fn sides(declaration: &str) -> (Dimension, Dimension, Dimension, Dimension) {
+        match value(declaration) {
+            Some(Value::Rect(r)) => (r.top, r.end, r.bottom, r.start),
+            v => panic!("{}: {:?}", declaration, v),
+        }
+    }
+
+    #[test]
+    fn box_shorthands() {
+        let p = Dimension::Points;
+        assert_eq!(sides("margin: 1"), (p(1.0), p(1.0), p(1.0), p(1.0)));
+        assert_eq!(sides("padding: 1 2"), (p(1.0), p(2.0), p(1.0), p(2.0)));
+        assert_eq!(
+            sides("border-width: 1 2 3"),
+            (p(1.0), p(2.0), p(3.0), p(2.0))
+        );
+        assert_eq!(sides("margin: 1 2 3 4"), (p(1.0), p(2.0), p(3.0), p(4.0)));
+        assert_eq!(
+            sides("margin: 10% auto"),
+            (
+                Dimension::Percent(0.1),
+                Dimension::Auto,
+                Dimension::Percent(0.1),
+                Dimension::Auto
+            )
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        let offset = |declaration| value(declaration).and_then(|v| v.dimension());
+        assert_eq!(offset("left: -4"), Some(Dimension::Points(-4.0)));
+        assert_eq!(offset("bottom: 25%"), Some(Dimension::Percent(0.25)));
+        assert_eq!(offset("right: auto"), Some(Dimension::Auto));
+        assert_eq!(offset("top: red"), None);
+    }
+
+    #[test]
+    fn scrollbars() {
+        let colors =
+            |declaration| value(declaration).and_then(|v| v.colors().map(<[Color]>::to_vec));
+        let (red, blue) = (Color { data: 0xff00_00ff }, Color { data: 0x0000_ffff });
+        assert_eq!(colors("scrollbar-color: #ff0000"), Some(vec![red]));
+        assert_eq!(
+            colors("scrollbar-color: #ff0000 #0000ff"),
+            Some(vec![red, blue])
+        );
+        assert_eq!(colors("scrollbar-color: 12"), None);
+
+        let width = |declaration| value(declaration).and_then(|v| v.scrollbar_width());
+        assert_eq!(width("scrollbar-width: none"), Some(0.0));
+        assert_eq!(width("scrollbar-width: thin"), Some(6.0));
+        assert_eq!(width("scrollbar-width: AUTO"), Some(12.0));
+        assert_eq!(width("scrollbar-width: 8"), Some(8.0));
+        assert_eq!(width("scrollbar-width: wide"), None);
+
+        let overflow = |declaration| value(declaration).and_then(|v| v.overflow());
+        assert_eq!(overflow("overflow: scroll"), Some(Overflow::Scroll));
+        assert_eq!(overflow("overflow: hidden"), Some(Overflow::Hidden));
+    }
+
+    #[test]
+    fn border_images() {
+        let image = |declaration| value(declaration).and_then(|v| v.border_image().cloned());
+        assert_eq!(
+            image("border-image: url(skin.png) 10 fill stretch"),
+            Some(BorderImage {
+                path: "skin.png".into(),
+                slice: [10.0; 4],
+                fill: true,
+                repeat: BorderRepeat::Stretch,
+            })
+        );
+        assert_eq!(
+            image("border-image: url(skin.png) 1 2 3 round fill"),
+            Some(BorderImage {
+                path: "skin.png".into(),
+                slice: [1.0, 2.0, 3.0, 2.0],
+                fill: true,
+                repeat: BorderRepeat::Round,
+            })
+        );
+        let default = image("border-image: url(skin.png) 4 2").unwrap();
+        assert_eq!((default.slice, default.fill), ([4.0, 2.0, 4.0, 2.0], false));
+        assert_eq!(default.repeat, BorderRepeat::default());
+        // `none` removes the image, a missing slice or a repeated keyword is invalid
+        assert!(matches!(value("border-image: none"), Some(Value::None)));
+        assert_eq!(image("border-image: url(skin.png) fill"), None);
+        assert_eq!(image("border-image: url(skin.png) 4 repeat round"), None);
+    }
+
+    #[test]
+    fn opacity_and_visibility() {
+        let opacity = |declaration| value(declaration).and_then(|v| v.float());
+        assert_eq!(opacity("opacity: 0.5"), Some(0.5));
+        assert_eq!(opacity("opacity: 2"), Some(2.0));
+        assert_eq!(opacity("opacity: 50%"), None);
+
+        let visibility = |declaration| value(declaration).and_then(|v| v.visibility());
+        assert_eq!(visibility("visibility: visible"), Some(Some(true)));
+        assert_eq!(visibility("visibility: hidden"), Some(Some(false)));
+        assert_eq!(visibility("visibility: Collapse"), Some(Some(false)));
+        assert_eq!(visibility("visibility: inherit"), Some(None));
+        assert_eq!(visibility("visibility: 0"), None);
+    }
+
+    #[test]
+    fn outline_width() {
+        assert_eq!(value("outline-width: 2").and_then(|v| v.float()), Some(2.0));
+        assert!(value("outline-width: -2").is_none());
+    }
+
+    #[test]
+    fn position() {
+        use crate::layout::Position;
+        let position = |p: &str| value(&format!("position: {}", p)).and_then(|v| v.position_type());
+        assert_eq!(position("static"), Some(Position::Static));
+        assert_eq!(position("relative"), Some(Position::Relative));
+        assert_eq!(position("absolute"), Some(Position::Absolute));
+        assert_eq!(position("fixed"), None);
+    }
+
+    #[test]
+    fn box_shorthand_bad_count() {
+        assert!(value("margin: 1 2 3 4 5").is_none());
+        assert!(value("padding: ;").is_none());
+        assert!(value("border-width: solid").is_none());
+    }
+
+    #[test]
+    fn malformed_stylesheet_loads() {
+        let manager = ResourceManager::new(Arc::new(Fxc));
+        manager.insert(
+            "generated/bad.css",
+            &b"A { margin: 1 2 3 4 5; padding: ; color: red } B { margin: }"[..],
+        );
+        let key = SimpleKey::Logical("generated/bad.css".into());
+        let stylesheet = manager.get::<Stylesheet>(&key).unwrap();
+        let rules = &stylesheet.borrow().0;
+        // the invalid declarations are dropped, not the rules
+        assert_eq!(rules.len(), 2);
+        let properties: Vec<&str> = rules[0]
+            .declarations
+            .iter()
+            .map(|d| d.property.as_str())
+            .collect();
+        assert_eq!(properties, vec!["color"]);
+        assert!(rules[1].declarations.is_empty());
+    }
+}
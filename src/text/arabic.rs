@@ -0,0 +1,179 @@
+//! Contextual joining of arabic letters.
+//!
+//! The bitmap fonts only contain codepoints, not OpenType substitution tables,
+//! so joining is done by mapping letters onto their Presentation Forms-B
+//! codepoints (U+FE70..U+FEFF), which the font charset includes.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Joining {
+    /// Joins on both sides (beh, lam, ...): isolated, final, initial, medial.
+    Dual,
+    /// Only joins to the preceding letter (alef, dal, waw, ...): isolated, final.
+    Right,
+    /// Joins to both neighbours but has no forms of its own (tatweel).
+    Causing,
+    /// Marks are skipped when looking for neighbours.
+    Transparent,
+    None,
+}
+
+/// (letter, first presentation form, joining type). Forms are laid out as
+/// isolated, final, initial, medial starting at the first one.
+const LETTERS: &[(char, u32, Joining)] = &[
+    ('\u{0621}', 0xFE80, Joining::None),
+    ('\u{0622}', 0xFE81, Joining::Right),
+    ('\u{0623}', 0xFE83, Joining::Right),
+    ('\u{0624}', 0xFE85, Joining::Right),
+    ('\u{0625}', 0xFE87, Joining::Right),
+    ('\u{0626}', 0xFE89, Joining::Dual),
+    ('\u{0627}', 0xFE8D, Joining::Right),
+    ('\u{0628}', 0xFE8F, Joining::Dual),
+    ('\u{0629}', 0xFE93, Joining::Right),
+    ('\u{062A}', 0xFE95, Joining::Dual),
+    ('\u{062B}', 0xFE99, Joining::Dual),
+    ('\u{062C}', 0xFE9D, Joining::Dual),
+    ('\u{062D}', 0xFEA1, Joining::Dual),
+    ('\u{062E}', 0xFEA5, Joining::Dual),
+    ('\u{062F}', 0xFEA9, Joining::Right),
+    ('\u{0630}', 0xFEAB, Joining::Right),
+    ('\u{0631}', 0xFEAD, Joining::Right),
+    ('\u{0632}', 0xFEAF, Joining::Right),
+    ('\u{0633}', 0xFEB1, Joining::Dual),
+    ('\u{0634}', 0xFEB5, Joining::Dual),
+    ('\u{0635}', 0xFEB9, Joining::Dual),
+    ('\u{0636}', 0xFEBD, Joining::Dual),
+    ('\u{0637}', 0xFEC1, Joining::Dual),
+    ('\u{0638}', 0xFEC5, Joining::Dual),
+    ('\u{0639}', 0xFEC9, Joining::Dual),
+    ('\u{063A}', 0xFECD, Joining::Dual),
+    ('\u{0641}', 0xFED1, Joining::Dual),
+    ('\u{0642}', 0xFED5, Joining::Dual),
+    ('\u{0643}', 0xFED9, Joining::Dual),
+    ('\u{0644}', 0xFEDD, Joining::Dual),
+    ('\u{0645}', 0xFEE1, Joining::Dual),
+    ('\u{0646}', 0xFEE5, Joining::Dual),
+    ('\u{0647}', 0xFEE9, Joining::Dual),
+    ('\u{0648}', 0xFEED, Joining::Right),
+    ('\u{0649}', 0xFEEF, Joining::Right),
+    ('\u{064A}', 0xFEF1, Joining::Dual),
+];
+
+const LAM: char = '\u{0644}';
+
+/// Lam followed by an alef variant is always replaced by a mandatory ligature
+/// (isolated form, final form = isolated + 1).
+fn lam_alef(alef: char) -> Option<u32> {
+    match alef {
+        '\u{0622}' => Some(0xFEF5),
+        '\u{0623}' => Some(0xFEF7),
+        '\u{0625}' => Some(0xFEF9),
+        '\u{0627}' => Some(0xFEFB),
+        _ => None,
+    }
+}
+
+fn letter(c: char) -> Option<&'static (char, u32, Joining)> {
+    LETTERS.iter().find(|l| l.0 == c)
+}
+
+fn joining(c: char) -> Joining {
+    match c {
+        '\u{0640}' => Joining::Causing,
+        '\u{064B}'..='\u{065F}' | '\u{0670}' | '\u{06D6}'..='\u{06ED}' => Joining::Transparent,
+        c => letter(c).map_or(Joining::None, |l| l.2),
+    }
+}
+
+/// Returns true if the text contains anything `join` would rewrite.
+pub fn needs_joining(text: &str) -> bool {
+    text.chars().any(|c| letter(c).is_some())
+}
+
+/// Replaces arabic letters by their contextual presentation form. The text
+/// stays in logical order.
+pub fn join(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+
+    // the closest non transparent neighbour in each direction
+    let prev_of = |i: usize| {
+        chars[..i]
+            .iter()
+            .rev()
+            .find(|c| joining(**c) != Joining::Transparent)
+    };
+    let next_of = |i: usize| {
+        chars[i + 1..]
+            .iter()
+            .position(|c| joining(*c) != Joining::Transparent)
+            .map(|p| i + 1 + p)
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let (base, kind) = match letter(c) {
+            Some(&(_, base, kind)) if kind != Joining::None => (base, kind),
+            _ => {
+                out.push(c);
+                i += 1;
+                continue;
+            }
+        };
+
+        let joins_prev = match prev_of(i).map(|c| joining(*c)) {
+            Some(Joining::Dual) | Some(Joining::Causing) => true,
+            _ => false,
+        };
+        let next = next_of(i);
+
+        if c == LAM {
+            if let Some(ligature) = next
+                .filter(|n| *n == i + 1)
+                .and_then(|n| lam_alef(chars[n]))
+            {
+                out.push(form(ligature + joins_prev as u32));
+                i += 2;
+                continue;
+            }
+        }
+
+        let joins_next = kind == Joining::Dual
+            && match next.map(|n| joining(chars[n])) {
+                Some(Joining::Dual) | Some(Joining::Right) | Some(Joining::Causing) => true,
+                _ => false,
+            };
+
+        let offset = match (joins_prev, joins_next) {
+            (true, true) => 3,
+            (false, true) => 2,
+            (true, false) => 1,
+            (false, false) => 0,
+        };
+        out.push(form(base + offset));
+        i += 1;
+    }
+
+    out
+}
+
+fn form(codepoint: u32) -> char {
+    std::char::from_u32(codepoint).expect("presentation forms are valid chars")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_forms() {
+        // beh beh beh: initial, medial, final
+        assert_eq!(join("\u{0628}\u{0628}\u{0628}"), "\u{FE91}\u{FE92}\u{FE90}");
+        // alef does not join forward, so the following beh is isolated
+        assert_eq!(join("\u{0628}\u{0627}\u{0628}"), "\u{FE91}\u{FE8E}\u{FE8F}");
+        // lam alef ligature
+        assert_eq!(join("\u{0644}\u{0627}"), "\u{FEFB}");
+        // non arabic text is untouched
+        assert_eq!(join("abc"), "abc");
+    }
+}
@@ -0,0 +1,95 @@
+//! Unicode bidirectional reordering (UAX #9), on top of `unicode-bidi`.
+
+use std::ops::Range;
+
+use stretch::style::Direction;
+use unicode_bidi::{BidiInfo, Level};
+
+/// A run of text sharing one direction, in visual order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisualRun {
+    /// Byte range in the source text.
+    pub range: Range<usize>,
+    pub rtl: bool,
+    /// Index of the paragraph the run belongs to; each paragraph is a line.
+    pub line: usize,
+}
+
+/// Splits the text into directional runs, ordered left to right on each line.
+/// `Direction::Inherit` lets the first strong character pick the paragraph direction.
+pub fn visual_runs(text: &str, base: Direction) -> Vec<VisualRun> {
+    let level = match base {
+        Direction::LTR => Some(Level::ltr()),
+        Direction::RTL => Some(Level::rtl()),
+        Direction::Inherit => None,
+    };
+    let info = BidiInfo::new(text, level);
+
+    let mut runs = Vec::new();
+    for (line, para) in info.paragraphs.iter().enumerate() {
+        let (levels, para_runs) = info.visual_runs(para, para.range.clone());
+        runs.extend(
+            para_runs
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| VisualRun {
+                    rtl: levels[range.start].is_rtl(),
+                    range,
+                    line,
+                }),
+        );
+    }
+    runs
+}
+
+/// Bidi_Mirrored characters are replaced by their mirror in right-to-left runs.
+pub fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual(text: &str, base: Direction) -> String {
+        visual_runs(text, base)
+            .into_iter()
+            .map(|run| {
+                let s = &text[run.range];
+                if run.rtl {
+                    s.chars().rev().map(mirror).collect()
+                } else {
+                    s.to_owned()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorder_mixed() {
+        // hebrew alef bet gimel embedded in latin text
+        assert_eq!(
+            visual("ab \u{5D0}\u{5D1}\u{5D2} cd", Direction::LTR),
+            "ab \u{5D2}\u{5D1}\u{5D0} cd"
+        );
+        assert_eq!(visual("abc", Direction::RTL), "abc");
+        assert_eq!(
+            visual("\u{5D0}(\u{5D1})", Direction::Inherit),
+            "(\u{5D1})\u{5D0}"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Text shaping: turns `Text` strings into positioned glyph runs shared by
+//! layout measurement and rendering.
+
+mod arabic;
+mod bidi;
+mod shaping;
+
+pub use shaping::*;
+
+use specs::prelude::*;
+use specs::shrev::EventChannel;
+use stretch::style::Direction;
+
+use crate::layout::{BitmapFont, Dimensions};
+use crate::manager::*;
+use crate::rendering::Text;
+use crate::transform::Parent;
+
+pub const FONT_PATH: &str = "style/NotoSans-Regular.ttf";
+/// The font used when `FONT_PATH` does not load, built into the binary.
+pub const FALLBACK_FONT: &str = "font/fallback";
+pub const FONT_SIZE: u8 = 16;
+/// The bytes of `FONT_PATH`, built into the binary once for `FALLBACK_FONT`
+/// and the embedded assets of release builds.
+pub(crate) const FONT_BYTES: &[u8] = include_bytes!("../../style/NotoSans-Regular.ttf");
+
+lazy_static! {
+    /// Characters rasterized in the font bitmaps: latin, hebrew, arabic with
+    /// its presentation forms, and latin ligatures.
+    pub static ref CHARSET: Vec<char> = {
+        let ranges = [
+            0x20..0x7F,
+            0xA0..0x180,
+            0x591..0x5F5,
+            0x600..0x700,
+            0xFB00..0xFB07,
+            0xFE70..0xFF00,
+        ];
+        ranges
+            .iter()
+            .cloned()
+            .flatten()
+            .filter_map(std::char::from_u32)
+            .collect()
+    };
+}
+
+/// The key of `FALLBACK_FONT`.
+pub fn fallback_font() -> SimpleKey {
+    SimpleKey::Logical(FALLBACK_FONT.into())
+}
+
+/// Registers the bytes of `FALLBACK_FONT`.
+pub fn register_fallback_font(store: &ResourceManager) {
+    store.insert(FALLBACK_FONT, FONT_BYTES);
+}
+
+/// The shaped glyphs of an entity's `Text`.
+#[derive(Debug)]
+pub struct ShapedText {
+    source: String,
+    direction: Direction,
+    pub run: GlyphRun,
+}
+
+impl Component for ShapedText {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Shapes every `Text` whose content or resolved direction changed.
+#[derive(Default)]
+pub struct TextShapingSystem {
+    font: Option<Handle<BitmapFont>>,
+    reloads: Option<ReaderId<ResourceReloaded>>,
+}
+impl<'a> System<'a> for TextShapingSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, ResourceManager>,
+        Read<'a, EventChannel<ResourceReloaded>>,
+        Write<'a, Diagnostics>,
+        ReadStorage<'a, Text>,
+        ReadStorage<'a, Parent>,
+        ReadStorage<'a, Dimensions>,
+        WriteStorage<'a, ShapedText>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, store, reloads, mut diagnostics, texts, parents, dimensions, mut shaped): Self::SystemData,
+    ) {
+        let stale: Vec<Entity> = (&entities, &shaped, !&texts)
+            .join()
+            .map(|(e, _, _)| e)
+            .collect();
+        for e in stale {
+            shaped.remove(e);
+        }
+
+        // glyph runs of a reloaded font are stale: everything is shaped again
+        let reader = self
+            .reloads
+            .as_mut()
+            .expect("`TextShapingSystem::setup` was not called before `TextShapingSystem::run`");
+        if reloads
+            .read(reader)
+            .any(|reloaded| reloaded.is::<BitmapFont>())
+        {
+            shaped.clear();
+        }
+
+        let handle = *self
+            .font
+            .get_or_insert_with(|| store.handle(&SimpleKey::Path(FONT_PATH.into())));
+        // texts are shaped once the font is ready, and take no room until then
+        let font = match store.resolve_async(handle) {
+            Ok(Some(font)) => font,
+            Ok(None) => return,
+            Err(e) => {
+                let key = store.key(handle);
+                diagnostics.report_resource(&key, Err(&e));
+                // shaped with the built-in font from now on
+                if key != fallback_font() {
+                    self.font = Some(store.handle(&fallback_font()));
+                }
+                return;
+            }
+        };
+        let font = font.borrow();
+
+        for (e, text) in (&entities, &texts).join() {
+            let direction = Dimensions::resolve_direction(e, &dimensions, &parents);
+            if let Some(previous) = shaped.get(e) {
+                if previous.source == text.text && previous.direction == direction {
+                    continue;
+                }
+            }
+
+            let run = shape(&font, &text.text, direction);
+            shaped
+                .insert(
+                    e,
+                    ShapedText {
+                        source: text.text.clone(),
+                        direction,
+                        run,
+                    },
+                )
+                .unwrap();
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        use specs::prelude::SystemData;
+        Self::SystemData::setup(res);
+        let mut reloads = res.fetch_mut::<EventChannel<ResourceReloaded>>();
+        self.reloads = Some(reloads.register_reader());
+    }
+}
@@ -0,0 +1,131 @@
+use stretch::geometry::Size;
+use stretch::style::Direction;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::layout::BitmapFont;
+use crate::text::{arabic, bidi};
+
+/// A glyph placed relative to the text origin, top left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapedGlyph {
+    pub ch: char,
+    pub x: f32,
+    pub y: f32,
+    /// Advance and line height: the box the glyph occupies on its line.
+    pub width: f32,
+    pub height: f32,
+    /// Index of the grapheme cluster the glyph belongs to, in visual order.
+    pub cluster: usize,
+}
+
+/// The output of the shaping stage: glyphs in visual order and the size of their bounding box.
+#[derive(Clone, Debug, Default)]
+pub struct GlyphRun {
+    pub glyphs: Vec<ShapedGlyph>,
+    pub size: Size<f32>,
+}
+
+/// Latin ligatures, substituted only when the font has the ligature glyph.
+const LIGATURES: &[(&str, char)] = &[
+    ("ffi", '\u{FB03}'),
+    ("ffl", '\u{FB04}'),
+    ("ff", '\u{FB00}'),
+    ("fi", '\u{FB01}'),
+    ("fl", '\u{FB02}'),
+];
+
+fn ligatures(font: &BitmapFont, text: &str) -> String {
+    let mut text = text.to_owned();
+    for (sequence, ligature) in LIGATURES {
+        if text.contains(sequence) && font.has_char(*ligature) {
+            text = text.replace(sequence, &ligature.to_string());
+        }
+    }
+    text
+}
+
+/// Turns a string into positioned glyphs: contextual forms and ligatures are
+/// substituted, the text is reordered for display, split in grapheme clusters
+/// and kerned. `base` is the paragraph direction.
+pub fn shape(font: &BitmapFont, text: &str, base: Direction) -> GlyphRun {
+    let mut text = ligatures(font, text);
+    if arabic::needs_joining(&text) {
+        text = arabic::join(&text);
+    }
+
+    let line_height = f32::from(font.0.get_font_height());
+    let mut run = GlyphRun::default();
+    let mut cluster = 0;
+    let mut line = None;
+    let mut pen = 0;
+    let mut previous: Option<char> = None;
+    let mut line_width = 0;
+
+    for visual in bidi::visual_runs(&text, base) {
+        if line != Some(visual.line) {
+            line = Some(visual.line);
+            run.size.width = run.size.width.max(line_width as f32);
+            run.size.height += line_height;
+            pen = 0;
+            previous = None;
+            line_width = 0;
+        }
+        let y = visual.line as f32 * line_height;
+
+        let mut clusters: Vec<&str> = text[visual.range.clone()].graphemes(true).collect();
+        if visual.rtl {
+            clusters.reverse();
+        }
+
+        for grapheme in clusters {
+            let mut chars = grapheme.chars();
+            let base_char = match chars.next() {
+                Some(c) if visual.rtl => bidi::mirror(c),
+                Some(c) => c,
+                None => continue,
+            };
+            let info = match font.0.find_char(base_char) {
+                Some(info) => info,
+                None => continue,
+            };
+
+            if let Some(previous) = previous {
+                pen += font.kerning(previous, base_char);
+            }
+            previous = Some(base_char);
+
+            run.glyphs.push(ShapedGlyph {
+                ch: base_char,
+                x: pen as f32,
+                y,
+                width: info.x_advance as f32,
+                height: line_height,
+                cluster,
+            });
+
+            // combining marks stack on the base glyph instead of advancing the pen
+            for mark in chars {
+                if let Some(mark_info) = font.0.find_char(mark) {
+                    run.glyphs.push(ShapedGlyph {
+                        ch: mark,
+                        x: (pen + (info.x_advance - mark_info.x_advance) / 2) as f32,
+                        y,
+                        width: mark_info.x_advance as f32,
+                        height: line_height,
+                        cluster,
+                    });
+                }
+            }
+
+            line_width = pen + info.x_offset + info.width;
+            pen += info.x_advance;
+            cluster += 1;
+        }
+    }
+    run.size.width = run.size.width.max(line_width as f32);
+    if run.size.height == 0.0 {
+        run.size.height = line_height;
+    }
+
+    run
+}
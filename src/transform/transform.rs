@@ -1,30 +1,49 @@
 use specs::prelude::*;
 
-use cgmath::{Matrix4, Point2};
+use cgmath::{Matrix4, Point2, SquareMatrix};
 
+/// Placement of an entity relative to its parent.
+///
+/// For an element laid out by the `LayoutSystem`, `position` is an offset
+/// added to its layout box and `size` its preferred size, used when the style
+/// leaves the width or height to `auto`. Other entities float freely: their
+/// box is at `position` with `size`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Transform {
     pub position: Point2<f32>,
-    pub size: Point2<f32>,
+    pub size: Option<Point2<f32>>,
+    /// Applied after the offset, around the top left corner of the box.
+    pub local: Matrix4<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(0.0, 0.0)
+    }
 }
 
 impl Transform {
     pub fn new(x: f32, y: f32) -> Self {
         Transform {
             position: (x, y).into(),
-            size: (100.0, 100.0).into(),
+            size: None,
+            local: Matrix4::identity(),
         }
     }
 
     pub fn with_size(self, w: f32, h: f32) -> Self {
         Self {
-            size: (w, h).into(),
+            size: Some((w, h).into()),
             ..self
         }
     }
 
+    pub fn with_local(self, local: Matrix4<f32>) -> Self {
+        Self { local, ..self }
+    }
+
     pub fn matrix(&self) -> Matrix4<f32> {
-        cgmath::Matrix4::from_translation([self.position.x, self.position.y, 0.0f32].into())
+        Matrix4::from_translation([self.position.x, self.position.y, 0.0f32].into()) * self.local
     }
 }
 
@@ -1,7 +1,8 @@
-use hibitset::BitSet;
+use cgmath::{Matrix4, SquareMatrix};
 use specs::prelude::*;
 use specs_hierarchy::HierarchyEvent;
 
+use crate::layout::LayoutBox;
 use crate::transform::global_transform::GlobalTransform;
 use crate::transform::parent::{Parent, ParentHierarchy};
 use crate::transform::transform::Transform;
@@ -13,12 +14,9 @@ impl Component for Vel {
     type Storage = VecStorage<Self>;
 }
 
+/// Composes the `GlobalTransform` of every placed entity, parents first: the
+/// layout box if any, then the local `Transform`, then the css transform.
 pub struct TransformSystem {
-    local_modified: BitSet,
-    global_modified: BitSet,
-
-    locals_events_id: Option<ReaderId<ComponentEvent>>,
-
     parent_events_id: Option<ReaderId<HierarchyEvent>>,
 
     scratch: Vec<Entity>,
@@ -28,13 +26,38 @@ impl TransformSystem {
     /// Creates a new transform processor.
     pub fn new() -> TransformSystem {
         TransformSystem {
-            locals_events_id: None,
             parent_events_id: None,
-            local_modified: BitSet::default(),
-            global_modified: BitSet::default(),
             scratch: Vec::new(),
         }
     }
+
+    fn compose(
+        e: Entity,
+        parent: Matrix4<f32>,
+        locals: &ReadStorage<'_, Transform>,
+        boxes: &ReadStorage<'_, LayoutBox>,
+        global: &mut GlobalTransform,
+    ) {
+        let local = locals.get(e);
+        let local_matrix = local.map_or(Matrix4::identity(), |t| t.matrix());
+        match boxes.get(e) {
+            Some(b) => {
+                let location = Matrix4::from_translation([b.location.0, b.location.1, 0.0].into());
+                global.0 = parent * location * local_matrix * b.transform;
+                global.1 = b.size;
+            }
+            None => {
+                global.0 = parent * local_matrix;
+                global.1 = local
+                    .and_then(|t| t.size)
+                    .map_or((0.0, 0.0), |size| (size.x, size.y));
+            }
+        }
+        debug_assert!(
+            global.is_finite(),
+            format!("Entity {:?} had a non-finite `Transform`", e)
+        );
+    }
 }
 
 impl<'a> System<'a> for TransformSystem {
@@ -42,93 +65,55 @@ impl<'a> System<'a> for TransformSystem {
         Entities<'a>,
         ReadExpect<'a, ParentHierarchy>,
         ReadStorage<'a, Transform>,
+        ReadStorage<'a, LayoutBox>,
         ReadStorage<'a, Parent>,
         WriteStorage<'a, GlobalTransform>,
     );
-    fn run(&mut self, (entities, hierarchy, locals, parents, mut globals): Self::SystemData) {
+    fn run(
+        &mut self,
+        (entities, hierarchy, locals, boxes, parents, mut globals): Self::SystemData,
+    ) {
         #[cfg(feature = "profiler")]
         profile_scope!("transform_system");
 
         self.scratch.clear();
-        self.scratch
-            .extend((&*entities, &locals, !&globals).join().map(|d| d.0));
+        self.scratch.extend(
+            (&*entities, locals.mask() | boxes.mask(), !&globals)
+                .join()
+                .map(|d| d.0),
+        );
         for entity in &self.scratch {
             globals
                 .insert(*entity, GlobalTransform::default())
                 .expect("unreachable");
         }
 
-        self.local_modified.clear();
-        self.global_modified.clear();
-
-        locals
-            .channel()
-            .read(
-                self.locals_events_id.as_mut().expect(
-                    "`TransformSystem::setup` was not called before `TransformSystem::run`",
-                ),
-            )
-            .for_each(|event| match event {
-                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
-                    self.local_modified.add(*id);
-                }
-                ComponentEvent::Removed(_id) => {}
-            });
-
         for event in hierarchy.changed().read(
             self.parent_events_id
                 .as_mut()
                 .expect("`TransformSystem::setup` was not called before `TransformSystem::run`"),
         ) {
-            match *event {
-                HierarchyEvent::Removed(entity) => {
-                    // Sometimes the user may have already deleted the entity.
-                    // This is fine, so we'll ignore any errors this may give
-                    // since it can only fail due to the entity already being dead.
-                    let _ = entities.delete(entity);
-                }
-                HierarchyEvent::Modified(entity) => {
-                    self.local_modified.add(entity.id());
-                }
+            if let HierarchyEvent::Removed(entity) = *event {
+                // Sometimes the user may have already deleted the entity.
+                // This is fine, so we'll ignore any errors this may give
+                // since it can only fail due to the entity already being dead.
+                let _ = entities.delete(entity);
             }
         }
 
-        // Compute transforms without parents.
-        for (entity, _, local, global, _) in (
-            &*entities,
-            &self.local_modified,
-            &locals,
-            &mut globals,
-            !&parents,
-        )
-            .join()
-        {
-            self.global_modified.add(entity.id());
-            global.0 = local.matrix();
-            debug_assert!(
-                global.is_finite(),
-                format!("Entity {:?} had a non-finite `Transform`", entity)
-            );
+        // the layout changes every frame, so everything is composed again
+        for (entity, global, _) in (&*entities, &mut globals, !&parents).join() {
+            Self::compose(entity, Matrix4::identity(), &locals, &boxes, global);
         }
 
-        // Compute transforms with parents.
+        // the hierarchy lists parents before their children
         for entity in hierarchy.all() {
-            let self_dirty = self.local_modified.contains(entity.id());
-            if let (Some(parent), Some(local)) = (parents.get(*entity), locals.get(*entity)) {
-                let parent_dirty = self.global_modified.contains(parent.entity.id());
-                if parent_dirty || self_dirty {
-                    let combined_transform = if let Some(parent_global) = globals.get(parent.entity)
-                    {
-                        (parent_global.0 * local.matrix())
-                    } else {
-                        local.matrix()
-                    };
-
-                    if let Some(global) = globals.get_mut(*entity) {
-                        self.global_modified.add(entity.id());
-                        global.0 = combined_transform;
-                    }
-                }
+            let parent = match parents.get(*entity).and_then(|p| globals.get(p.entity)) {
+                Some(parent) => parent.0,
+                None => continue,
+            };
+            if let Some(global) = globals.get_mut(*entity) {
+                Self::compose(*entity, parent, &locals, &boxes, global);
             }
         }
     }
@@ -137,8 +122,46 @@ impl<'a> System<'a> for TransformSystem {
         use specs::prelude::SystemData;
         Self::SystemData::setup(res);
         let mut hierarchy = res.fetch_mut::<ParentHierarchy>();
-        let mut locals = WriteStorage::<Transform>::fetch(res);
         self.parent_events_id = Some(hierarchy.track());
-        self.locals_events_id = Some(locals.register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, Transform as _};
+
+    #[test]
+    fn layout_box_in_free_floating_parent() {
+        let mut world = World::new();
+        let mut hierarchy = specs_hierarchy::HierarchySystem::<Parent>::new();
+        let mut system = TransformSystem::new();
+        System::setup(&mut hierarchy, &mut world.res);
+        System::setup(&mut system, &mut world.res);
+
+        let parent = world
+            .create_entity()
+            .with(Transform::new(10.0, 20.0).with_size(100.0, 50.0))
+            .build();
+        let child = world
+            .create_entity()
+            .with(Parent { entity: parent })
+            .with(Transform::new(1.0, 2.0))
+            .with(LayoutBox {
+                location: (5.0, 5.0),
+                size: (30.0, 10.0),
+                transform: Matrix4::identity(),
+            })
+            .build();
+        hierarchy.run_now(&world.res);
+        system.run_now(&world.res);
+
+        let globals = world.read_storage::<GlobalTransform>();
+        assert_eq!(globals.get(parent).unwrap().1, (100.0, 50.0));
+        let child = globals.get(child).unwrap();
+        assert_eq!(child.1, (30.0, 10.0));
+        // the parent position, then the layout box, then the offset
+        let p = child.0.transform_point(Point3::new(0.0, 0.0, 0.0));
+        assert_eq!((p.x, p.y), (16.0, 27.0));
     }
 }
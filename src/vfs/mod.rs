@@ -0,0 +1,123 @@
+//! The virtual file system `Path` resources are read from: directories, packs
+//! and files embedded in memory, mounted at virtual roots. The last mount
+//! holding a file wins, so a theme folder mounted over a pack overrides its
+//! defaults.
+#![allow(dead_code)]
+
+mod pack;
+
+pub use pack::{write as write_pack, Pack};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+enum Source {
+    Dir(PathBuf),
+    Pack(Pack),
+    Memory(HashMap<PathBuf, Cow<'static, [u8]>>),
+}
+
+struct Mount {
+    /// Relative, as the paths read; empty for the root.
+    at: PathBuf,
+    source: Source,
+}
+
+#[derive(Default)]
+pub struct Vfs {
+    /// Lowest priority first.
+    mounts: Vec<Mount>,
+}
+
+impl Vfs {
+    /// Mounts the directory `dir` at `at`, over the previous mounts.
+    pub fn mount_dir<A, D>(&mut self, at: A, dir: D)
+    where
+        A: Into<PathBuf>,
+        D: Into<PathBuf>,
+    {
+        self.mount(at.into(), Source::Dir(dir.into()));
+    }
+
+    /// Mounts the pack file `file` at `at`, over the previous mounts.
+    pub fn mount_pack<A, P>(&mut self, at: A, file: P) -> io::Result<()>
+    where
+        A: Into<PathBuf>,
+        P: AsRef<Path>,
+    {
+        let pack = Pack::open(file)?;
+        self.mount(at.into(), Source::Pack(pack));
+        Ok(())
+    }
+
+    /// Mounts `files`, given by path relative to `at`, over the previous
+    /// mounts.
+    pub fn mount_memory<A, I>(&mut self, at: A, files: I)
+    where
+        A: Into<PathBuf>,
+        I: IntoIterator<Item = (PathBuf, Cow<'static, [u8]>)>,
+    {
+        self.mount(at.into(), Source::Memory(files.into_iter().collect()));
+    }
+
+    fn mount(&mut self, at: PathBuf, source: Source) {
+        self.mounts.push(Mount { at, source });
+    }
+
+    /// The bytes of the virtual file `path`, from the last mount holding it.
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        for mount in self.mounts.iter().rev() {
+            let relative = match path.strip_prefix(&mount.at) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            match mount.source {
+                Source::Dir(ref dir) => match std::fs::read(dir.join(relative)) {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                },
+                Source::Pack(ref pack) => {
+                    if let Some(bytes) = pack.get(relative) {
+                        return Ok(bytes.to_vec());
+                    }
+                }
+                Source::Memory(ref files) => {
+                    if let Some(bytes) = files.get(relative) {
+                        return Ok(bytes.to_vec());
+                    }
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is in no mount", path.display()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_mount_wins() {
+        let mut vfs = Vfs::default();
+        vfs.mount_memory(
+            "",
+            vec![
+                ("style/style.css".into(), Cow::Borrowed(&b"default"[..])),
+                ("style/font.ttf".into(), Cow::Borrowed(&b"font"[..])),
+            ],
+        );
+        vfs.mount_memory(
+            "style",
+            vec![("style.css".into(), Cow::Borrowed(&b"theme"[..]))],
+        );
+        assert_eq!(vfs.read(Path::new("style/style.css")).unwrap(), b"theme");
+        assert_eq!(vfs.read(Path::new("style/font.ttf")).unwrap(), b"font");
+        assert!(vfs.read(Path::new("shader/cube.hlsl")).is_err());
+    }
+}
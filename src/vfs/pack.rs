@@ -0,0 +1,217 @@
+//! A read-only archive of files, written by the `packer` binary: a table of
+//! the files, then their bytes.
+//!
+//! ```text
+//! "PEGPACK\0" | count: u32 | count * (name length: u32 | name | offset: u64 | length: u64) | bytes
+//! ```
+//!
+//! Integers are little endian. Names are relative paths separated by `/`, and
+//! offsets start after the table.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+
+const MAGIC: &[u8; 8] = b"PEGPACK\0";
+/// The size of an entry of the table with an empty name.
+const ENTRY_SIZE: usize = 4 + 8 + 8;
+
+pub struct Pack {
+    bytes: Vec<u8>,
+    /// The range of each file in `bytes`.
+    files: HashMap<String, (usize, usize)>,
+}
+
+impl Pack {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Pack::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        let mut reader = Reader {
+            bytes: &bytes,
+            at: 0,
+        };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a pack"));
+        }
+        let count = reader.u32()? as usize;
+        // an entry takes 20 bytes at least, so a corrupt count allocates no
+        // more than the pack
+        let mut table = Vec::with_capacity(count.min(reader.remaining() / ENTRY_SIZE));
+        for _ in 0..count {
+            let length = reader.u32()? as usize;
+            let name = String::from_utf8(reader.take(length)?.to_vec())
+                .map_err(|_| invalid("file name is not utf-8"))?;
+            let offset = reader.usize()?;
+            let length = reader.usize()?;
+            table.push((name, offset, length));
+        }
+
+        let start = reader.at;
+        let mut files = HashMap::with_capacity(table.len());
+        for (name, offset, length) in table {
+            let range = start
+                .checked_add(offset)
+                .and_then(|begin| Some((begin, begin.checked_add(length)?)));
+            match range {
+                Some((begin, end)) if end <= bytes.len() => {
+                    files.insert(name, (begin, end));
+                }
+                _ => return Err(invalid("file out of the pack")),
+            }
+        }
+        Ok(Pack { bytes, files })
+    }
+
+    /// The bytes of the file at `path`, relative to the root of the pack.
+    pub fn get(&self, path: &Path) -> Option<&[u8]> {
+        let &(begin, end) = self.files.get(&name(path))?;
+        Some(&self.bytes[begin..end])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|name| name.as_str())
+    }
+}
+
+/// Writes a pack of `files`, given by path relative to the root of the pack.
+pub fn write<W: Write>(out: &mut W, files: &[(&Path, &[u8])]) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+    let mut offset = 0u64;
+    for (path, bytes) in files {
+        let name = name(path);
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&offset.to_le_bytes())?;
+        out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        offset += bytes.len() as u64;
+    }
+    for (_, bytes) in files {
+        out.write_all(bytes)?;
+    }
+    Ok(())
+}
+
+/// `style/style.css` for `style/style.css`, `./style/style.css` and, on
+/// Windows, `style\style.css`.
+fn name(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.at
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .at
+            .checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("truncated pack"))?;
+        let taken = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut le = [0; 4];
+        le.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(le))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut le = [0; 8];
+        le.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(le))
+    }
+
+    /// An offset or a length, which must fit in memory.
+    fn usize(&mut self) -> io::Result<usize> {
+        use std::convert::TryFrom;
+        usize::try_from(self.u64()?).map_err(|_| invalid("file out of the pack"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        let mut bytes = Vec::new();
+        write(
+            &mut bytes,
+            &[
+                (Path::new("style/style.css"), b"* {}"),
+                (Path::new("shader/cube.hlsl"), b""),
+            ],
+        )
+        .unwrap();
+        let pack = Pack::from_bytes(bytes).unwrap();
+        assert_eq!(pack.get(Path::new("./style/style.css")), Some(&b"* {}"[..]));
+        assert_eq!(pack.get(Path::new("shader/cube.hlsl")), Some(&b""[..]));
+        assert_eq!(pack.get(Path::new("style")), None);
+        assert!(Pack::from_bytes(b"PEGPACK\0\x01\0\0\0".to_vec()).is_err());
+    }
+
+    fn pack_of(entries: &[(&str, u64, u64)], data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&(entries.len() as u32).to_le_bytes());
+        for (name, offset, length) in entries {
+            bytes.extend(&(name.len() as u32).to_le_bytes());
+            bytes.extend(name.as_bytes());
+            bytes.extend(&offset.to_le_bytes());
+            bytes.extend(&length.to_le_bytes());
+        }
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn truncated_pack() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &[(Path::new("style/style.css"), b"* {}")]).unwrap();
+        for length in 0..bytes.len() {
+            assert!(Pack::from_bytes(bytes[..length].to_vec()).is_err());
+        }
+        // a count of files the pack cannot hold
+        let mut huge = MAGIC.to_vec();
+        huge.extend(&u32::max_value().to_le_bytes());
+        assert!(Pack::from_bytes(huge).is_err());
+        assert!(Pack::from_bytes(pack_of(&[("a", 0, 5)], b"abcd")).is_err());
+    }
+
+    #[test]
+    fn overflowing_pack() {
+        let max = u64::max_value();
+        assert!(Pack::from_bytes(pack_of(&[("a", max, 1)], b"a")).is_err());
+        assert!(Pack::from_bytes(pack_of(&[("a", 1, max)], b"a")).is_err());
+        assert!(Pack::from_bytes(pack_of(&[("a", max, max)], b"a")).is_err());
+        assert!(Pack::from_bytes(pack_of(&[("a", 1, 0)], b"a")).is_ok());
+        // a name longer than the pack
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&1u32.to_le_bytes());
+        bytes.extend(&u32::max_value().to_le_bytes());
+        assert!(Pack::from_bytes(bytes).is_err());
+    }
+}